@@ -0,0 +1,70 @@
+//! 定义体的连续存储，用于在子类型判定中以下标解析 `Def`
+
+use std::collections::HashMap;
+
+use crate::constraint::ConstraintNode;
+
+/// 按名字排序存放的定义体，以及名字到下标的索引
+///
+/// 定义体中的 Def 在构建时预先解析：按节点的地址记下它引用的定义的下标，判定时
+/// 解析这些 Def 不再需要按名字查找。定义体构建后不再移动，地址因此保持有效
+#[derive(Debug, Default)]
+pub(crate) struct DefArena {
+    nodes: Vec<ConstraintNode>,
+    index: HashMap<String, usize>,
+    targets: HashMap<usize, usize>, // 定义体中 Def 节点的地址到其引用的定义的下标
+}
+
+impl DefArena {
+    pub(crate) fn build(graph: &HashMap<String, ConstraintNode>) -> Self {
+        let mut names: Vec<&String> = graph.keys().collect();
+        names.sort();
+        let index = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ((*name).clone(), i))
+            .collect();
+        let nodes: Vec<ConstraintNode> =
+            names.into_iter().map(|name| graph[name].clone()).collect();
+        fn collect(
+            node: &ConstraintNode,
+            index: &HashMap<String, usize>,
+            targets: &mut HashMap<usize, usize>,
+        ) {
+            if let ConstraintNode::Def(name) = node
+                && let Some(&target) = index.get(name)
+            {
+                targets.insert(address(node), target);
+            }
+            for child in node.children() {
+                collect(child, index, targets);
+            }
+        }
+        let mut targets = HashMap::new();
+        for node in &nodes {
+            collect(node, &index, &mut targets);
+        }
+        DefArena {
+            nodes,
+            index,
+            targets,
+        }
+    }
+
+    /// node 是定义体中的 Def 时，它引用的定义的下标；不在定义体中的节点返回 None
+    pub(crate) fn target(&self, node: &ConstraintNode) -> Option<usize> {
+        self.targets.get(&address(node)).copied()
+    }
+
+    pub(crate) fn index_of(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    pub(crate) fn node(&self, index: usize) -> &ConstraintNode {
+        &self.nodes[index]
+    }
+}
+
+fn address(node: &ConstraintNode) -> usize {
+    node as *const ConstraintNode as usize
+}
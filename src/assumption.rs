@@ -0,0 +1,108 @@
+//! 余归纳判定中使用的假设集，定义以其在定义存储中的编号表示，避免复制定义名；
+//! 另一侧的节点驻留为编号，同一个节点只复制一次
+//!
+//! 驻留只用于假设集：`ConstraintNode::Def` 与约束图的键仍然是定义名
+
+use std::collections::{HashMap, HashSet};
+
+use crate::constraint::ConstraintNode;
+
+/// 定义在所属约束的定义存储中的编号，相当于驻留后的定义名
+pub(crate) type DefId = usize;
+
+/// 驻留在假设集中的非定义节点的编号
+pub(crate) type NodeId = usize;
+
+/// 一个被假设成立的判定 a >= b，至少有一侧是定义
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Goal<'n> {
+    Defs(DefId, DefId),
+    DefLeft(DefId, &'n ConstraintNode),
+    DefRight(&'n ConstraintNode, DefId),
+}
+
+/// 按作用域使用的假设集：展开定义前加入假设，该分支判定结束后立即撤销
+///
+/// 假设 a >= b 只在它自身的证明内部有效，这正是余归纳的规则：沿一条路径再次遇到
+/// 同一个判定时用假设闭合。撤销是可靠的，因为兄弟分支再次遇到同一个判定时只是
+/// 重新展开一次；每条路径上的判定各不相同且只有有限种，因此仍然会终止，
+/// 代价只是重复的展开。同一路径上不会重复加入同一个假设，`remove` 因此总是
+/// 撤销最近一次加入的那一个
+///
+/// 非定义一侧的节点在第一次加入时驻留，之后同样的节点只查到编号而不再复制；
+/// 驻留的节点在整个判定期间保留，数量不超过判定中出现过的不同节点
+#[derive(Debug, Default)]
+pub(crate) struct Assumptions {
+    pub(crate) defs: HashSet<(DefId, DefId)>,
+    pub(crate) left: HashMap<(DefId, NodeId), usize>, // Def(a) >= b 及其加入的次数
+    pub(crate) right: HashMap<(NodeId, DefId), usize>, // a >= Def(b) 及其加入的次数
+    pub(crate) interned: HashMap<ConstraintNode, NodeId>,
+}
+
+impl Assumptions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.defs.is_empty() && self.left.is_empty() && self.right.is_empty()
+    }
+
+    fn intern(&mut self, node: &ConstraintNode) -> NodeId {
+        if let Some(&id) = self.interned.get(node) {
+            return id;
+        }
+        let id = self.interned.len();
+        self.interned.insert(node.clone(), id);
+        id
+    }
+
+    pub(crate) fn contains(&self, goal: Goal<'_>) -> bool {
+        let id = |node| self.interned.get(node).copied();
+        match goal {
+            Goal::Defs(a, b) => self.defs.contains(&(a, b)),
+            Goal::DefLeft(a, b) => id(b).is_some_and(|b| self.left.contains_key(&(a, b))),
+            Goal::DefRight(a, b) => id(a).is_some_and(|a| self.right.contains_key(&(a, b))),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, goal: Goal<'_>) {
+        match goal {
+            Goal::Defs(a, b) => {
+                self.defs.insert((a, b));
+            }
+            Goal::DefLeft(a, b) => {
+                let b = self.intern(b);
+                *self.left.entry((a, b)).or_default() += 1;
+            }
+            Goal::DefRight(a, b) => {
+                let a = self.intern(a);
+                *self.right.entry((a, b)).or_default() += 1;
+            }
+        }
+    }
+
+    /// 撤销最近一次加入的同一个假设
+    pub(crate) fn remove(&mut self, goal: Goal<'_>) {
+        fn pop<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, usize>, key: K) {
+            if let Some(count) = counts.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&key);
+                }
+            }
+        }
+        let id = |node| self.interned.get(node).copied();
+        match goal {
+            Goal::Defs(a, b) => {
+                self.defs.remove(&(a, b));
+            }
+            Goal::DefLeft(a, b) => {
+                if let Some(b) = id(b) {
+                    pop(&mut self.left, (a, b));
+                }
+            }
+            Goal::DefRight(a, b) => {
+                if let Some(a) = id(a) {
+                    pop(&mut self.right, (a, b));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! 两个约束图之间的互模拟判定
+
+use std::collections::HashMap;
+
+use crate::{
+    assumption::{Assumptions, DefId, Goal},
+    constraint::{Constraint, ConstraintNode},
+    range,
+    union::fresh_name,
+};
+
+/// 判定两个节点互模拟的上下文，两个方向共用同一个假设集
+///
+/// 已经证明的定义对会留在假设集中供之后的判定复用；某个定义对判定失败时，
+/// 在它之后加入的所有定义对都可能依赖了这个错误的假设，因此一并撤销
+pub(crate) struct BisimChecker<'a> {
+    constraint_a: &'a Constraint,
+    constraint_b: &'a Constraint,
+    assumption: Assumptions,
+    log: Vec<Goal<'a>>,            // 按加入顺序记录假设集中的定义对
+    pub(crate) resolutions: usize, // 解析 Def 的次数
+}
+
+impl<'a> BisimChecker<'a> {
+    pub(crate) fn new(constraint_a: &'a Constraint, constraint_b: &'a Constraint) -> Self {
+        BisimChecker {
+            constraint_a,
+            constraint_b,
+            assumption: Assumptions::default(),
+            log: Vec::new(),
+            resolutions: 0,
+        }
+    }
+
+    /// 节点顶层的枚举成员，非枚举节点视为只有自身一个成员；定义体为枚举的成员
+    /// 会被展开为定义体的成员，使成员集合与定义的拆分方式无关
+    fn members(
+        &self,
+        constraint: &'a Constraint,
+        node: &'a ConstraintNode,
+    ) -> Vec<&'a ConstraintNode> {
+        fn collect<'a>(
+            constraint: &'a Constraint,
+            node: &'a ConstraintNode,
+            expanding: &mut Vec<&'a str>,
+            result: &mut Vec<&'a ConstraintNode>,
+        ) {
+            match node.strip_annotations() {
+                ConstraintNode::Enum(nodes) => {
+                    for node in nodes {
+                        collect(constraint, node, expanding, result);
+                    }
+                }
+                ConstraintNode::F => {}
+                ConstraintNode::Def(name) if !expanding.contains(&name.as_str()) => {
+                    match constraint
+                        .get_node(name)
+                        .map(ConstraintNode::strip_annotations)
+                    {
+                        Some(body @ (ConstraintNode::Enum(_) | ConstraintNode::F)) => {
+                            expanding.push(name);
+                            collect(constraint, body, expanding, result);
+                            expanding.pop();
+                        }
+                        _ => result.push(node.strip_annotations()),
+                    }
+                }
+                other => result.push(other),
+            }
+        }
+        let mut result = Vec::new();
+        collect(constraint, node, &mut Vec::new(), &mut result);
+        result
+    }
+
+    fn resolve(
+        &mut self,
+        constraint: &'a Constraint,
+        node: &'a ConstraintNode,
+    ) -> &'a ConstraintNode {
+        match node {
+            ConstraintNode::Def(name) => {
+                self.resolutions += 1;
+                match constraint.get_node(name) {
+                    Some(body) => body.strip_annotations(),
+                    // 未定义的定义不包含任何值
+                    None => &ConstraintNode::F,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// 假设集中留下的定义对：判定结束后它们都已证明互模拟，因此在两个方向上互相包含
+    pub(crate) fn proven(&self) -> Vec<(DefId, DefId)> {
+        self.log
+            .iter()
+            .filter_map(|goal| match goal {
+                Goal::Defs(a, b) => Some((*a, *b)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn check(&mut self, node_a: &'a ConstraintNode, node_b: &'a ConstraintNode) -> bool {
+        let node_a = node_a.strip_annotations();
+        let node_b = node_b.strip_annotations();
+        let def_id = |constraint: &Constraint, node: &ConstraintNode| match node {
+            ConstraintNode::Def(name) => constraint.arena().index_of(name),
+            _ => None,
+        };
+        let goal = match (
+            def_id(self.constraint_a, node_a),
+            def_id(self.constraint_b, node_b),
+        ) {
+            (Some(a), Some(b)) => Some(Goal::Defs(a, b)),
+            (Some(a), None) => Some(Goal::DefLeft(a, node_b)),
+            (None, Some(b)) => Some(Goal::DefRight(node_a, b)),
+            (None, None) => None,
+        };
+        if let Some(goal) = goal {
+            if self.assumption.contains(goal) {
+                return true;
+            }
+            let mark = self.log.len();
+            self.assumption.insert(goal);
+            self.log.push(goal);
+            let body_a = self.resolve(self.constraint_a, node_a);
+            let body_b = self.resolve(self.constraint_b, node_b);
+            let result = self.check(body_a, body_b);
+            if !result {
+                for goal in self.log.drain(mark..).rev() {
+                    self.assumption.remove(goal);
+                }
+            }
+            return result;
+        }
+
+        match (node_a, node_b) {
+            (ConstraintNode::Enum(_) | ConstraintNode::F, _)
+            | (_, ConstraintNode::Enum(_) | ConstraintNode::F) => {
+                // 两侧的成员互相找得到互模拟的对应成员
+                let a_members = self.members(self.constraint_a, node_a);
+                let b_members = self.members(self.constraint_b, node_b);
+                a_members
+                    .iter()
+                    .all(|a| b_members.iter().any(|b| self.check(a, b)))
+                    && b_members
+                        .iter()
+                        .all(|b| a_members.iter().any(|a| self.check(a, b)))
+            }
+            (ConstraintNode::T, ConstraintNode::T) => true,
+            (ConstraintNode::Leaf(a), ConstraintNode::Leaf(b)) => a == b,
+            (ConstraintNode::Kind(a), ConstraintNode::Kind(b)) => a == b,
+            (ConstraintNode::IntSet(a), ConstraintNode::IntSet(b)) => a == b,
+            (
+                ConstraintNode::Range { lo: a_lo, hi: a_hi },
+                ConstraintNode::Range { lo: b_lo, hi: b_hi },
+            ) => range::normalize(a_lo, a_hi) == range::normalize(b_lo, b_hi),
+            (ConstraintNode::Pair(a_left, a_right), ConstraintNode::Pair(b_left, b_right)) => {
+                self.check(a_left, b_left) && self.check(a_right, b_right)
+            }
+            (ConstraintNode::Custom(a), ConstraintNode::Custom(b)) => a.eq_custom(b.as_ref()),
+            (ConstraintNode::Predicate(a), ConstraintNode::Predicate(b)) => {
+                a == b && self.constraint_a.same_predicate(self.constraint_b, *a)
+            }
+            // 成员集合互相对应的交互模拟
+            (ConstraintNode::And(a_members), ConstraintNode::And(b_members)) => {
+                a_members
+                    .iter()
+                    .all(|a| b_members.iter().any(|b| self.check(a, b)))
+                    && b_members
+                        .iter()
+                        .all(|b| a_members.iter().any(|a| self.check(a, b)))
+            }
+            (ConstraintNode::TaggedEnum(a_members), ConstraintNode::TaggedEnum(b_members)) => {
+                // 按标签分组后与 Enum 相同，两侧互相找得到对应成员
+                a_members.iter().all(|(a_tag, a)| {
+                    b_members
+                        .iter()
+                        .any(|(b_tag, b)| a_tag == b_tag && self.check(a, b))
+                }) && b_members.iter().all(|(b_tag, b)| {
+                    a_members
+                        .iter()
+                        .any(|(a_tag, a)| a_tag == b_tag && self.check(a, b))
+                })
+            }
+            (ConstraintNode::Record(a_fields), ConstraintNode::Record(b_fields)) => {
+                a_fields.len() == b_fields.len()
+                    && a_fields.iter().all(|(name, a)| {
+                        b_fields.iter().any(|(b_name, b)| {
+                            name == b_name
+                                && self.check(&a.ty, &b.ty)
+                                && match (&a.default, &b.default) {
+                                    (Some(a), Some(b)) => self.check(a, b),
+                                    (None, None) => true,
+                                    _ => false,
+                                }
+                        })
+                    })
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Constraint {
+    /// 两个约束图在展开定义后是否互模拟：枚举按成员集合比较，其余节点逐层比较
+    ///
+    /// 互模拟的约束一定互为超约束；反过来不一定成立，例如 `1 | 2` 与 `1..=2`
+    /// 互为超约束却不互模拟。对 `reduce`、`coalesce_ints` 之后的图，两者通常一致。
+    /// 与 `==` 相比，它只遍历一次两张图，并且两个方向共用同一个假设集
+    pub fn bisimilar(&self, other: &Self) -> bool {
+        match (self.get_node(self.entry()), other.get_node(other.entry())) {
+            (Some(a), Some(b)) => BisimChecker::new(self, other).check(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// 把两两互模拟的定义合并为同一个定义，得到互模拟意义下最小的图
+    ///
+    /// 嵌套在其他节点中的枚举先提升为定义，使展开了几层的递归定义也能与原定义合并。
+    /// 每一类保留一个代表：入口所在的类保留入口，其余的类优先保留原有的、名字最小的
+    /// 定义，对同类其他定义的引用都改为引用代表
+    pub(crate) fn merge_bisimilar(&self) -> Constraint {
+        fn hoist(
+            node: &ConstraintNode,
+            top: bool,
+            owner: &str,
+            graph: &mut HashMap<String, ConstraintNode>,
+            hoisted: &mut Vec<String>,
+        ) -> ConstraintNode {
+            match node {
+                ConstraintNode::Enum(_) if !top => {
+                    let body = node.map_children(|child| hoist(child, true, owner, graph, hoisted));
+                    let name = fresh_name(graph, owner);
+                    graph.insert(name.clone(), body);
+                    hoisted.push(name.clone());
+                    ConstraintNode::Def(name)
+                }
+                ConstraintNode::Enum(_) | ConstraintNode::Annotated(..) => {
+                    node.map_children(|child| hoist(child, top, owner, graph, hoisted))
+                }
+                other => other.map_children(|child| hoist(child, false, owner, graph, hoisted)),
+            }
+        }
+        let mut graph = (*self.graph).clone();
+        let mut hoisted = Vec::new();
+        for (name, body) in self.iter_defs() {
+            let body = hoist(body, true, name, &mut graph, &mut hoisted);
+            graph.insert(name.to_string(), body);
+        }
+
+        let mut names: Vec<&String> = graph.keys().collect();
+        names.sort_by_key(|name| (**name != self.entry, hoisted.contains(name), *name));
+        let defs: Vec<ConstraintNode> = names
+            .iter()
+            .map(|name| ConstraintNode::Def(name.to_string()))
+            .collect();
+        let expanded = Constraint::from_graph(graph.clone(), self.entry.clone());
+        let mut checker = BisimChecker::new(&expanded, &expanded);
+        let mut classes: Vec<Vec<usize>> = Vec::new();
+        let mut renames = HashMap::new();
+        for (index, def) in defs.iter().enumerate() {
+            match classes
+                .iter_mut()
+                .find(|class| checker.check(&defs[class[0]], def))
+            {
+                Some(class) => {
+                    renames.insert(names[index].clone(), names[class[0]].clone());
+                    class.push(index);
+                }
+                None => classes.push(vec![index]),
+            }
+        }
+        let merged = classes
+            .into_iter()
+            .map(|class| {
+                // 代表的定义体只是对同类定义的引用时，改为引用之后会指向自身，
+                // 因此选用类中第一个不是引用的定义体
+                let body = class
+                    .iter()
+                    .map(|&index| &graph[names[index]])
+                    .find(|body| !matches!(body.strip_annotations(), ConstraintNode::Def(_)))
+                    .unwrap_or(&graph[names[class[0]]]);
+                (names[class[0]].clone(), body.rename_defs(&renames))
+            })
+            .collect();
+        let mut merged = Constraint::from_graph(merged, self.entry.clone());
+        merged.predicates = self.predicates.clone();
+        merged
+    }
+}
@@ -0,0 +1,27 @@
+use std::collections::HashSet;
+
+use crate::constraint::Kind;
+
+/// 类别之间允许的隐式转换表，例如整数可以被当作浮点数使用
+///
+/// 转换只按表中登记的方向生效，不做传递闭包
+#[derive(Debug, Clone, Default)]
+pub struct CoercionTable {
+    allowed: HashSet<(Kind, Kind)>,
+}
+
+impl CoercionTable {
+    pub fn new() -> Self {
+        CoercionTable::default()
+    }
+
+    /// 允许类别 from 的值被当作类别 to 使用
+    pub fn allow(&mut self, from: Kind, to: Kind) -> &mut Self {
+        self.allowed.insert((from, to));
+        self
+    }
+
+    pub fn allows(&self, from: Kind, to: Kind) -> bool {
+        self.allowed.contains(&(from, to))
+    }
+}
@@ -0,0 +1,191 @@
+//! 约束在给定定义域内的补集，以及把约束拆分为互不相交的分支
+
+use crate::{
+    constraint::{Checker, Constraint, ConstraintNode, Kind, int_set_parts},
+    simplify::{int_interval, interval_node},
+    union::fresh_name,
+};
+
+/// 把整数区间限制到 i32 的取值范围内
+fn clamp((lo, hi): (i64, i64)) -> (i64, i64) {
+    (lo.max(i32::MIN as i64), hi.min(i32::MAX as i64))
+}
+
+/// 闭区间 (lo, hi) 去掉 removed 中所有区间后剩下的部分
+fn subtract_intervals((lo, hi): (i64, i64), removed: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut pieces = Vec::new();
+    let mut start = lo;
+    for &(r_lo, r_hi) in removed {
+        if r_hi < start || r_lo > hi {
+            continue;
+        }
+        if r_lo > start {
+            pieces.push((start, r_lo - 1));
+        }
+        start = start.max(r_hi + 1);
+    }
+    if start <= hi {
+        pieces.push((start, hi));
+    }
+    pieces
+}
+
+/// 整数成员覆盖的闭区间，整个整数类别对应两侧无界的区间
+fn interval(node: &ConstraintNode) -> Option<(i64, i64)> {
+    match node {
+        ConstraintNode::Kind(Kind::Int) => Some((i64::MIN, i64::MAX)),
+        other => int_interval(other),
+    }
+}
+
+/// 枚举的成员，整数集合按连续的段拆成字面量与区间，使它们可以按区间相减
+fn members(node: &ConstraintNode) -> Vec<ConstraintNode> {
+    match node.strip_annotations() {
+        ConstraintNode::Enum(nodes) => nodes
+            .iter()
+            .flat_map(|node| match node.strip_annotations() {
+                ConstraintNode::IntSet(set) => int_set_parts(set),
+                _ => vec![node.clone()],
+            })
+            .collect(),
+        ConstraintNode::IntSet(set) => int_set_parts(set),
+        ConstraintNode::F => Vec::new(),
+        other => vec![other.clone()],
+    }
+}
+
+impl Constraint {
+    /// 在 domain 之内取 self 的补集，即 domain 中不被 self 接受的部分
+    ///
+    /// 整数字面量与区间按区间精确相减，其余成员整体保留或整体去除：只有被 self
+    /// 完全接受的成员会被去除，因此结果对复合成员是补集的上近似
+    pub fn complement_within(&self, domain: &Constraint) -> Constraint {
+        let this = self.simplify();
+        let domain_simplified = domain.simplify();
+        let Some(domain_entry) = domain_simplified.get_node(domain_simplified.entry()) else {
+            return Constraint::bottom();
+        };
+        // 补集放在新的定义中，定义域内对入口的递归引用仍然指向原来的定义域
+        let complement = |remaining: Vec<ConstraintNode>| {
+            let mut graph = (*domain_simplified.graph).clone();
+            let entry = fresh_name(&graph, domain_simplified.entry());
+            graph.insert(entry.clone(), ConstraintNode::Enum(remaining));
+            Constraint::from_graph(graph, entry).reduce().prune()
+        };
+        let removed = match this.get_node(this.entry()) {
+            Some(removed) if !members(removed).is_empty() => removed,
+            _ => return complement(members(domain_entry)),
+        };
+        if self.super_of(domain) {
+            return Constraint::bottom();
+        }
+
+        let mut removed_intervals: Vec<(i64, i64)> = members(removed)
+            .into_iter()
+            .filter_map(|member| interval(&member))
+            .map(clamp)
+            .collect();
+        removed_intervals.sort();
+
+        let mut remaining = Vec::new();
+        for member in members(domain_entry) {
+            if let Some(interval) = interval(&member) {
+                let pieces = subtract_intervals(clamp(interval), &removed_intervals);
+                remaining.extend(pieces.into_iter().map(interval_node));
+            } else if !Checker::new(&this, &domain_simplified).check_subsumption(removed, &member) {
+                remaining.push(member);
+            }
+        }
+
+        complement(remaining)
+    }
+
+    /// 封闭世界中的补集：domain 中的 T 解释为 universe 的并，因此补集是精确的
+    pub fn complement_within_closed(
+        &self,
+        domain: &Constraint,
+        universe: &[ConstraintNode],
+    ) -> Constraint {
+        self.close_over(universe)
+            .complement_within(&domain.close_over(universe))
+    }
+
+    /// 把入口枚举拆分为互不相交、并集等于 self 的若干分支，便于逐个生成分支代码
+    ///
+    /// 整数成员按区间端点切分，被同一组成员覆盖的片段归为同一个分支，例如
+    /// `1..=5 | 3..=8` 拆分为 `1..=2`、`3..=5` 与 `6..=8`。其余成员依次减去之前的分支，
+    /// 与 `complement_within` 一样只去除被完全接受的部分，因此彼此部分重叠的复合成员
+    /// 会保留重叠
+    pub fn disjoint_partition(&self) -> Vec<Constraint> {
+        let Some(entry) = self.get_node(self.entry()) else {
+            return Vec::new();
+        };
+        let with_entry = |node: ConstraintNode| {
+            let mut graph = (*self.graph).clone();
+            graph.insert(self.entry().clone(), node);
+            Constraint::from_graph(graph, self.entry().clone()).prune()
+        };
+        let is_bottom = |constraint: &Constraint| {
+            constraint
+                .get_node(constraint.entry())
+                .is_none_or(|node| members(node).is_empty())
+        };
+
+        let mut intervals = Vec::new();
+        let mut others = Vec::new();
+        for member in members(entry) {
+            match interval(&member).map(clamp) {
+                Some((lo, hi)) if lo <= hi => intervals.push((lo, hi)),
+                Some(_) => {}
+                None => others.push(member),
+            }
+        }
+
+        // 相邻端点之间的片段被同一组整数成员覆盖
+        let mut points: Vec<i64> = intervals
+            .iter()
+            .flat_map(|&(lo, hi)| [lo, hi + 1])
+            .collect();
+        points.sort();
+        points.dedup();
+        let mut groups: Vec<(Vec<usize>, Vec<ConstraintNode>)> = Vec::new();
+        for window in points.windows(2) {
+            let piece = (window[0], window[1] - 1);
+            let covering: Vec<usize> = intervals
+                .iter()
+                .enumerate()
+                .filter(|(_, (lo, hi))| *lo <= piece.0 && piece.1 <= *hi)
+                .map(|(index, _)| index)
+                .collect();
+            if covering.is_empty() {
+                continue;
+            }
+            match groups.iter_mut().find(|(key, _)| *key == covering) {
+                Some((_, pieces)) => pieces.push(interval_node(piece)),
+                None => groups.push((covering, vec![interval_node(piece)])),
+            }
+        }
+        let mut cases: Vec<Constraint> = groups
+            .into_iter()
+            .map(
+                |(_, pieces)| match <[ConstraintNode; 1]>::try_from(pieces) {
+                    Ok([piece]) => with_entry(piece),
+                    Err(pieces) => with_entry(ConstraintNode::Enum(pieces)),
+                },
+            )
+            .collect();
+
+        for member in others {
+            let mut rest = with_entry(member);
+            for case in &cases {
+                if !case.disjoint(&rest) {
+                    rest = case.complement_within(&rest);
+                }
+            }
+            if !is_bottom(&rest) {
+                cases.push(rest);
+            }
+        }
+        cases
+    }
+}
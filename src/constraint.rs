@@ -1,54 +1,373 @@
 use std::{
-    collections::{HashMap, HashSet},
+    cell::Cell,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt,
     hash::Hash,
-    sync::Arc,
+    sync::{Arc, OnceLock},
 };
 
+use crate::{
+    arena::DefArena,
+    assumption::{Assumptions, DefId, Goal},
+    bisim::BisimChecker,
+    coercion::CoercionTable,
+    custom::CustomConstraint,
+    disjoint::DisjointChecker,
+    error::ConstraintError,
+    explain::DerivationTrace,
+    format::FormatterRegistry,
+    predicate::{PredicateId, PredicateRegistry},
+    range, simplify,
+};
+
+/// 只在启用 trace feature 时打印判定过程
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace")]
+        println!($($arg)*);
+    };
+}
+
 #[derive(Debug, Clone)]
 pub enum AtomicConstraint {
     Nil,
     LiteralInt(i32),
+    LiteralString(String),
+    LiteralChar(char),
+    LiteralFloat(f64),
 }
 
 impl PartialEq for AtomicConstraint {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (AtomicConstraint::LiteralInt(a), AtomicConstraint::LiteralInt(b)) => a == b,
+            (AtomicConstraint::LiteralString(a), AtomicConstraint::LiteralString(b)) => a == b,
+            (AtomicConstraint::LiteralChar(a), AtomicConstraint::LiteralChar(b)) => a == b,
+            // 与 Hash 相同按规范化的位比较：0.0 与 -0.0 相等，NaN 与自身相等，使 Eq 自反
+            (AtomicConstraint::LiteralFloat(a), AtomicConstraint::LiteralFloat(b)) => {
+                (a + 0.0).to_bits() == (b + 0.0).to_bits()
+            }
             (AtomicConstraint::Nil, AtomicConstraint::Nil) => true,
             _ => false,
         }
     }
 }
 
+impl AtomicConstraint {
+    /// 原子所属的类别，Nil 这样的单例原子没有类别
+    pub fn kind(&self) -> Option<Kind> {
+        match self {
+            AtomicConstraint::LiteralInt(_) => Some(Kind::Int),
+            AtomicConstraint::LiteralString(_) => Some(Kind::String),
+            AtomicConstraint::LiteralChar(_) => Some(Kind::Char),
+            AtomicConstraint::LiteralFloat(_) => Some(Kind::Float),
+            AtomicConstraint::Nil => None,
+        }
+    }
+
+    /// 是否为离散的有序原子，离散原子的开区间端点可以换成相邻元素的闭区间端点
+    pub(crate) fn is_discrete(&self) -> bool {
+        matches!(
+            self,
+            AtomicConstraint::LiteralInt(_) | AtomicConstraint::LiteralChar(_)
+        )
+    }
+
+    /// 离散原子的后继，没有后继（溢出或非离散）时返回 None
+    pub(crate) fn successor(&self) -> Option<AtomicConstraint> {
+        match self {
+            AtomicConstraint::LiteralInt(i) => i.checked_add(1).map(AtomicConstraint::LiteralInt),
+            // 跳过代理码点
+            AtomicConstraint::LiteralChar('\u{d7ff}') => {
+                Some(AtomicConstraint::LiteralChar('\u{e000}'))
+            }
+            AtomicConstraint::LiteralChar(c) => {
+                char::from_u32(*c as u32 + 1).map(AtomicConstraint::LiteralChar)
+            }
+            AtomicConstraint::LiteralString(_)
+            | AtomicConstraint::LiteralFloat(_)
+            | AtomicConstraint::Nil => None,
+        }
+    }
+
+    /// 离散原子的前驱，没有前驱（溢出或非离散）时返回 None
+    pub(crate) fn predecessor(&self) -> Option<AtomicConstraint> {
+        match self {
+            AtomicConstraint::LiteralInt(i) => i.checked_sub(1).map(AtomicConstraint::LiteralInt),
+            AtomicConstraint::LiteralChar('\u{e000}') => {
+                Some(AtomicConstraint::LiteralChar('\u{d7ff}'))
+            }
+            AtomicConstraint::LiteralChar(c) => (*c as u32)
+                .checked_sub(1)
+                .and_then(char::from_u32)
+                .map(AtomicConstraint::LiteralChar),
+            AtomicConstraint::LiteralString(_)
+            | AtomicConstraint::LiteralFloat(_)
+            | AtomicConstraint::Nil => None,
+        }
+    }
+}
+
+/// 原子之间的序，只有同一类别的原子之间可以比较
+///
+/// 区间约束完全依赖这里的序：新增一种有序原子时，只需在此给出同类原子之间的比较，
+/// 并在 `kind` 中为其指定类别；若它是离散的，再补充 `is_discrete`、`successor`
+/// 与 `predecessor`，开区间端点即可被正确规范化
+impl PartialOrd for AtomicConstraint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (AtomicConstraint::LiteralInt(a), AtomicConstraint::LiteralInt(b)) => a.partial_cmp(b),
+            (AtomicConstraint::LiteralString(a), AtomicConstraint::LiteralString(b)) => {
+                a.partial_cmp(b)
+            }
+            (AtomicConstraint::LiteralChar(a), AtomicConstraint::LiteralChar(b)) => {
+                a.partial_cmp(b)
+            }
+            (AtomicConstraint::LiteralFloat(a), AtomicConstraint::LiteralFloat(b)) => {
+                a.partial_cmp(b)
+            }
+            (AtomicConstraint::Nil, AtomicConstraint::Nil) => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
 impl Hash for AtomicConstraint {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self {
             AtomicConstraint::LiteralInt(value) => value.hash(state),
+            AtomicConstraint::LiteralString(value) => {
+                "String".hash(state);
+                value.hash(state);
+            }
+            AtomicConstraint::LiteralChar(value) => {
+                "Char".hash(state);
+                value.hash(state);
+            }
+            AtomicConstraint::LiteralFloat(value) => {
+                "Float".hash(state);
+                // 0.0 与 -0.0 相等，哈希也必须相同
+                (value + 0.0).to_bits().hash(state);
+            }
             AtomicConstraint::Nil => "Nil".hash(state),
         }
     }
 }
 
+/// 原子的类别，类别约束表示该类别下所有原子的并集
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Int,
+    Float,
+    String,
+    Char,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Int => write!(f, "Int"),
+            Kind::Float => write!(f, "Float"),
+            Kind::String => write!(f, "String"),
+            Kind::Char => write!(f, "Char"),
+        }
+    }
+}
+
+/// 区间端点
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum Bound {
+    Inclusive(AtomicConstraint),
+    Exclusive(AtomicConstraint),
+    Unbounded,
+}
+
 #[derive(Debug, Clone)]
 pub enum ConstraintNode {
     T,                                              // 顶点约束，表示任意值
     F,                                              // 假约束，表示不可能的值
     Leaf(AtomicConstraint),                         // 原子约束，例如整数字面量
+    Kind(Kind),                                     // 类别约束，例如全体整数
+    Range { lo: Bound, hi: Bound },                 // 区间约束，包含两端点之间的所有有序原子
+    IntSet(BTreeSet<i32>),                          // 整数集合，大量整数字面量组成的枚举的紧凑表示
     Enum(Vec<ConstraintNode>),                      // 枚举约束，对应集合并集
+    And(Vec<ConstraintNode>),                       // 交约束，对应集合交集，没有成员时等同于 T
+    TaggedEnum(Vec<(String, ConstraintNode)>), // 带标签的枚举，按标签匹配成员，对应 Rust 风格的枚举
     Pair(Box<ConstraintNode>, Box<ConstraintNode>), // 组合约束，对应笛卡尔积
-    Def(String),                                    // 定义约束，用于表示递归定义
+    Record(Vec<(String, RecordField)>),        // 记录约束，按字段名匹配，不允许未声明的字段
+    Def(String),                               // 定义约束，用于表示递归定义
+    Annotated(Box<ConstraintNode>, Metadata),  // 注释约束，附带元数据，语义上等同于内部约束
+    Custom(Arc<dyn CustomConstraint>),         // 自定义约束，判定交给用户实现的 trait
+    Predicate(PredicateId),                    // 谓词约束，由约束的谓词表中的闭包判定
+}
+
+/// 记录的字段：值缺少某个字段时，只有带默认值的字段成立，并视为取默认值
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecordField {
+    pub ty: ConstraintNode,
+    pub default: Option<ConstraintNode>, // 默认值的约束，应当被 ty 包含
+}
+
+impl RecordField {
+    /// 必须出现的字段
+    pub fn required(ty: ConstraintNode) -> RecordField {
+        RecordField { ty, default: None }
+    }
+
+    /// 缺少时取 default 的字段
+    pub fn with_default(ty: ConstraintNode, default: ConstraintNode) -> RecordField {
+        RecordField {
+            ty,
+            default: Some(default),
+        }
+    }
+}
+
+/// 附加在约束节点上的元数据（例如源码位置），不参与任何语义比较
+pub type Metadata = String;
+
+impl ConstraintNode {
+    /// 去除外层的所有注释包装，返回语义上等价的裸节点
+    pub fn strip_annotations(&self) -> &ConstraintNode {
+        let mut node = self;
+        while let ConstraintNode::Annotated(inner, _) = node {
+            node = inner;
+        }
+        node
+    }
+
+    /// 闭区间 [lo, hi] 上的整数
+    pub fn int_range(lo: i32, hi: i32) -> ConstraintNode {
+        ConstraintNode::Range {
+            lo: Bound::Inclusive(AtomicConstraint::LiteralInt(lo)),
+            hi: Bound::Inclusive(AtomicConstraint::LiteralInt(hi)),
+        }
+    }
+
+    /// 闭区间 [lo, hi] 上的字符，可用于表示 `[a-z]` 这样的字符类
+    pub fn char_range(lo: char, hi: char) -> ConstraintNode {
+        ConstraintNode::Range {
+            lo: Bound::Inclusive(AtomicConstraint::LiteralChar(lo)),
+            hi: Bound::Inclusive(AtomicConstraint::LiteralChar(hi)),
+        }
+    }
+
+    /// 直接子节点
+    pub(crate) fn children(&self) -> Vec<&ConstraintNode> {
+        match self {
+            ConstraintNode::Enum(nodes) | ConstraintNode::And(nodes) => nodes.iter().collect(),
+            ConstraintNode::TaggedEnum(members) => members.iter().map(|(_, node)| node).collect(),
+            ConstraintNode::Pair(left, right) => vec![left, right],
+            ConstraintNode::Record(fields) => fields
+                .iter()
+                .flat_map(|(_, field)| std::iter::once(&field.ty).chain(&field.default))
+                .collect(),
+            ConstraintNode::Annotated(inner, _) => vec![inner],
+            ConstraintNode::T
+            | ConstraintNode::F
+            | ConstraintNode::Leaf(_)
+            | ConstraintNode::Kind(_)
+            | ConstraintNode::Range { .. }
+            | ConstraintNode::IntSet(_)
+            | ConstraintNode::Def(_)
+            | ConstraintNode::Custom(_)
+            | ConstraintNode::Predicate(_) => Vec::new(),
+        }
+    }
+
+    /// 节点中（包括子节点中）引用的所有定义名
+    pub(crate) fn referenced_defs<'a>(&'a self, result: &mut Vec<&'a str>) {
+        if let ConstraintNode::Def(name) = self {
+            result.push(name);
+        }
+        for child in self.children() {
+            child.referenced_defs(result);
+        }
+    }
+
+    /// 对每个直接子节点应用 f 后重建节点，没有子节点的节点原样复制
+    pub(crate) fn map_children<F>(&self, mut f: F) -> ConstraintNode
+    where
+        F: FnMut(&ConstraintNode) -> ConstraintNode,
+    {
+        match self {
+            ConstraintNode::Enum(nodes) => ConstraintNode::Enum(nodes.iter().map(f).collect()),
+            ConstraintNode::And(nodes) => ConstraintNode::And(nodes.iter().map(f).collect()),
+            ConstraintNode::TaggedEnum(members) => ConstraintNode::TaggedEnum(
+                members
+                    .iter()
+                    .map(|(tag, node)| (tag.clone(), f(node)))
+                    .collect(),
+            ),
+            ConstraintNode::Pair(left, right) => {
+                ConstraintNode::Pair(f(left).into(), f(right).into())
+            }
+            ConstraintNode::Record(fields) => ConstraintNode::Record(
+                fields
+                    .iter()
+                    .map(|(name, field)| {
+                        let ty = f(&field.ty);
+                        let default = field.default.as_ref().map(&mut f);
+                        (name.clone(), RecordField { ty, default })
+                    })
+                    .collect(),
+            ),
+            ConstraintNode::Annotated(inner, metadata) => {
+                ConstraintNode::Annotated(f(inner).into(), metadata.clone())
+            }
+            ConstraintNode::T
+            | ConstraintNode::F
+            | ConstraintNode::Leaf(_)
+            | ConstraintNode::Kind(_)
+            | ConstraintNode::Range { .. }
+            | ConstraintNode::IntSet(_)
+            | ConstraintNode::Def(_)
+            | ConstraintNode::Custom(_)
+            | ConstraintNode::Predicate(_) => self.clone(),
+        }
+    }
+
+    /// 按 renames 替换节点中引用的定义名，未出现在 renames 中的名字保持不变
+    pub(crate) fn rename_defs(&self, renames: &HashMap<String, String>) -> ConstraintNode {
+        match self {
+            ConstraintNode::Def(name) => {
+                ConstraintNode::Def(renames.get(name).unwrap_or(name).clone())
+            }
+            other => other.map_children(|node| node.rename_defs(renames)),
+        }
+    }
+
+    /// 返回最外层注释携带的元数据
+    pub fn metadata(&self) -> Option<&Metadata> {
+        match self {
+            ConstraintNode::Annotated(_, metadata) => Some(metadata),
+            _ => None,
+        }
+    }
 }
 
 impl PartialEq for ConstraintNode {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
+        // 元数据不参与比较，注释节点与裸节点视为相等
+        match (self.strip_annotations(), other.strip_annotations()) {
             (ConstraintNode::T, ConstraintNode::T) => true,
             (ConstraintNode::F, ConstraintNode::F) => true,
             (ConstraintNode::Leaf(a), ConstraintNode::Leaf(b)) => a == b,
+            (ConstraintNode::Kind(a), ConstraintNode::Kind(b)) => a == b,
+            (
+                ConstraintNode::Range { lo: lo1, hi: hi1 },
+                ConstraintNode::Range { lo: lo2, hi: hi2 },
+            ) => lo1 == lo2 && hi1 == hi2,
+            (ConstraintNode::IntSet(a), ConstraintNode::IntSet(b)) => a == b,
             (ConstraintNode::Enum(a), ConstraintNode::Enum(b)) => a == b,
+            (ConstraintNode::And(a), ConstraintNode::And(b)) => a == b,
+            (ConstraintNode::TaggedEnum(a), ConstraintNode::TaggedEnum(b)) => a == b,
             (ConstraintNode::Pair(a1, b1), ConstraintNode::Pair(a2, b2)) => a1 == a2 && b1 == b2,
+            (ConstraintNode::Record(a), ConstraintNode::Record(b)) => a == b,
             (ConstraintNode::Def(name1), ConstraintNode::Def(name2)) => name1 == name2,
+            (ConstraintNode::Custom(a), ConstraintNode::Custom(b)) => a.eq_custom(b.as_ref()),
+            (ConstraintNode::Predicate(a), ConstraintNode::Predicate(b)) => a == b,
             _ => false,
         }
     }
@@ -58,28 +377,182 @@ impl Eq for ConstraintNode {}
 
 impl Hash for ConstraintNode {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        match self {
+        match self.strip_annotations() {
             ConstraintNode::T => "T".hash(state),
             ConstraintNode::F => "F".hash(state),
             ConstraintNode::Leaf(lit) => lit.hash(state),
+            ConstraintNode::Kind(kind) => {
+                "Kind".hash(state);
+                kind.hash(state);
+            }
+            ConstraintNode::Range { lo, hi } => {
+                "Range".hash(state);
+                lo.hash(state);
+                hi.hash(state);
+            }
+            ConstraintNode::IntSet(set) => {
+                "IntSet".hash(state);
+                set.hash(state);
+            }
             ConstraintNode::Enum(nodes) => {
                 "Enum".hash(state);
                 nodes.hash(state);
             }
+            ConstraintNode::And(nodes) => {
+                "And".hash(state);
+                nodes.hash(state);
+            }
+            ConstraintNode::TaggedEnum(members) => {
+                "TaggedEnum".hash(state);
+                members.hash(state);
+            }
             ConstraintNode::Pair(a, b) => {
                 "Pair".hash(state);
                 a.hash(state);
                 b.hash(state);
             }
+            ConstraintNode::Record(fields) => {
+                "Record".hash(state);
+                fields.hash(state);
+            }
             ConstraintNode::Def(name) => name.hash(state),
+            ConstraintNode::Custom(custom) => {
+                "Custom".hash(state);
+                custom.hash_custom(state);
+            }
+            ConstraintNode::Predicate(id) => {
+                "Predicate".hash(state);
+                id.hash(state);
+            }
+            ConstraintNode::Annotated(..) => {
+                unreachable!("annotations are stripped before hashing")
+            }
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// 约束图的内存概况，见 `Constraint::graph_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphStats {
+    pub definitions: usize,   // 定义的个数
+    pub nodes: usize,         // 所有定义体中节点的总数
+    pub uniquely_owned: bool, // 图没有与其他约束共享
+}
+
+/// 两个约束之间的包含关系，见 `Constraint::compare`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ordering4 {
+    Equal,        // 互为超约束
+    Subtype,      // self 严格小于 other
+    Supertype,    // self 严格大于 other
+    Incomparable, // 互不包含
+}
+
+/// 一次包含判定在余归纳与归纳两种解释下的结果，见 `Constraint::subsumption_semantics_note`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SemanticsNote {
+    Proved,          // 有限次展开即可证明，两种解释下都成立
+    Refuted,         // 默认的余归纳判定不成立，归纳解释下未必不成立，例如 `Nil >= S := (1, S)`
+    CoinductiveOnly, // 需要用假设闭合循环：默认的 `super_of` 成立，`super_of_finite` 不成立
+}
+
+/// `Constraint::depth_truncate` 截断递归时使用的近似方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Approximation {
+    Over,  // 截断处替换为 T，结果是原约束的超约束
+    Under, // 截断处替换为 F，结果是原约束的子约束
+}
+
+/// `Constraint::combine` 遇到两侧同名但定义体不同的定义时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConflictPolicy {
+    Rename,   // 右侧的定义改名，并改写右侧对它的引用
+    Error,    // 返回 `ConstraintError::ConflictingDef`
+    KeepLeft, // 丢弃右侧的定义，右侧的引用改为指向左侧的同名定义
+}
+
+/// 字符串字面量之间的包含规则，见 `Constraint::super_of_string_mode`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StringMatch {
+    #[default]
+    Exact, // 两个字符串相同
+    Prefix, // 左侧是右侧的前缀
+    Glob,   // 左侧是通配模式，`*` 匹配任意长度的字符序列，`?` 匹配一个字符
+}
+
+/// text 是否匹配通配模式 pattern，`*` 匹配任意长度的字符序列，`?` 匹配一个字符
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 `*` 的位置，以及它当前匹配到的文本位置，失配时回到这里让 `*` 多匹配一个字符
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    t = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 从入口到某个节点的路径中的一步，见 `Constraint::def_occurrences`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    InDef(String),        // 进入定义的定义体
+    EnumIndex(usize),     // 枚举的第 i 个成员
+    AndIndex(usize),      // 交的第 i 个成员
+    Tagged(String),       // 带标签的枚举中该标签的载荷
+    Field(String),        // 记录中该字段的类型
+    FieldDefault(String), // 记录中该字段的默认值
+    PairLeft,
+    PairRight,
+}
+
+/// 一次子类型判定的统计，见 `Constraint::super_of_diagnostic`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    pub steps: usize,            // 判定的总步数
+    pub max_depth: usize,        // 判定嵌套的最大深度
+    pub threshold_hit: bool,     // 步数是否超过了软阈值
+    pub goal_stack: Vec<String>, // 首次超过阈值时正在展开的定义判定，从外到内
+}
+
+/// 两个版本的约束图之间定义的变化，见 `Constraint::diff_defs`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefDiff {
+    pub added: BTreeSet<String>,   // 只出现在新图中的定义
+    pub removed: BTreeSet<String>, // 只出现在旧图中的定义
+    pub changed: BTreeSet<String>, // 两个图中都有但定义体不同的定义
+}
+
+impl DefDiff {
+    /// 两个图的定义完全相同
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Clone)]
 pub struct Constraint {
-    graph: Arc<HashMap<String, ConstraintNode>>,
-    entry: String, // 入口节点
+    pub(crate) graph: Arc<HashMap<String, ConstraintNode>>,
+    pub(crate) entry: String,                      // 入口节点
+    arena: Arc<OnceLock<DefArena>>,                // 首次判定时构建，之后 Def 按下标解析
+    pub(crate) predicates: Arc<PredicateRegistry>, // 谓词节点引用的闭包
 }
 
 impl Constraint {
@@ -87,17 +560,403 @@ impl Constraint {
         Constraint {
             graph: HashMap::new().into(),
             entry,
+            arena: Default::default(),
+            predicates: Default::default(),
+        }
+    }
+
+    /// 与 `new` 相同，但拒绝空的或只含空白的入口名
+    pub fn try_new(entry: String) -> Result<Self, ConstraintError> {
+        if entry.trim().is_empty() {
+            return Err(ConstraintError::InvalidEntryName(entry));
+        }
+        Ok(Constraint::new(entry))
+    }
+
+    pub(crate) fn from_graph(graph: HashMap<String, ConstraintNode>, entry: String) -> Self {
+        Constraint {
+            graph: Arc::new(graph),
+            entry,
+            arena: Default::default(),
+            predicates: Default::default(),
         }
     }
 
+    /// 与已有的约束共享同一张图，只替换入口
+    pub(crate) fn from_graph_shared(
+        graph: Arc<HashMap<String, ConstraintNode>>,
+        entry: String,
+    ) -> Self {
+        Constraint {
+            graph,
+            entry,
+            arena: Default::default(),
+            predicates: Default::default(),
+        }
+    }
+
+    /// 一次性从 (名字, 定义体) 序列构建约束图，并校验入口与所有引用均已定义
+    pub fn from_nodes<I>(entry: String, nodes: I) -> Result<Self, ConstraintError>
+    where
+        I: IntoIterator<Item = (String, ConstraintNode)>,
+    {
+        let constraint = Constraint::from_graph(nodes.into_iter().collect(), entry);
+        constraint.validate()?;
+        Ok(constraint)
+    }
+
+    /// 校验入口名非空、入口存在，且图中引用到的定义都已定义
+    pub fn validate(&self) -> Result<(), ConstraintError> {
+        self.validate_with(&HashSet::new())
+    }
+
+    /// 与 `validate` 相同，但允许引用 undefined 中的名字（例如待求解的占位定义）
+    pub(crate) fn validate_with(&self, undefined: &HashSet<String>) -> Result<(), ConstraintError> {
+        if self.entry.trim().is_empty() {
+            return Err(ConstraintError::InvalidEntryName(self.entry.clone()));
+        }
+        if !self.graph.contains_key(&self.entry) {
+            return Err(ConstraintError::MissingEntry(self.entry.clone()));
+        }
+        let mut referenced = Vec::new();
+        for node in self.nodes() {
+            node.referenced_defs(&mut referenced);
+        }
+        match referenced
+            .into_iter()
+            .find(|name| !self.graph.contains_key(*name) && !undefined.contains(*name))
+        {
+            Some(name) => Err(ConstraintError::UndefinedDef(name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// 删除一个定义并返回其定义体；图被共享时先复制一份，其他约束不受影响
+    pub fn remove_node(&mut self, name: &str) -> Option<ConstraintNode> {
+        if !self.graph.contains_key(name) {
+            return None;
+        }
+        self.arena = Default::default();
+        Arc::make_mut(&mut self.graph).remove(name)
+    }
+
+    /// 释放图中多余的容量；图被共享时先复制一份，复制出的图本身就是紧凑的
+    pub fn shrink_to_fit(&mut self) {
+        Arc::make_mut(&mut self.graph).shrink_to_fit();
+    }
+
+    pub fn graph_stats(&self) -> GraphStats {
+        fn count(node: &ConstraintNode) -> usize {
+            1 + node.children().into_iter().map(count).sum::<usize>()
+        }
+        GraphStats {
+            definitions: self.graph.len(),
+            nodes: self.graph.values().map(count).sum(),
+            uniquely_owned: Arc::strong_count(&self.graph) == 1,
+        }
+    }
+
+    /// 相对于旧版本 old 的定义变化，self 是新版本
+    ///
+    /// 定义体按结构逐节点比较，不做语义判定：接受相同值但写法不同的定义也算作改变。
+    /// 引用了改变的定义的定义本身不算改变，需要时由调用方沿依赖传播
+    pub fn diff_defs(&self, old: &Self) -> DefDiff {
+        let mut diff = DefDiff::default();
+        for (name, node) in self.graph.iter() {
+            match old.graph.get(name) {
+                None => {
+                    diff.added.insert(name.clone());
+                }
+                Some(old_node) if old_node != node => {
+                    diff.changed.insert(name.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .graph
+            .keys()
+            .filter(|name| !self.graph.contains_key(*name))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    /// 从 self 到 other 的逐定义文本差异，定义按名字排序，每行以紧凑形式输出一个定义
+    ///
+    /// 未变的定义以两个空格开头，删除的以 `- ` 开头，新增的以 `+ ` 开头；改变的定义先后
+    /// 输出旧、新两行，再逐行列出定义体中不同的最小子节点 `旧 -> 新`。定义按名字与结构
+    /// 比较（见 `diff_defs`），递归引用只打印定义名，不会展开
+    pub fn pretty_diff(&self, other: &Self) -> String {
+        // 两侧形状相同时逐个子节点比较，否则整个节点算作不同
+        fn differences<'n>(
+            old: &'n ConstraintNode,
+            new: &'n ConstraintNode,
+            result: &mut Vec<(&'n ConstraintNode, &'n ConstraintNode)>,
+        ) {
+            let (old, new) = (old.strip_annotations(), new.strip_annotations());
+            if old == new {
+                return;
+            }
+            let old_children = old.children();
+            let new_children = new.children();
+            let same_shape = std::mem::discriminant(old) == std::mem::discriminant(new)
+                && !old_children.is_empty()
+                && old_children.len() == new_children.len()
+                && match (old, new) {
+                    (ConstraintNode::TaggedEnum(a), ConstraintNode::TaggedEnum(b)) => a
+                        .iter()
+                        .zip(b)
+                        .all(|((a_tag, _), (b_tag, _))| a_tag == b_tag),
+                    (ConstraintNode::Record(a), ConstraintNode::Record(b)) => {
+                        a.iter()
+                            .zip(b)
+                            .all(|((a_name, a_field), (b_name, b_field))| {
+                                a_name == b_name
+                                    && a_field.default.is_some() == b_field.default.is_some()
+                            })
+                    }
+                    _ => true,
+                };
+            if same_shape {
+                for (old, new) in old_children.into_iter().zip(new_children) {
+                    differences(old, new, result);
+                }
+            } else {
+                result.push((old, new));
+            }
+        }
+
+        let diff = other.diff_defs(self);
+        let mut names: BTreeSet<&String> = self.graph.keys().collect();
+        names.extend(other.graph.keys());
+        let mut lines = Vec::new();
+        for name in names {
+            let line = |prefix: &str, constraint: &Constraint, node: &ConstraintNode| {
+                format!("{}{} := {}", prefix, name, NodeDisplay { constraint, node })
+            };
+            if diff.added.contains(name) {
+                lines.push(line("+ ", other, &other.graph[name]));
+            } else if diff.removed.contains(name) {
+                lines.push(line("- ", self, &self.graph[name]));
+            } else if diff.changed.contains(name) {
+                let (old, new) = (&self.graph[name], &other.graph[name]);
+                lines.push(line("- ", self, old));
+                lines.push(line("+ ", other, new));
+                let mut changes = Vec::new();
+                differences(old, new, &mut changes);
+                for (old, new) in changes {
+                    lines.push(format!(
+                        "    {} -> {}",
+                        NodeDisplay {
+                            constraint: self,
+                            node: old
+                        },
+                        NodeDisplay {
+                            constraint: other,
+                            node: new
+                        }
+                    ));
+                }
+            } else {
+                lines.push(line("  ", self, &self.graph[name]));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// 从入口出发的每一处定义引用及其路径，按深度优先的顺序排列
+    ///
+    /// 路径从 `InDef(入口)` 开始；每个定义只在第一次被引用的位置展开一次，
+    /// 它的定义体中的引用沿这条路径报告，注释节点不占路径
+    pub fn def_occurrences(&self) -> Vec<(String, Vec<PathSegment>)> {
+        fn visit<'a>(
+            constraint: &'a Constraint,
+            node: &'a ConstraintNode,
+            path: &mut Vec<PathSegment>,
+            expanded: &mut HashSet<&'a str>,
+            result: &mut Vec<(String, Vec<PathSegment>)>,
+        ) {
+            let child = |segment: PathSegment,
+                         node: &'a ConstraintNode,
+                         path: &mut Vec<PathSegment>,
+                         expanded: &mut HashSet<&'a str>,
+                         result: &mut Vec<(String, Vec<PathSegment>)>| {
+                path.push(segment);
+                visit(constraint, node, path, expanded, result);
+                path.pop();
+            };
+            match node.strip_annotations() {
+                ConstraintNode::Def(name) => {
+                    result.push((name.clone(), path.clone()));
+                    if expanded.insert(name)
+                        && let Some(body) = constraint.get_node(name)
+                    {
+                        child(
+                            PathSegment::InDef(name.clone()),
+                            body,
+                            path,
+                            expanded,
+                            result,
+                        );
+                    }
+                }
+                ConstraintNode::Enum(nodes) => {
+                    for (i, node) in nodes.iter().enumerate() {
+                        child(PathSegment::EnumIndex(i), node, path, expanded, result);
+                    }
+                }
+                ConstraintNode::And(nodes) => {
+                    for (i, node) in nodes.iter().enumerate() {
+                        child(PathSegment::AndIndex(i), node, path, expanded, result);
+                    }
+                }
+                ConstraintNode::TaggedEnum(members) => {
+                    for (tag, node) in members {
+                        child(
+                            PathSegment::Tagged(tag.clone()),
+                            node,
+                            path,
+                            expanded,
+                            result,
+                        );
+                    }
+                }
+                ConstraintNode::Record(fields) => {
+                    for (name, field) in fields {
+                        let segment = PathSegment::Field(name.clone());
+                        child(segment, &field.ty, path, expanded, result);
+                        if let Some(default) = &field.default {
+                            let segment = PathSegment::FieldDefault(name.clone());
+                            child(segment, default, path, expanded, result);
+                        }
+                    }
+                }
+                ConstraintNode::Pair(left, right) => {
+                    child(PathSegment::PairLeft, left, path, expanded, result);
+                    child(PathSegment::PairRight, right, path, expanded, result);
+                }
+                _ => {}
+            }
+        }
+        let mut result = Vec::new();
+        if let Some(body) = self.get_node(&self.entry) {
+            let mut expanded = HashSet::from([self.entry.as_str()]);
+            let mut path = vec![PathSegment::InDef(self.entry.clone())];
+            visit(self, body, &mut path, &mut expanded, &mut result);
+        }
+        result
+    }
+
+    /// 从 name 的定义体出发传递引用到的所有定义，name 只在经由引用回到自身时包含在内
+    pub fn dependencies(&self, name: &str) -> HashSet<String> {
+        let mut dependencies = HashSet::new();
+        let mut pending = Vec::new();
+        if let Some(node) = self.graph.get(name) {
+            node.referenced_defs(&mut pending);
+        }
+        while let Some(current) = pending.pop() {
+            if dependencies.insert(current.to_string())
+                && let Some(node) = self.graph.get(current)
+            {
+                node.referenced_defs(&mut pending);
+            }
+        }
+        dependencies
+    }
+
+    /// 传递引用了 name 的所有定义，即 name 改变时需要重新校验的定义
+    pub fn dependents(&self, name: &str) -> HashSet<String> {
+        // 反向边：被引用的定义 -> 引用它的定义
+        let mut referrers: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (referrer, node) in self.graph.iter() {
+            let mut referenced = Vec::new();
+            node.referenced_defs(&mut referenced);
+            for referenced in referenced {
+                referrers.entry(referenced).or_default().push(referrer);
+            }
+        }
+        let mut dependents = HashSet::new();
+        let mut pending = referrers.get(name).cloned().unwrap_or_default();
+        while let Some(current) = pending.pop() {
+            if dependents.insert(current.to_string())
+                && let Some(next) = referrers.get(current)
+            {
+                pending.extend(next);
+            }
+        }
+        dependents
+    }
+
+    /// 定义依赖图中环的个数，即按定义名顺序深度优先遍历时遇到的回边数
+    ///
+    /// 每条直接自引用算一个环，互相引用的一组定义 A -> B -> A 也算一个环
+    pub fn cycle_count(&self) -> usize {
+        fn visit<'a>(
+            constraint: &'a Constraint,
+            name: &'a str,
+            on_stack: &mut HashSet<&'a str>,
+            done: &mut HashSet<&'a str>,
+        ) -> usize {
+            on_stack.insert(name);
+            let mut referenced = Vec::new();
+            if let Some(node) = constraint.graph.get(name) {
+                node.referenced_defs(&mut referenced);
+            }
+            referenced.sort();
+            referenced.dedup();
+            let mut back_edges = 0;
+            for next in referenced {
+                if on_stack.contains(next) {
+                    back_edges += 1;
+                } else if !done.contains(next) && constraint.graph.contains_key(next) {
+                    back_edges += visit(constraint, next, on_stack, done);
+                }
+            }
+            on_stack.remove(name);
+            done.insert(name);
+            back_edges
+        }
+        let mut names: Vec<&String> = self.graph.keys().collect();
+        names.sort();
+        let mut done = HashSet::new();
+        let mut cycles = 0;
+        for name in names {
+            if !done.contains(name.as_str()) {
+                cycles += visit(self, name, &mut HashSet::new(), &mut done);
+            }
+        }
+        cycles
+    }
+
+    /// 环的个数超过 max 时返回错误，用于限制接受的递归约束的复杂程度
+    pub fn reject_if_cycles_exceed(&self, max: usize) -> Result<(), ConstraintError> {
+        match self.cycle_count() {
+            cycles if cycles > max => Err(ConstraintError::TooManyCycles { cycles, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// 加入或替换一个定义，图被共享时 panic，不希望 panic 时使用 `try_add_node`
     pub fn add_node(&mut self, name: String, node: ConstraintNode) {
+        if self.try_add_node(name, node).is_err() {
+            panic!("Cannot modify a shared graph");
+        }
+    }
+
+    pub fn try_add_node(
+        &mut self,
+        name: String,
+        node: ConstraintNode,
+    ) -> Result<(), ConstraintError> {
         match Arc::get_mut(&mut self.graph) {
             Some(graph) => {
                 graph.insert(name, node);
+                // 图已改变，丢弃旧的索引
+                self.arena = Default::default();
+                Ok(())
             }
-            None => {
-                panic!("Cannot modify a shared graph");
-            }
+            None => Err(ConstraintError::SharedGraph(name)),
         }
     }
 
@@ -105,53 +964,1247 @@ impl Constraint {
         self.graph.get(name)
     }
 
+    /// 定义的连续存储，第一次访问时构建并在共享同一张图的约束之间复用
+    pub(crate) fn arena(&self) -> &DefArena {
+        self.arena.get_or_init(|| DefArena::build(&self.graph))
+    }
+
     pub fn entry(&self) -> &String {
         &self.entry
     }
-}
 
-impl Constraint {
-    pub fn super_of(&self, other: &Self) -> bool {
-        let mut assumption = HashSet::new();
-        match self.get_node(&self.entry) {
-            Some(node_a) => match other.get_node(&other.entry) {
-                Some(node_b) => {
-                    Constraint::check_subsumption(self, other, node_a, node_b, &mut assumption)
-                }
-                None => false,
-            },
-            None => false,
+    /// 以定义 name 为入口的约束，与 self 共享同一张图与谓词表；只保留可达的定义可再调用
+    /// `prune`
+    pub fn subconstraint(&self, name: &str) -> Result<Constraint, ConstraintError> {
+        if !self.graph.contains_key(name) {
+            return Err(ConstraintError::MissingEntry(name.to_string()));
+        }
+        let mut sub = self.clone();
+        sub.entry = name.to_string();
+        Ok(sub)
+    }
+
+    /// 图中所有定义的名字及其参数个数，按名字排序
+    ///
+    /// 目前的定义都没有参数，参数个数总是 0；保留这一项是为了以后加入参数化的定义
+    pub fn definitions(&self) -> Vec<(String, usize)> {
+        self.iter_defs()
+            .map(|(name, _)| (name.to_string(), 0))
+            .collect()
+    }
+
+    /// 约束是否只接受有限个值：不含 T、类别、谓词与自定义约束，区间两端有界且不是字符串
+    /// 或浮点区间（单点除外），从入口出发不经过递归的定义
+    ///
+    /// 判定在 `reduce` 之后的图上进行，`(F, T)` 这样的空约束是有限的；交只要有一个成员
+    /// 有限即为有限。判定是保守的：返回 true 时一定有限，经过递归定义的约束总是视为
+    /// 无限，即使递归实际上不产生新的值
+    pub fn is_finite(&self) -> bool {
+        fn finite<'a>(
+            constraint: &'a Constraint,
+            node: &'a ConstraintNode,
+            expanding: &mut Vec<&'a str>,
+            finished: &mut HashSet<&'a str>,
+        ) -> bool {
+            match node.strip_annotations() {
+                ConstraintNode::F | ConstraintNode::Leaf(_) | ConstraintNode::IntSet(_) => true,
+                ConstraintNode::T
+                | ConstraintNode::Kind(_)
+                | ConstraintNode::Custom(_)
+                | ConstraintNode::Predicate(_) => false,
+                ConstraintNode::Range { lo, hi } => {
+                    range::is_empty(lo, hi)
+                        || range::singleton(lo, hi).is_some()
+                        || (*lo != Bound::Unbounded
+                            && *hi != Bound::Unbounded
+                            && matches!(range::kind(lo, hi), Some(Kind::Int | Kind::Char)))
+                }
+                ConstraintNode::Enum(nodes) => nodes
+                    .iter()
+                    .all(|node| finite(constraint, node, expanding, finished)),
+                ConstraintNode::And(nodes) => nodes
+                    .iter()
+                    .any(|node| finite(constraint, node, expanding, finished)),
+                ConstraintNode::TaggedEnum(members) => members
+                    .iter()
+                    .all(|(_, node)| finite(constraint, node, expanding, finished)),
+                ConstraintNode::Pair(left, right) => {
+                    finite(constraint, left, expanding, finished)
+                        && finite(constraint, right, expanding, finished)
+                }
+                ConstraintNode::Record(fields) => fields
+                    .iter()
+                    .all(|(_, field)| finite(constraint, &field.ty, expanding, finished)),
+                ConstraintNode::Def(name) => {
+                    if finished.contains(name.as_str()) {
+                        return true;
+                    }
+                    if expanding.contains(&name.as_str()) {
+                        return false;
+                    }
+                    // 未定义的定义不接受任何值
+                    let Some(body) = constraint.get_node(name) else {
+                        return true;
+                    };
+                    expanding.push(name);
+                    let result = finite(constraint, body, expanding, finished);
+                    expanding.pop();
+                    if result {
+                        finished.insert(name);
+                    }
+                    result
+                }
+                ConstraintNode::Annotated(..) => unreachable!("annotations are stripped"),
+            }
+        }
+        let reduced = self.reduce();
+        let entry = ConstraintNode::Def(reduced.entry.clone());
+        finite(&reduced, &entry, &mut Vec::new(), &mut HashSet::new())
+    }
+
+    /// 图中所有定义的名字与定义体，按名字排序
+    ///
+    /// 图本身是无序的 `HashMap`，需要可复现的输出（快照测试、报告）时应当通过这里遍历
+    pub fn iter_defs(&self) -> impl Iterator<Item = (&str, &ConstraintNode)> {
+        let mut defs: Vec<(&str, &ConstraintNode)> = self
+            .graph
+            .iter()
+            .map(|(name, node)| (name.as_str(), node))
+            .collect();
+        defs.sort_by_key(|(name, _)| *name);
+        defs.into_iter()
+    }
+
+    /// 图中所有定义的定义体，按定义名排序
+    pub fn nodes(&self) -> impl Iterator<Item = &ConstraintNode> {
+        self.iter_defs().map(|(_, node)| node)
+    }
+
+    /// 定义名到定义体的引用表，可用于一次性建立外部的交叉引用索引
+    ///
+    /// 引用借用自 self，在 self 被修改之前一直有效。克隆与 self 共享同一张图，引用同样
+    /// 指向克隆中的定义体；共享的图不会被原地修改，修改要么被拒绝（`try_add_node`），
+    /// 要么先复制一份（`remove_node`），因此修改克隆不会影响这些引用
+    pub fn node_refs(&self) -> HashMap<&str, &ConstraintNode> {
+        self.graph
+            .iter()
+            .map(|(name, node)| (name.as_str(), node))
+            .collect()
+    }
+
+    /// 对每个定义体应用 f 并重建约束图，入口与定义名保持不变
+    ///
+    /// f 的参数为定义名和定义体，返回新的定义体；除 f 本身的改动外，`Def` 引用原样保留
+    pub fn map_defs<F>(&self, mut f: F) -> Constraint
+    where
+        F: FnMut(&str, &ConstraintNode) -> ConstraintNode,
+    {
+        let graph = self
+            .graph
+            .iter()
+            .map(|(name, node)| (name.clone(), f(name, node)))
+            .collect();
+        let mut mapped = Constraint::from_graph(graph, self.entry.clone());
+        mapped.predicates = self.predicates.clone();
+        mapped
+    }
+
+    /// 擦除字面量精度：字面量与区间替换为所属的类别，Pair、Enum 与 Def 结构保持不变
+    ///
+    /// 结果总是原约束的超约束。Nil 与无法确定类别的区间没有对应的类别，原样保留
+    pub fn erase_literals(&self) -> Constraint {
+        fn erase(node: &ConstraintNode) -> ConstraintNode {
+            erased_literal(node).unwrap_or_else(|| node.map_children(erase))
+        }
+        self.map_defs(|_, node| erase(node))
+    }
+
+    /// 只按结构与类别判定 self >= other，忽略字面量的具体值
+    ///
+    /// 与 `self.erase_literals().super_of(&other.erase_literals())` 的结果相同，但不需要
+    /// 先复制两张图：判定时遇到字面量、区间或整数集合就按其类别比较
+    pub fn super_of_structural(&self, other: &Self) -> bool {
+        let mut checker = Checker::new(self, other);
+        checker.structural = true;
+        checker.run()
+    }
+
+    /// 与 `super_of` 相同，但缓存已经判定的定义对，缓存至多保存 max_memo_entries 项，
+    /// 超过时淘汰最久未使用的项
+    ///
+    /// 缓存只影响需要展开的次数而不影响结果：容量越小命中越少、判定越慢，内存占用
+    /// 不超过容量
+    pub fn super_of_bounded_mem(&self, other: &Self, max_memo_entries: usize) -> bool {
+        let mut checker = Checker::new(self, other);
+        checker.memo = Some(Memo::new(max_memo_entries));
+        checker.run()
+    }
+
+    /// 所有 items[i] >= items[j] 组成的矩阵，对角线总是 true
+    ///
+    /// 共享同一张图与同一入口的约束（例如彼此的克隆）只判定一次，其行与列直接复用
+    pub fn subsumption_matrix(items: &[Constraint]) -> Vec<Vec<bool>> {
+        // 每一项对应的代表项：第一个与它共享图与入口的项
+        let representative: Vec<usize> = (0..items.len())
+            .map(|i| {
+                (0..i)
+                    .find(|&j| {
+                        Arc::ptr_eq(&items[i].graph, &items[j].graph)
+                            && items[i].entry == items[j].entry
+                    })
+                    .unwrap_or(i)
+            })
+            .collect();
+        let mut matrix = vec![vec![true; items.len()]; items.len()];
+        for i in 0..items.len() {
+            for j in 0..items.len() {
+                let (a, b) = (representative[i], representative[j]);
+                matrix[i][j] = if a == b {
+                    true
+                } else if a < i || b < j {
+                    matrix[a][b]
+                } else {
+                    items[i].super_of(&items[j])
+                };
+            }
+        }
+        matrix
+    }
+
+    /// `subsumption_matrix` 的覆盖关系（传递归约），即 Hasse 图的边：covers[i][j] 为真
+    /// 当且仅当 items[i] 严格大于 items[j]，且没有严格介于两者之间的项
+    ///
+    /// 互相包含的项视为相等，它们之间没有边
+    pub fn subsumption_covers(items: &[Constraint]) -> Vec<Vec<bool>> {
+        let matrix = Constraint::subsumption_matrix(items);
+        let above = |i: usize, j: usize| matrix[i][j] && !matrix[j][i];
+        (0..items.len())
+            .map(|i| {
+                (0..items.len())
+                    .map(|j| above(i, j) && !(0..items.len()).any(|k| above(i, k) && above(k, j)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// 把递归定义展开至多 depth 层得到的不含递归的近似，所有定义都内联到入口中
+    ///
+    /// 从入口的定义体开始，沿每条路径每个递归定义的引用最多展开 depth 次，更深处的引用按
+    /// approximation 替换为 T（超约束）或 F（子约束）；非递归的定义总是展开。
+    /// 非递归定义的展开受 occurs-check 保护，失败时 panic，见 `try_depth_truncate`
+    pub fn depth_truncate(&self, depth: usize, approximation: Approximation) -> Constraint {
+        match self.try_depth_truncate(depth, approximation) {
+            Ok(truncated) => truncated,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// 不会 panic 的 `depth_truncate`
+    pub fn try_depth_truncate(
+        &self,
+        depth: usize,
+        approximation: Approximation,
+    ) -> Result<Constraint, ConstraintError> {
+        fn unfold(
+            constraint: &Constraint,
+            node: &ConstraintNode,
+            depth: usize,
+            cut: &ConstraintNode,
+            recursive: &HashSet<String>,
+            expansions: &mut HashMap<String, usize>,
+            check: &mut simplify::OccursCheck,
+        ) -> ConstraintNode {
+            let ConstraintNode::Def(name) = node else {
+                return node.map_children(|child| {
+                    unfold(constraint, child, depth, cut, recursive, expansions, check)
+                });
+            };
+            let Some(body) = constraint.get_node(name) else {
+                return node.clone();
+            };
+            if !recursive.contains(name) {
+                if !check.enter(name) {
+                    return node.clone();
+                }
+                let result = unfold(constraint, body, depth, cut, recursive, expansions, check);
+                check.exit();
+                return result;
+            }
+            let count = expansions.entry(name.clone()).or_default();
+            if *count >= depth {
+                return cut.clone();
+            }
+            *count += 1;
+            let result = unfold(constraint, body, depth, cut, recursive, expansions, check);
+            *expansions.get_mut(name.as_str()).unwrap() -= 1;
+            result
+        }
+
+        let cut = match approximation {
+            Approximation::Over => ConstraintNode::T,
+            Approximation::Under => ConstraintNode::F,
+        };
+        let entry = ConstraintNode::Def(self.entry.clone());
+        let mut check = simplify::OccursCheck::default();
+        let node = unfold(
+            self,
+            self.get_node(&self.entry).unwrap_or(&entry),
+            depth,
+            &cut,
+            &self.recursive_defs(),
+            &mut HashMap::new(),
+            &mut check,
+        );
+        check.finish()?;
+        Ok(Constraint::from_graph(
+            HashMap::from([(self.entry.clone(), simplify::reduce_node(&node))]),
+            self.entry.clone(),
+        ))
+    }
+
+    /// 约束中属于类别 kind 的值，即与 `Kind(kind)` 的交集
+    ///
+    /// 从入口出发经过 Enum 与 Def 收集该类别的字面量、区间与类别本身，T 与两端都无界的
+    /// 区间贡献整个类别；Pair、Nil、带标签的枚举等其他形状的值都被丢弃，自定义节点
+    /// 无法判断，同样丢弃。结果只有一个定义，不接受任何值时为 F
+    pub fn restrict_to_kind(&self, kind: Kind) -> Constraint {
+        fn collect<'a>(
+            constraint: &'a Constraint,
+            node: &'a ConstraintNode,
+            kind: Kind,
+            expanded: &mut HashSet<&'a str>,
+            kept: &mut Vec<ConstraintNode>,
+        ) {
+            match node.strip_annotations() {
+                ConstraintNode::T => kept.push(ConstraintNode::Kind(kind)),
+                ConstraintNode::Leaf(atom) if atom.kind() == Some(kind) => kept.push(node.clone()),
+                ConstraintNode::Kind(other) if *other == kind => kept.push(node.clone()),
+                ConstraintNode::IntSet(_) if kind == Kind::Int => kept.push(node.clone()),
+                ConstraintNode::Range { lo, hi } => match range::kind(lo, hi) {
+                    Some(other) if other == kind => kept.push(node.clone()),
+                    None if lo == &Bound::Unbounded && hi == &Bound::Unbounded => {
+                        kept.push(ConstraintNode::Kind(kind))
+                    }
+                    _ => {}
+                },
+                ConstraintNode::Enum(nodes) => {
+                    for node in nodes {
+                        collect(constraint, node, kind, expanded, kept);
+                    }
+                }
+                // 交集中属于该类别的值是各成员中属于该类别的值的交集
+                ConstraintNode::And(nodes) => kept.push(ConstraintNode::And(
+                    nodes
+                        .iter()
+                        .filter(|node| !is_expanded(node, expanded))
+                        .map(|node| {
+                            let mut member = Vec::new();
+                            collect(constraint, node, kind, &mut expanded.clone(), &mut member);
+                            ConstraintNode::Enum(member)
+                        })
+                        .chain([ConstraintNode::Kind(kind)])
+                        .collect(),
+                )),
+                ConstraintNode::Def(name) => {
+                    if let Some(body) = constraint.get_node(name)
+                        && expanded.insert(name)
+                    {
+                        collect(constraint, body, kind, expanded, kept);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut kept = Vec::new();
+        if let Some(entry) = self.get_node(self.entry()) {
+            collect(self, entry, kind, &mut HashSet::new(), &mut kept);
+        }
+        let node = simplify::reduce_node(&ConstraintNode::Enum(kept));
+        Constraint::from_graph(
+            HashMap::from([(self.entry().clone(), node)]),
+            self.entry().clone(),
+        )
+    }
+
+    /// 封闭世界：把图中所有的 T 替换为 universe 中各成员的并
+    ///
+    /// universe 的成员应当是不引用定义的节点，替换进来的成员不会再被展开
+    pub fn close_over(&self, universe: &[ConstraintNode]) -> Constraint {
+        fn close(node: &ConstraintNode, universe: &[ConstraintNode]) -> ConstraintNode {
+            match node {
+                ConstraintNode::T => ConstraintNode::Enum(universe.to_vec()),
+                other => other.map_children(|child| close(child, universe)),
+            }
+        }
+        self.map_defs(|_, node| close(node, universe))
+    }
+
+    /// 收集图中所有注释节点，返回 (所在定义名, 元数据)，按定义名排序
+    pub fn annotations(&self) -> Vec<(&str, &Metadata)> {
+        fn collect<'a>(
+            def: &'a str,
+            node: &'a ConstraintNode,
+            result: &mut Vec<(&'a str, &'a Metadata)>,
+        ) {
+            if let ConstraintNode::Annotated(_, metadata) = node {
+                result.push((def, metadata));
+            }
+            for child in node.children() {
+                collect(def, child, result);
+            }
+        }
+
+        let mut result = Vec::new();
+        for (name, node) in self.iter_defs() {
+            collect(name, node, &mut result);
+        }
+        result
+    }
+}
+
+impl Constraint {
+    pub fn super_of(&self, other: &Self) -> bool {
+        Checker::new(self, other).run()
+    }
+
+    /// self 在允许的类别转换下能否赋值给 target，即 target >= coerce(self)
+    pub fn assignable_to(&self, target: &Self, coercions: &CoercionTable) -> bool {
+        let mut checker = Checker::new(target, self);
+        checker.coercions = Some(coercions);
+        checker.run()
+    }
+
+    /// 求解 self 中的占位定义，使 self >= other 成立
+    ///
+    /// 占位定义以 `Def` 的形式出现在 self 中，无需在图中定义；返回的解中的节点来自
+    /// other 的约束图。若无论如何都无法满足则返回 None
+    pub fn unify_super_of(
+        &self,
+        other: &Self,
+        placeholders: &HashSet<String>,
+    ) -> Option<HashMap<String, ConstraintNode>> {
+        let mut checker = Checker::new(self, other);
+        checker.placeholders = Some(placeholders);
+        if checker.run() {
+            Some(checker.bindings)
+        } else {
+            None
+        }
+    }
+
+    /// 不会 panic 的 `super_of`：先校验两侧的图，入口缺失或存在悬空引用时返回错误
+    pub fn try_super_of(&self, other: &Self) -> Result<bool, ConstraintError> {
+        self.validate()?;
+        other.validate()?;
+        Ok(self.super_of(other))
+    }
+
+    /// 不会 panic 的 `assignable_to`
+    pub fn try_assignable_to(
+        &self,
+        target: &Self,
+        coercions: &CoercionTable,
+    ) -> Result<bool, ConstraintError> {
+        self.validate()?;
+        target.validate()?;
+        Ok(self.assignable_to(target, coercions))
+    }
+
+    /// 不会 panic 的 `unify_super_of`，self 中允许引用未定义的占位定义
+    pub fn try_unify_super_of(
+        &self,
+        other: &Self,
+        placeholders: &HashSet<String>,
+    ) -> Result<Option<HashMap<String, ConstraintNode>>, ConstraintError> {
+        self.validate_with(placeholders)?;
+        other.validate()?;
+        Ok(self.unify_super_of(other, placeholders))
+    }
+
+    /// 按有限值的含义判定 self >= other：递归定义按归纳解释，最多展开 `FINITE_FUEL` 层
+    ///
+    /// 余归纳的 `super_of` 用假设闭合循环，归纳模式下再次遇到同一个判定则视为失败，
+    /// 因此只有能通过有限次展开完成的判定才成立，例如 `bounded_list` 被递归的列表包含，
+    /// 而需要无穷展开的比较（两侧都是递归类型）不成立。结果为真时 `super_of` 也一定为真
+    pub fn super_of_finite(&self, other: &Self) -> bool {
+        self.super_of_finite_with_fuel(other, FINITE_FUEL)
+    }
+
+    /// 说明 self >= other 的结果是否依赖循环的解释方式
+    ///
+    /// 默认的 `super_of` 取最大不动点：再次遇到正在证明的判定时视为成立，因此两个递归的
+    /// 列表 `(Int, L) | Nil >= (1, L') | Nil` 成立，这也是列表相关测试所依赖的语义。
+    /// 这不会多接受值：约束图是正则的，最大不动点中的有限值都有有限的推导，所以余归纳地
+    /// 证明的包含对有限值同样成立；差别只在于 `super_of_finite` 要求有限次展开完成证明，
+    /// 这类判定在那里不成立，此时返回 `CoinductiveOnly`
+    pub fn subsumption_semantics_note(&self, other: &Self) -> SemanticsNote {
+        match (self.super_of(other), self.super_of_finite(other)) {
+            (true, true) => SemanticsNote::Proved,
+            (true, false) => SemanticsNote::CoinductiveOnly,
+            (false, _) => SemanticsNote::Refuted,
+        }
+    }
+
+    /// 与 `super_of_finite` 相同，但指定最多展开的层数
+    pub fn super_of_finite_with_fuel(&self, other: &Self, fuel: usize) -> bool {
+        let mut checker = Checker::new(self, other);
+        checker.fuel = Some(fuel);
+        checker.run()
+    }
+
+    /// 在封闭世界中判定 self >= other：两侧的 T 都解释为 universe 的并而不是抽象的顶
+    pub fn super_of_closed(&self, other: &Self, universe: &[ConstraintNode]) -> bool {
+        self.close_over(universe)
+            .super_of(&other.close_over(universe))
+    }
+
+    /// 判定 self >= other 并返回判定的统计：总步数、最大嵌套深度，以及步数是否超过了
+    /// 默认的软阈值 `SOFT_STEP_THRESHOLD`
+    ///
+    /// 超过阈值并不中止判定，只记录此时正在展开的定义判定，用于排查疑似不终止的比较；
+    /// 启用 trace feature 时同时打印出来
+    pub fn super_of_diagnostic(&self, other: &Self) -> (bool, Diagnostics) {
+        self.super_of_diagnostic_with_threshold(other, SOFT_STEP_THRESHOLD)
+    }
+
+    /// 与 `super_of_diagnostic` 相同，但指定软阈值
+    pub fn super_of_diagnostic_with_threshold(
+        &self,
+        other: &Self,
+        threshold: usize,
+    ) -> (bool, Diagnostics) {
+        let mut checker = Checker::new(self, other);
+        checker.diagnostics = Some(DiagnosticState {
+            report: Diagnostics::default(),
+            threshold,
+            depth: 0,
+            goals: Vec::new(),
+        });
+        let result = checker.run();
+        (result, checker.diagnostics.unwrap().report)
+    }
+
+    /// 整数字面量在容差 tol 之内视为相同的 `super_of`：`a >= b` 在 `|a - b| <= tol` 时成立，
+    /// 区间与类别的判定不受影响，tol 为负数时按 0 处理
+    ///
+    /// 容差比较不是传递的：容差为 2 时 `5 >= 7`、`7 >= 9` 都成立，`5 >= 9` 却不成立，
+    /// 因此不能用它的结果推导出其他的包含关系，也不要与 `==`、`simplify` 的结果混用
+    pub fn super_of_with_int_tolerance(&self, other: &Self, tol: i32) -> bool {
+        let mut checker = Checker::new(self, other);
+        checker.int_tolerance = tol.max(0) as u32;
+        checker.run()
+    }
+
+    /// 字符串字面量按 mode 比较的 `super_of`，例如 `Glob` 下 `"foo/*" >= "foo/bar"`
+    ///
+    /// 只改变字符串字面量之间的规则，字符串区间与其他类别的字面量仍然精确比较。
+    /// 与容差比较一样，`Prefix` 与 `Glob` 下的结果不要与 `==`、`simplify` 的结果混用
+    pub fn super_of_string_mode(&self, other: &Self, mode: StringMatch) -> bool {
+        let mut checker = Checker::new(self, other);
+        checker.string_match = mode;
+        checker.run()
+    }
+
+    /// 一次得到 self 与 other 的包含关系
+    ///
+    /// 先用 `BisimChecker` 同时检查两个方向，两侧互模拟时直接得到 `Equal`。否则再分别判定
+    /// 两个方向：互模拟检查中已经证明的定义对在两个方向上都成立，预先写入两个方向的缓存，
+    /// 判定到这些定义对时不再展开
+    pub fn compare(&self, other: &Self) -> Ordering4 {
+        let proven = match (self.get_node(&self.entry), other.get_node(&other.entry)) {
+            (Some(a), Some(b)) => {
+                let mut bisim = BisimChecker::new(self, other);
+                if bisim.check(a, b) {
+                    return Ordering4::Equal;
+                }
+                bisim.proven()
+            }
+            _ => Vec::new(),
+        };
+        let directed = |a: &Constraint, b: &Constraint, pairs: &mut dyn Iterator<Item = _>| {
+            let mut memo = Memo::new(usize::MAX);
+            for pair in pairs {
+                memo.insert(pair, true);
+            }
+            let mut checker = Checker::new(a, b);
+            checker.memo = Some(memo);
+            checker.run()
+        };
+        let forward = directed(self, other, &mut proven.iter().copied());
+        let backward = directed(other, self, &mut proven.iter().map(|&(a, b)| (b, a)));
+        match (forward, backward) {
+            (true, true) => Ordering4::Equal,
+            (true, false) => Ordering4::Supertype,
+            (false, true) => Ordering4::Subtype,
+            (false, false) => Ordering4::Incomparable,
+        }
+    }
+
+    /// 不会 panic 的 `refine`
+    pub fn try_refine(&self, v: &Self) -> Result<Self, ConstraintError> {
+        self.validate()?;
+        v.validate()?;
+        Ok(self.refine(v))
+    }
+
+    pub fn refine(&self, v: &Self) -> Self {
+        if self.super_of(v) {
+            return v.clone();
+        }
+        Constraint::bottom()
+    }
+
+    /// 由给定整数组成的整数集合，入口名为 `IntSet`
+    pub fn int_set(values: impl IntoIterator<Item = i32>) -> Constraint {
+        let entry = "IntSet".to_string();
+        let node = ConstraintNode::IntSet(values.into_iter().collect());
+        Constraint::from_graph(HashMap::from([(entry.clone(), node)]), entry)
+    }
+
+    /// 只含定义 `T := T` 的全集约束，与 `bottom` 一样所有调用共享同一张图
+    pub fn top() -> Self {
+        static TOP: OnceLock<Constraint> = OnceLock::new();
+        TOP.get_or_init(|| {
+            Constraint::from_graph(
+                HashMap::from([("T".to_string(), ConstraintNode::T)]),
+                "T".to_string(),
+            )
+        })
+        .clone()
+    }
+
+    /// 只含定义 `F := F` 的空约束；所有调用共享同一张图，不会重新分配，
+    /// 因此与克隆一样不能用 `add_node` 原地修改
+    pub fn bottom() -> Self {
+        static BOTTOM: OnceLock<Constraint> = OnceLock::new();
+        BOTTOM
+            .get_or_init(|| {
+                Constraint::from_graph(
+                    HashMap::from([("F".to_string(), ConstraintNode::F)]),
+                    "F".to_string(),
+                )
+            })
+            .clone()
+    }
+}
+
+const FINITE_FUEL: usize = 64; // super_of_finite 默认最多展开的层数
+const SOFT_STEP_THRESHOLD: usize = 100_000; // super_of_diagnostic 默认的软阈值
+const DISTRIBUTE_LIMIT: usize = 64; // 把右侧的 Pair 拆成并集时至多产生的成员数
+const DISTRIBUTE_DEPTH: usize = 2; // 拆分时至多嵌套展开的定义层数
+
+/// `Checker::union_members` 无法拆分的原因
+enum Distribution {
+    Cycle(usize), // 回到了正在展开的第 n 个定义
+    TooMany,
+    TooDeep,
+}
+
+/// 收集 `Diagnostics` 时的状态
+pub(crate) struct DiagnosticState {
+    report: Diagnostics,
+    threshold: usize,
+    depth: usize,
+    goals: Vec<String>, // 正在展开的定义判定
+}
+
+/// `super_of_bounded_mem` 使用的定义对判定结果缓存，超过容量时淘汰最久未使用的项
+///
+/// 假设只会使判定更容易成立，因此为真的结果在任何假设集下都可以直接使用，为假的结果
+/// 只在假设集同样为空时使用。在非空假设集下得到的真结果依赖于尚未证明的假设，先记为
+/// 待定：依赖的某个假设被否定时一并丢弃，最外层的判定成立时才写入缓存。待定的结果在
+/// 它依赖的假设仍然成立期间同样可以直接使用
+pub(crate) struct Memo {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<(DefId, DefId), (bool, u64)>, // 结果与最近一次使用的时刻
+    recency: BTreeMap<u64, (DefId, DefId)>,
+    pending: Vec<(DefId, DefId)>, // 依赖于当前假设集的真结果
+    marks: Vec<usize>,            // 每个假设加入时 pending 的长度
+}
+
+impl Memo {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Memo {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            pending: Vec::new(),
+            marks: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, key: (DefId, DefId)) -> Option<bool> {
+        if self.pending.contains(&key) {
+            return Some(true);
+        }
+        let (result, used) = self.entries.get_mut(&key)?;
+        self.recency.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.recency.insert(self.tick, key);
+        Some(*result)
+    }
+
+    fn insert(&mut self, key: (DefId, DefId), result: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.recency.remove(&used);
+        }
+        if self.entries.len() >= self.capacity
+            && let Some((_, oldest)) = self.recency.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.entries.insert(key, (result, self.tick));
+        self.recency.insert(self.tick, key);
+    }
+
+    /// 记下依赖于当前假设集的真结果，待定的结果同样不超过容量
+    fn defer(&mut self, key: (DefId, DefId)) {
+        if self.pending.len() < self.capacity {
+            self.pending.push(key);
+        }
+    }
+
+    /// 加入一个假设；与 `close` 配对
+    fn open(&mut self) {
+        self.marks.push(self.pending.len());
+    }
+
+    /// 撤销一个假设：假设不成立时丢弃之后记下的待定结果，最外层的假设成立时
+    /// 把所有待定的结果写入缓存
+    fn close(&mut self, result: bool, outermost: bool) {
+        let mark = self.marks.pop().unwrap_or_default();
+        if !result {
+            self.pending.truncate(mark);
+        } else if outermost {
+            for key in std::mem::take(&mut self.pending) {
+                self.insert(key, true);
+            }
+        }
+    }
+}
+
+/// 一次子类型判定 a >= b 的上下文，保存两侧的约束图、假设集以及判定选项
+pub(crate) struct Checker<'a> {
+    pub(crate) constraint_a: &'a Constraint,
+    pub(crate) constraint_b: &'a Constraint,
+    pub(crate) assumption: Assumptions,
+    pub(crate) coercions: Option<&'a CoercionTable>, // 允许的类别转换，None 表示纯粹的子类型判定
+    pub(crate) trace: Option<DerivationTrace>,       // 需要推导树时记录每一步判定
+    pub(crate) placeholders: Option<&'a HashSet<String>>, // a 侧待求解的占位定义
+    pub(crate) bindings: HashMap<String, ConstraintNode>, // 占位定义当前的解
+    pub(crate) resolutions: usize,                   // 解析 Def 的次数
+    pub(crate) name_lookups: Cell<usize>,            // 按名字查找定义的次数
+    pub(crate) fuel: Option<usize>, // 归纳模式下剩余的展开深度，None 表示余归纳模式
+    pub(crate) diagnostics: Option<DiagnosticState>, // 需要统计时记录步数与深度
+    pub(crate) int_tolerance: u32,  // 整数字面量之间允许的差，0 表示精确比较
+    pub(crate) string_match: StringMatch, // 字符串字面量之间的包含规则
+    pub(crate) structural: bool,    // 字面量、区间与整数集合按其类别比较
+    pub(crate) memo: Option<Memo>,  // 定义对的判定结果缓存，None 表示不缓存
+}
+
+impl<'a> Checker<'a> {
+    pub(crate) fn new(constraint_a: &'a Constraint, constraint_b: &'a Constraint) -> Self {
+        Checker {
+            constraint_a,
+            constraint_b,
+            assumption: Assumptions::default(),
+            coercions: None,
+            trace: None,
+            placeholders: None,
+            bindings: HashMap::new(),
+            resolutions: 0,
+            name_lookups: Cell::new(0),
+            fuel: None,
+            diagnostics: None,
+            int_tolerance: 0,
+            string_match: StringMatch::Exact,
+            structural: false,
+            memo: None,
+        }
+    }
+
+    /// 从两侧的入口开始判定；入口取自定义存储，其中的 Def 都已经预先解析
+    pub(crate) fn run(&mut self) -> bool {
+        let entry = |constraint: &'a Constraint| {
+            let arena = constraint.arena();
+            arena
+                .index_of(&constraint.entry)
+                .map(|index| arena.node(index))
+        };
+        self.name_lookups.set(self.name_lookups.get() + 2);
+        match entry(self.constraint_a) {
+            Some(node_a) => match entry(self.constraint_b) {
+                Some(node_b) => self.check_subsumption(node_a, node_b),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Def 节点引用的定义在 constraint 的定义存储中的下标：定义体中的 Def 直接取预先
+    /// 解析的下标，其余的 Def（入口之外构造出的节点）才按名字查找；不是 Def 时为 None
+    fn def_id(&self, constraint: &Constraint, node: &ConstraintNode) -> Option<DefId> {
+        let ConstraintNode::Def(name) = node else {
+            return None;
+        };
+        let arena = constraint.arena();
+        arena.target(node).or_else(|| {
+            self.name_lookups.set(self.name_lookups.get() + 1);
+            arena.index_of(name)
+        })
+    }
+
+    /// 通过约束的定义存储解析 Def 节点，找不到定义时 panic
+    fn resolve(&mut self, constraint: &'a Constraint, node: &ConstraintNode) -> &'a ConstraintNode {
+        self.resolutions += 1;
+        let index = self
+            .def_id(constraint, node)
+            .unwrap_or_else(|| panic!("Failed to find definition {:?} in constraint graph", node));
+        constraint.arena().node(index)
+    }
+
+    fn is_placeholder(&self, name: &str) -> bool {
+        self.placeholders
+            .is_some_and(|placeholders| placeholders.contains(name))
+    }
+
+    /// 为占位定义绑定 node；已有绑定时放宽为两者的并集，使先前的匹配依然成立
+    fn bind(&mut self, name: &str, node: &ConstraintNode) -> bool {
+        let bound = match self.bindings.remove(name) {
+            None => node.clone(),
+            Some(previous) if previous == *node => previous,
+            Some(ConstraintNode::Enum(mut nodes)) => {
+                if !nodes.contains(node) {
+                    nodes.push(node.clone());
+                }
+                ConstraintNode::Enum(nodes)
+            }
+            Some(previous) => ConstraintNode::Enum(vec![previous, node.clone()]),
+        };
+        self.bindings.insert(name.to_string(), bound);
+        true
+    }
+
+    /// 尝试一个可能失败的分支，失败时撤销该分支中产生的绑定
+    fn attempt(&mut self, node_a: &ConstraintNode, node_b: &ConstraintNode) -> bool {
+        if self.placeholders.is_none() {
+            return self.check_subsumption(node_a, node_b);
+        }
+        let snapshot = self.bindings.clone();
+        let result = self.check_subsumption(node_a, node_b);
+        if !result {
+            self.bindings = snapshot;
+        }
+        result
+    }
+
+    /// a >= b 在假设集中的键，两侧都不是已定义的 Def 时为 None
+    fn goal<'n>(&self, node_a: &'n ConstraintNode, node_b: &'n ConstraintNode) -> Option<Goal<'n>> {
+        match (
+            self.def_id(self.constraint_a, node_a),
+            self.def_id(self.constraint_b, node_b),
+        ) {
+            (Some(a), Some(b)) => Some(Goal::Defs(a, b)),
+            (Some(a), None) if !matches!(node_b, ConstraintNode::Def(_)) => {
+                Some(Goal::DefLeft(a, node_b))
+            }
+            (None, Some(b)) if !matches!(node_a, ConstraintNode::Def(_)) => {
+                Some(Goal::DefRight(node_a, b))
+            }
+            _ => None,
+        }
+    }
+
+    fn is_assumed(&self, node_a: &ConstraintNode, node_b: &ConstraintNode) -> bool {
+        self.goal(node_a, node_b)
+            .is_some_and(|goal| self.assumption.contains(goal))
+    }
+
+    /// 归纳模式下消耗一层展开深度，深度耗尽时返回 false；与 `retract` 配对
+    fn descend(&mut self) -> bool {
+        match self.fuel.as_mut() {
+            Some(0) => false,
+            Some(fuel) => {
+                *fuel -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// 把 a >= b 加入假设集，需要时在推导树中记录这一步
+    fn assume(&mut self, node_a: &ConstraintNode, node_b: &ConstraintNode) {
+        let (constraint_a, constraint_b) = (self.constraint_a, self.constraint_b);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.assume(constraint_a, constraint_b, node_a, node_b);
+        }
+        if let Some(state) = self.diagnostics.as_mut() {
+            state.goals.push(format!(
+                "{} >= {}",
+                NodeDisplay {
+                    constraint: constraint_a,
+                    node: node_a
+                },
+                NodeDisplay {
+                    constraint: constraint_b,
+                    node: node_b
+                }
+            ));
+        }
+        if let Some(goal) = self.goal(node_a, node_b) {
+            self.assumption.insert(goal);
+        }
+        if let Some(memo) = self.memo.as_mut() {
+            memo.open();
+        }
+    }
+
+    /// 撤销 assume 加入的假设，假设只在其自身的证明内部有效，见 `Assumptions`；
+    /// result 为这一步判定的结果，决定依赖这个假设的待定缓存是否保留
+    fn retract(&mut self, node_a: &ConstraintNode, node_b: &ConstraintNode, result: bool) {
+        if let Some(fuel) = self.fuel.as_mut() {
+            *fuel += 1;
+        }
+        if let Some(state) = self.diagnostics.as_mut() {
+            state.goals.pop();
+        }
+        if let Some(goal) = self.goal(node_a, node_b) {
+            self.assumption.remove(goal);
+        }
+        let outermost = self.assumption.is_empty();
+        if let Some(memo) = self.memo.as_mut() {
+            memo.close(result, outermost);
+        }
+    }
+
+    /// 字面量 a 能否接受字面量 b，整数在容差之内视为相同，字符串按 `string_match` 比较
+    fn leaf_accepts(&self, a: &AtomicConstraint, b: &AtomicConstraint) -> bool {
+        match (a, b) {
+            (AtomicConstraint::LiteralInt(a), AtomicConstraint::LiteralInt(b)) => {
+                (*a as i64 - *b as i64).unsigned_abs() <= self.int_tolerance as u64
+            }
+            (AtomicConstraint::LiteralString(a), AtomicConstraint::LiteralString(b)) => {
+                match self.string_match {
+                    StringMatch::Exact => a == b,
+                    StringMatch::Prefix => b.starts_with(a.as_str()),
+                    StringMatch::Glob => glob_matches(a, b),
+                }
+            }
+            _ => a == b,
+        }
+    }
+
+    /// 整数集合 a 包含整数 i，考虑整数容差
+    fn set_accepts(&self, a: &BTreeSet<i32>, i: i32) -> bool {
+        let tolerance = self.int_tolerance as i64;
+        let lo = (i as i64 - tolerance).max(i32::MIN as i64) as i32;
+        let hi = (i as i64 + tolerance).min(i32::MAX as i64) as i32;
+        a.range(lo..=hi).next().is_some()
+    }
+
+    /// 整数集合 a 包含 b：b 须是整数字面量、整数集合或有界的整数区间
+    fn includes_in_set(&self, a: &BTreeSet<i32>, b: &ConstraintNode) -> bool {
+        match b {
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)) => self.set_accepts(a, *i),
+            ConstraintNode::IntSet(b) => b.iter().all(|i| self.set_accepts(a, *i)),
+            ConstraintNode::Range { .. } => match simplify::int_interval(b) {
+                // 区间比集合大时不可能被包含（容差为 0 时），不必逐个检查
+                Some((lo, hi))
+                    if lo > i64::MIN
+                        && hi < i64::MAX
+                        && (self.int_tolerance > 0 || hi - lo < a.len() as i64) =>
+                {
+                    (lo..=hi).all(|i| self.set_accepts(a, i as i32))
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// 类别 from 能否视为类别 to
+    fn kind_accepts(&self, to: Kind, from: Kind) -> bool {
+        to == from
+            || self
+                .coercions
+                .is_some_and(|coercions| coercions.allows(from, to))
+    }
+
+    /// 右侧枚举的每个成员都要落到左侧的某个成员上；Pair 成员交给分配分支，使拆开后的
+    /// 各个 Pair 可以落到不同的成员上
+    ///
+    /// 单独成为一个函数，避免增大递归的 `check_subsumption_inner` 的栈帧
+    fn includes_enum(
+        &mut self,
+        a: &ConstraintNode,
+        a_nodes: &[ConstraintNode],
+        b_nodes: &[ConstraintNode],
+    ) -> bool {
+        b_nodes.iter().all(|b_node| {
+            if matches!(b_node.strip_annotations(), ConstraintNode::Pair(..)) {
+                self.check_subsumption(a, b_node)
+            } else {
+                a_nodes.iter().any(|a_node| self.attempt(a_node, b_node))
+            }
+        })
+    }
+
+    /// 枚举 a 包含 Pair b：b 能拆成多个成员时逐个判定，否则由 a 的某个成员包含 b
+    fn includes_distributed(&mut self, a: &ConstraintNode, b: &ConstraintNode) -> bool {
+        let members = match self.union_members(b, &mut Vec::new()) {
+            Ok(members) if members.len() > 1 => members,
+            _ => {
+                let ConstraintNode::Enum(a_nodes) = a else {
+                    unreachable!("distribution starts from an enum");
+                };
+                self.rule("enum-left");
+                return a_nodes.iter().any(|a_node| self.attempt(a_node, b));
+            }
+        };
+        members
+            .iter()
+            .all(|member| self.check_subsumption(a, member))
+    }
+
+    /// 把 b 侧的节点拆成并集的成员：展开枚举、b 侧的定义、至多 `DISTRIBUTE_LIMIT` 个
+    /// 整数的区间与整数集合，Pair 取两个分量成员的积
+    ///
+    /// 递归的定义保持为 Def 本身，使拆分总会终止；成员超过上限、或定义嵌套超过
+    /// `DISTRIBUTE_DEPTH` 层时返回 Err，不再沿着一长串定义逐层展开
+    fn union_members(
+        &mut self,
+        node: &ConstraintNode,
+        expanding: &mut Vec<String>,
+    ) -> Result<Vec<ConstraintNode>, Distribution> {
+        let int = |i: i64| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i as i32));
+        let members = match node.strip_annotations() {
+            ConstraintNode::Enum(nodes) => {
+                let mut members = Vec::new();
+                for node in nodes {
+                    members.extend(self.union_members(node, expanding)?);
+                    if members.len() > DISTRIBUTE_LIMIT {
+                        return Err(Distribution::TooMany);
+                    }
+                }
+                members
+            }
+            def @ ConstraintNode::Def(name) if self.def_id(self.constraint_b, def).is_some() => {
+                if let Some(position) = expanding.iter().position(|expanded| expanded == name) {
+                    return Err(Distribution::Cycle(position));
+                }
+                if expanding.len() >= DISTRIBUTE_DEPTH {
+                    return Err(Distribution::TooDeep);
+                }
+                expanding.push(name.clone());
+                let body = self.resolve(self.constraint_b, def);
+                let members = self.union_members(body, expanding);
+                expanding.pop();
+                match members {
+                    // 回到自身的定义不展开
+                    Err(Distribution::Cycle(position)) if position >= expanding.len() => {
+                        vec![node.clone()]
+                    }
+                    members => members?,
+                }
+            }
+            ConstraintNode::Range { .. } => match simplify::int_interval(node) {
+                Some((lo, hi)) if hi - lo < DISTRIBUTE_LIMIT as i64 => (lo..=hi).map(int).collect(),
+                _ => vec![node.clone()],
+            },
+            ConstraintNode::IntSet(set) if set.len() <= DISTRIBUTE_LIMIT => {
+                set.iter().map(|i| int(*i as i64)).collect()
+            }
+            ConstraintNode::Pair(left, right) => {
+                let lefts = self.union_members(left, expanding)?;
+                let rights = self.union_members(right, expanding)?;
+                if lefts.len() * rights.len() > DISTRIBUTE_LIMIT {
+                    return Err(Distribution::TooMany);
+                }
+                if lefts.len() == 1 && rights.len() == 1 {
+                    return Ok(vec![node.clone()]);
+                }
+                lefts
+                    .iter()
+                    .flat_map(|left| {
+                        rights.iter().map(move |right| {
+                            ConstraintNode::Pair(left.clone().into(), right.clone().into())
+                        })
+                    })
+                    .collect()
+            }
+            other => vec![other.clone()],
+        };
+        Ok(members)
+    }
+
+    /// 先把交分配到枚举的各个成员上，成员两两不相交时交集为空
+    ///
+    /// 单独成为一个函数，避免增大递归的 `check_subsumption_inner` 的栈帧
+    fn includes_intersection(&mut self, a: &ConstraintNode, b_nodes: &[ConstraintNode]) -> bool {
+        if let Some(merged) = intersect_ranges(b_nodes) {
+            return match <[ConstraintNode; 1]>::try_from(merged) {
+                Ok([node]) => self.check_subsumption(a, &node),
+                Err(merged) => self.check_subsumption(a, &ConstraintNode::And(merged)),
+            };
+        }
+        if let Some(index) = b_nodes.iter().position(is_enum) {
+            for member in enum_members(&b_nodes[index]) {
+                let mut distributed = b_nodes.to_vec();
+                distributed[index] = member.clone();
+                if !self.check_subsumption(a, &ConstraintNode::And(distributed)) {
+                    return false;
+                }
+            }
+            return true;
+        }
+        for b_node in b_nodes {
+            if self.attempt(a, b_node) {
+                return true;
+            }
+        }
+        let mut disjoint = DisjointChecker::new(self.constraint_b, self.constraint_b);
+        b_nodes
+            .iter()
+            .enumerate()
+            .any(|(i, x)| b_nodes[i + 1..].iter().any(|y| disjoint.check(x, y)))
+    }
+
+    /// 记录之间的包含：b 的字段都要在 a 中声明且类型被包含；b 的值可能缺少的字段
+    /// （b 中有默认值或未声明的字段）在 a 中必须有默认值，并且默认值被 a 的字段类型包含
+    fn includes_record(
+        &mut self,
+        a_fields: &[(String, RecordField)],
+        b_fields: &[(String, RecordField)],
+    ) -> bool {
+        for (name, b_field) in b_fields {
+            let Some((_, a_field)) = a_fields.iter().find(|(a_name, _)| a_name == name) else {
+                return false;
+            };
+            if !self.check_subsumption(&a_field.ty, &b_field.ty) {
+                return false;
+            }
+            if b_field.default.is_some() && !self.default_fits(a_field) {
+                return false;
+            }
+        }
+        a_fields.iter().all(|(name, a_field)| {
+            b_fields.iter().any(|(b_name, _)| b_name == name) || self.default_fits(a_field)
+        })
+    }
+
+    /// 展开两侧的定义并在假设 a >= b 之下比较定义体
+    fn includes_defs(&mut self, node_a: &ConstraintNode, node_b: &ConstraintNode) -> bool {
+        // 这种情况下，显然假设集中不包含，那么我们在假设集中加入假设
+        trace!("Assuming {:?} >= {:?}", node_a, node_b);
+        if !self.descend() {
+            return false;
         }
+        self.assume(node_a, node_b);
+        // 然后解包
+        let a = self.resolve(self.constraint_a, node_a);
+        let b = self.resolve(self.constraint_b, node_b);
+        let result = self.check_subsumption(a, b);
+        self.retract(node_a, node_b, result);
+        result
     }
 
-    pub fn refine(&self, v: &Self) -> Self {
-        if self.super_of(v) {
-            return v.clone();
+    /// 先查缓存的定义对判定，未命中时展开；空假设集下得到的结果直接写入缓存，
+    /// 非空假设集下得到的真结果先记为待定，见 `Memo`
+    fn includes_memoized(&mut self, node_a: &ConstraintNode, node_b: &ConstraintNode) -> bool {
+        let Some(Goal::Defs(id_a, id_b)) = self.goal(node_a, node_b) else {
+            return self.includes_defs(node_a, node_b);
+        };
+        let independent = self.assumption.is_empty();
+        let cached = self.memo.as_mut().and_then(|memo| memo.get((id_a, id_b)));
+        match cached {
+            Some(true) => return true,
+            Some(false) if independent => return false,
+            _ => {}
         }
-        let mut graph = HashMap::new();
-        graph.insert("F".to_string(), ConstraintNode::F);
-        Constraint {
-            graph: Arc::new(graph),
-            entry: "F".to_string(),
+        let result = self.includes_defs(node_a, node_b);
+        if let Some(memo) = &mut self.memo {
+            if independent {
+                memo.insert((id_a, id_b), result);
+            } else if result {
+                memo.defer((id_a, id_b));
+            }
+        }
+        result
+    }
+
+    /// 记录推导树时，把当前判定使用的规则名写入推导树
+    fn rule(&mut self, rule: &'static str) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.rule(rule);
         }
     }
 
-    fn check_subsumption(
-        constraint_a: &Self,
-        constraint_b: &Self,
+    /// 结构模式下两侧的字面量先替换为类别再比较，与 `erase_literals` 之后再比较相同
+    fn includes_erased(&mut self, a: &ConstraintNode, b: &ConstraintNode) -> bool {
+        let erased_a = erased_literal(a);
+        let erased_b = erased_literal(b);
+        self.check_subsumption_inner(
+            erased_a.as_ref().unwrap_or(a),
+            erased_b.as_ref().unwrap_or(b),
+        )
+    }
+
+    /// a 侧的字段有默认值，且默认值被字段类型包含；两者都在 a 的图中，另起一次判定
+    fn default_fits(&self, field: &RecordField) -> bool {
+        let Some(default) = &field.default else {
+            return false;
+        };
+        let mut checker = Checker::new(self.constraint_a, self.constraint_a);
+        checker.coercions = self.coercions;
+        checker.int_tolerance = self.int_tolerance;
+        checker.string_match = self.string_match;
+        checker.structural = self.structural;
+        checker.check_subsumption(&field.ty, default)
+    }
+
+    pub(crate) fn check_subsumption(
+        &mut self,
         node_a: &ConstraintNode,
         node_b: &ConstraintNode,
-        assumption: &mut HashSet<(ConstraintNode, ConstraintNode)>,
     ) -> bool {
-        println!("Checking {:?} >= {:?}", node_a, node_b);
-        let result = Constraint::check_subsumption_inner(
-            constraint_a,
-            constraint_b,
-            node_a,
-            node_b,
-            assumption,
-        );
-        println!(
+        trace!("Checking {:?} >= {:?}", node_a, node_b);
+        if self.trace.is_some() {
+            let assumed = self.is_assumed(node_a.strip_annotations(), node_b.strip_annotations());
+            let (constraint_a, constraint_b) = (self.constraint_a, self.constraint_b);
+            if let Some(trace) = self.trace.as_mut() {
+                trace.enter(constraint_a, constraint_b, node_a, node_b, assumed);
+            }
+        }
+        if let Some(state) = self.diagnostics.as_mut() {
+            state.report.steps += 1;
+            state.depth += 1;
+            state.report.max_depth = state.report.max_depth.max(state.depth);
+            // 超过软阈值时只记录一次当前的定义判定栈，判定照常进行
+            if state.report.steps > state.threshold && !state.report.threshold_hit {
+                state.report.threshold_hit = true;
+                state.report.goal_stack = state.goals.clone();
+                trace!(
+                    "Step threshold {} exceeded, expanding: {:?}",
+                    state.threshold, state.goals
+                );
+            }
+        }
+        let result = self.check_subsumption_inner(node_a, node_b);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.exit(result);
+        }
+        if let Some(state) = self.diagnostics.as_mut() {
+            state.depth -= 1;
+        }
+        trace!(
             "Result of subsumption check: {:?} >= {:?} is {:?}",
             node_a, node_b, result
         );
@@ -159,36 +2212,126 @@ impl Constraint {
     }
 
     /// a >= b
+    ///
+    /// 分支按顺序匹配，顺序本身是判定的一部分：T 在左与 F（以及空区间、空的带标签枚举）
+    /// 在右总是成立，最先处理；F 在左与 T 在右的否定分支必须排在 Enum 分支之后，
+    /// 否则空枚举与含 T 的枚举会被误判。每个分支用 `rule` 记下推导树中的规则名
     fn check_subsumption_inner(
-        constraint_a: &Self,
-        constraint_b: &Self,
+        &mut self,
         node_a: &ConstraintNode,
         node_b: &ConstraintNode,
-        assumption: &mut HashSet<(ConstraintNode, ConstraintNode)>,
     ) -> bool {
-        if assumption.contains(&(node_a.clone(), node_b.clone())) {
-            return true;
+        // 注释对子类型判定是透明的
+        let node_a = node_a.strip_annotations();
+        let node_b = node_b.strip_annotations();
+
+        if self.is_assumed(node_a, node_b) {
+            // 归纳模式下再次遇到同一个判定意味着需要无穷的展开，视为失败
+            return self.fuel.is_none();
+        }
+
+        if self.structural && (is_erasable(node_a) || is_erasable(node_b)) {
+            return self.includes_erased(node_a, node_b);
         }
 
         match (node_a, node_b) {
-            (ConstraintNode::T, _) => true,
-            (_, ConstraintNode::F) => true,
-            (ConstraintNode::F, _) => false,
-            (_, ConstraintNode::T) => false,
-            (ConstraintNode::Leaf(a_lit), ConstraintNode::Leaf(b_lit)) => a_lit == b_lit,
+            (ConstraintNode::T, _) => {
+                self.rule("top");
+                true
+            }
+            (_, ConstraintNode::F) => {
+                self.rule("bottom");
+                true
+            }
+            // 空区间与 F 等价
+            (_, ConstraintNode::Range { lo, hi }) if range::is_empty(lo, hi) => {
+                self.rule("empty-range");
+                true
+            }
+            (_, ConstraintNode::TaggedEnum(members)) if members.is_empty() => {
+                self.rule("empty-tagged");
+                true
+            }
+            (ConstraintNode::Def(name), b) if self.is_placeholder(name) => {
+                self.rule("placeholder");
+                self.bind(name, b)
+            }
+            // 交集被 b 包含当且仅当每个成员都包含 b
+            (ConstraintNode::And(a_nodes), b) => {
+                self.rule("and-left");
+                for a_node in a_nodes {
+                    if !self.check_subsumption(a_node, b) {
+                        return false;
+                    }
+                }
+                true
+            }
+            (a, ConstraintNode::And(b_nodes)) => {
+                self.rule("and-right");
+                self.includes_intersection(a, b_nodes)
+            }
+            (ConstraintNode::Leaf(a_lit), ConstraintNode::Leaf(b_lit)) => {
+                self.rule("leaf");
+                self.leaf_accepts(a_lit, b_lit)
+            }
+            (ConstraintNode::Kind(a_kind), ConstraintNode::Kind(b_kind)) => {
+                self.rule("kind");
+                self.kind_accepts(*a_kind, *b_kind)
+            }
+            (ConstraintNode::Kind(a_kind), ConstraintNode::Leaf(b_lit)) => {
+                self.rule("kind-leaf");
+                b_lit
+                    .kind()
+                    .is_some_and(|b_kind| self.kind_accepts(*a_kind, b_kind))
+            }
+            (ConstraintNode::Kind(a_kind), ConstraintNode::Range { lo, hi }) => {
+                self.rule("kind-range");
+                range::kind(lo, hi).is_some_and(|b_kind| self.kind_accepts(*a_kind, b_kind))
+            }
+            (ConstraintNode::Range { lo, hi }, ConstraintNode::Kind(_)) => {
+                self.rule("range-kind");
+                *lo == Bound::Unbounded && *hi == Bound::Unbounded
+            }
+            (ConstraintNode::Range { lo, hi }, ConstraintNode::Leaf(b_lit)) => {
+                self.rule("range-leaf");
+                range::contains(lo, hi, b_lit)
+            }
+            (
+                ConstraintNode::Range { lo: a_lo, hi: a_hi },
+                ConstraintNode::Range { lo: b_lo, hi: b_hi },
+            ) => {
+                self.rule("range");
+                range::subsumes(a_lo, a_hi, b_lo, b_hi)
+            }
+            (ConstraintNode::Leaf(a_lit), ConstraintNode::Range { lo, hi }) => {
+                self.rule("leaf-range");
+                range::singleton(lo, hi).is_some_and(|b_lit| self.leaf_accepts(a_lit, &b_lit))
+            }
+            (
+                ConstraintNode::IntSet(a_set),
+                ConstraintNode::Leaf(_) | ConstraintNode::IntSet(_) | ConstraintNode::Range { .. },
+            ) => {
+                self.rule("int-set");
+                self.includes_in_set(a_set, node_b)
+            }
+            // 其他节点逐个检查集合中的整数，定义先展开
+            (a, ConstraintNode::IntSet(b_set)) if !matches!(a, ConstraintNode::Def(_)) => {
+                self.rule("int-set-right");
+                b_set.iter().all(|i| {
+                    self.check_subsumption(
+                        a,
+                        &ConstraintNode::Leaf(AtomicConstraint::LiteralInt(*i)),
+                    )
+                })
+            }
 
-            // 这一行是用来避免歧义的
-            (ConstraintNode::Enum(a_nodes), ConstraintNode::Enum(b_nodes)) => {
-                for b_node in b_nodes {
+            // 带标签的成员只与同名标签比较载荷，不同标签的成员互不包含
+            (ConstraintNode::TaggedEnum(a_members), ConstraintNode::TaggedEnum(b_members)) => {
+                self.rule("tagged");
+                for (b_tag, b_payload) in b_members {
                     let mut subsumed = false;
-                    for a_node in a_nodes {
-                        if Constraint::check_subsumption(
-                            constraint_a,
-                            constraint_b,
-                            a_node,
-                            b_node,
-                            assumption,
-                        ) {
+                    for (_, a_payload) in a_members.iter().filter(|(a_tag, _)| a_tag == b_tag) {
+                        if self.attempt(a_payload, b_payload) {
                             subsumed = true;
                             break;
                         }
@@ -199,106 +2342,289 @@ impl Constraint {
                 }
                 true
             }
+            // 左侧为普通 Enum 时将右侧逐个标签拆开，使不同标签可以落到不同的成员上
+            (ConstraintNode::Enum(_), ConstraintNode::TaggedEnum(b_members))
+                if b_members.len() > 1 =>
+            {
+                self.rule("enum-tagged");
+                for member in b_members {
+                    let single = ConstraintNode::TaggedEnum(vec![member.clone()]);
+                    if !self.check_subsumption(node_a, &single) {
+                        return false;
+                    }
+                }
+                true
+            }
+
+            // 这一行是用来避免歧义的
+            (ConstraintNode::Enum(a_nodes), ConstraintNode::Enum(b_nodes)) => {
+                self.rule("enum");
+                self.includes_enum(node_a, a_nodes, b_nodes)
+            }
             (a, ConstraintNode::Enum(b_nodes)) => {
+                self.rule("enum-right");
                 for b_node in b_nodes {
-                    if !Constraint::check_subsumption(
-                        constraint_a,
-                        constraint_b,
-                        a,
-                        b_node,
-                        assumption,
-                    ) {
+                    if !self.check_subsumption(a, b_node) {
                         return false;
                     }
                 }
                 true
             }
+            // Pair(a1 | a2, b) 等于 Pair(a1, b) | Pair(a2, b)：右侧的 Pair 能拆成多个成员时
+            // 先展开，使展开后的每个 Pair 可以落到左侧枚举的不同成员上
+            (ConstraintNode::Enum(_), ConstraintNode::Pair(..)) => {
+                self.rule("pair-distribute");
+                self.includes_distributed(node_a, node_b)
+            }
             (ConstraintNode::Enum(a_nodes), b) => {
+                self.rule("enum-left");
                 for a_node in a_nodes {
-                    if Constraint::check_subsumption(
-                        constraint_a,
-                        constraint_b,
-                        a_node,
-                        b,
-                        assumption,
-                    ) {
+                    if self.attempt(a_node, b) {
                         return true;
                     }
                 }
                 false
             }
-
+            // Pair(T, T) 包含任意 Pair，无需比较两个分量
+            (ConstraintNode::Pair(..), ConstraintNode::Pair(..)) if is_any_pair(node_a) => {
+                self.rule("any-pair");
+                true
+            }
             (ConstraintNode::Pair(a_left, a_right), ConstraintNode::Pair(b_left, b_right)) => {
-                if Constraint::check_subsumption(
-                    constraint_a,
-                    constraint_b,
-                    a_left,
-                    b_left,
-                    assumption,
-                ) && Constraint::check_subsumption(
-                    constraint_a,
-                    constraint_b,
-                    a_right,
-                    b_right,
-                    assumption,
-                ) {
+                self.rule("pair");
+                if self.check_subsumption(a_left, b_left)
+                    && self.check_subsumption(a_right, b_right)
+                {
                     return true;
                 }
                 false
             }
+            (ConstraintNode::Record(a_fields), ConstraintNode::Record(b_fields)) => {
+                self.rule("record");
+                self.includes_record(a_fields, b_fields)
+            }
+            // Pair 与原子值（Nil、字面量、类别、区间）互不相交：Nil 只匹配 Nil，Pair 只匹配
+            // Pair，列表的两个分支因此不会互相吸收。以后加入的转换也不会跨越这一边界
+            (
+                ConstraintNode::Pair(..),
+                ConstraintNode::Leaf(_) | ConstraintNode::Kind(_) | ConstraintNode::Range { .. },
+            ) => {
+                self.rule("pair-atom");
+                false
+            }
+            (
+                ConstraintNode::Leaf(_) | ConstraintNode::Kind(_) | ConstraintNode::Range { .. },
+                ConstraintNode::Pair(..),
+            ) => {
+                self.rule("atom-pair");
+                false
+            }
+            // 自定义节点之间交给用户实现判定；与内置节点之间除 Def 展开外均不成立
+            (ConstraintNode::Custom(a), ConstraintNode::Custom(b)) => {
+                self.rule("custom");
+                a.subsumes(b.as_ref()).unwrap_or(false)
+            }
+            // 谓词是不透明的，只包含同一个谓词
+            (ConstraintNode::Predicate(a), ConstraintNode::Predicate(b)) => {
+                self.rule("predicate");
+                a == b && self.constraint_a.same_predicate(self.constraint_b, *a)
+            }
 
+            // 两侧共享同一张图时，同名定义显然互相包含，无需展开；记录推导树时仍然展开，
+            // 使推导树完整
+            (ConstraintNode::Def(a), ConstraintNode::Def(b))
+                if a == b
+                    && self.trace.is_none()
+                    && Arc::ptr_eq(&self.constraint_a.graph, &self.constraint_b.graph) =>
+            {
+                self.rule("def");
+                true
+            }
+            (ConstraintNode::Def(_), ConstraintNode::Def(_)) if self.memo.is_some() => {
+                self.rule("def");
+                self.includes_memoized(node_a, node_b)
+            }
             // 这一行是用来避免歧义的
-            (ConstraintNode::Def(a), ConstraintNode::Def(b)) => {
-                // 这种情况下，显然假设集中不包含，那么我们在假设集中加入假设
-                println!("Assuming {} >= {}", a, b);
-                assumption.insert((node_a.clone(), node_b.clone()));
-                // 然后解包
-                let a = constraint_a
-                    .get_node(a)
-                    .expect("Failed to find definition in constraint_a");
-                let b = constraint_b
-                    .get_node(b)
-                    .expect("Failed to find definition in constraint_b");
-                let result =
-                    Constraint::check_subsumption(constraint_a, constraint_b, a, b, assumption);
-                assumption.remove(&(node_a.clone(), node_b.clone()));
-                result
+            (ConstraintNode::Def(_), ConstraintNode::Def(_)) => {
+                self.rule("def");
+                self.includes_defs(node_a, node_b)
             }
-            (ConstraintNode::Def(a), b) => {
+            (ConstraintNode::Def(_), b) => {
+                self.rule("def-left");
                 // 这种情况下，显然假设集中不包含，那么我们在假设集中加入假设
-                println!("Assuming {} >= {:?}", a, b);
-                assumption.insert((node_a.clone(), b.clone()));
+                trace!("Assuming {:?} >= {:?}", node_a, b);
+                if !self.descend() {
+                    return false;
+                }
+                self.assume(node_a, b);
                 // 然后解包
-                let a = constraint_a
-                    .get_node(a)
-                    .expect("Failed to find definition in constraint_a");
-                let result =
-                    Constraint::check_subsumption(constraint_a, constraint_b, a, b, assumption);
-                assumption.remove(&(node_a.clone(), b.clone()));
+                let a = self.resolve(self.constraint_a, node_a);
+                let result = self.check_subsumption(a, b);
+                self.retract(node_a, b, result);
                 result
             }
-            (a, ConstraintNode::Def(b)) => {
+            (a, ConstraintNode::Def(_)) => {
+                self.rule("def-right");
                 // 这种情况下，显然假设集中不包含，那么我们在假设集中加入假设
-                println!("Assuming {:?} >= {}", a, b);
-                assumption.insert((a.clone(), node_b.clone()));
+                trace!("Assuming {:?} >= {:?}", a, node_b);
+                if !self.descend() {
+                    return false;
+                }
+                self.assume(a, node_b);
                 // 然后解包
-                let b = constraint_b
-                    .get_node(b)
-                    .expect("Failed to find definition in constraint_b");
-                let result =
-                    Constraint::check_subsumption(constraint_a, constraint_b, a, b, assumption);
-                assumption.remove(&(a.clone(), node_b.clone()));
+                let b = self.resolve(self.constraint_b, node_b);
+                let result = self.check_subsumption(a, b);
+                self.retract(a, node_b, result);
                 result
             }
-            _ => false,
+            // F 在左或 T 在右时只有上面的分支可能成立：F >= Enum[]（空枚举等价于 F）、
+            // Enum[T] >= T，以及展开 Def 之后的情形，例如 F >= (Y := Enum[])、(X := T) >= T
+            _ => {
+                self.rule("mismatch");
+                false
+            }
+        }
+    }
+}
+
+pub(crate) fn is_enum(node: &ConstraintNode) -> bool {
+    matches!(node.strip_annotations(), ConstraintNode::Enum(_))
+}
+
+/// 整数集合按连续的段拆成字面量与闭区间，至少三个整数的段使用区间
+pub(crate) fn int_set_parts(set: &BTreeSet<i32>) -> Vec<ConstraintNode> {
+    let mut runs: Vec<(i32, i32)> = Vec::new();
+    for &i in set {
+        match runs.last_mut() {
+            Some((_, hi)) if *hi as i64 + 1 == i as i64 => *hi = i,
+            _ => runs.push((i, i)),
+        }
+    }
+    let mut parts = Vec::new();
+    for (lo, hi) in runs {
+        if hi as i64 - lo as i64 >= 2 {
+            parts.push(ConstraintNode::int_range(lo, hi));
+        } else {
+            parts.extend((lo..=hi).map(|i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i))));
+        }
+    }
+    parts
+}
+
+fn is_erasable(node: &ConstraintNode) -> bool {
+    match node {
+        ConstraintNode::Leaf(atom) => atom.kind().is_some(),
+        ConstraintNode::Range { lo, hi } => range::kind(lo, hi).is_some(),
+        ConstraintNode::IntSet(set) => !set.is_empty(),
+        _ => false,
+    }
+}
+
+/// 字面量、区间或非空整数集合所属的类别节点；Nil、无法确定类别的区间与其他节点返回 None
+fn erased_literal(node: &ConstraintNode) -> Option<ConstraintNode> {
+    match node {
+        ConstraintNode::Leaf(atom) => atom.kind().map(ConstraintNode::Kind),
+        ConstraintNode::Range { lo, hi } => range::kind(lo, hi).map(ConstraintNode::Kind),
+        ConstraintNode::IntSet(set) if !set.is_empty() => Some(ConstraintNode::Kind(Kind::Int)),
+        _ => None,
+    }
+}
+
+/// 两个分量都是 T 的 Pair，即全体 Pair
+pub(crate) fn is_any_pair(node: &ConstraintNode) -> bool {
+    match node.strip_annotations() {
+        ConstraintNode::Pair(left, right) => {
+            matches!(left.strip_annotations(), ConstraintNode::T)
+                && matches!(right.strip_annotations(), ConstraintNode::T)
+        }
+        _ => false,
+    }
+}
+
+/// 已经展开过的定义，作为交集的成员时不再展开，视为没有额外的限制
+pub(crate) fn is_expanded(node: &ConstraintNode, expanded: &HashSet<&str>) -> bool {
+    matches!(node.strip_annotations(), ConstraintNode::Def(name) if expanded.contains(name.as_str()))
+}
+
+/// 交集中有两个以上的区间成员时，把它们合并为一个区间，单点区间改为字面量，
+/// 原子类别不同的区间相交为 F；不需要合并时返回 None
+fn intersect_ranges(nodes: &[ConstraintNode]) -> Option<Vec<ConstraintNode>> {
+    let is_range =
+        |node: &ConstraintNode| matches!(node.strip_annotations(), ConstraintNode::Range { .. });
+    if nodes.iter().filter(|node| is_range(node)).count() < 2 {
+        return None;
+    }
+    let mut merged: Option<(Bound, Bound)> = Some((Bound::Unbounded, Bound::Unbounded));
+    let mut rest = Vec::new();
+    for node in nodes {
+        match node.strip_annotations() {
+            ConstraintNode::Range { lo, hi } => {
+                merged = merged.and_then(|(m_lo, m_hi)| range::intersection(&m_lo, &m_hi, lo, hi));
+            }
+            _ => rest.push(node.clone()),
+        }
+    }
+    let range = match merged {
+        Some((lo, hi)) if range::is_empty(&lo, &hi) => ConstraintNode::F,
+        Some((lo, hi)) => match range::singleton(&lo, &hi) {
+            Some(atom) => ConstraintNode::Leaf(atom),
+            None => ConstraintNode::Range { lo, hi },
+        },
+        None => ConstraintNode::F,
+    };
+    rest.push(range);
+    Some(rest)
+}
+
+/// 枚举的成员，其他节点视为只有自身一个成员
+fn enum_members(node: &ConstraintNode) -> &[ConstraintNode] {
+    match node.strip_annotations() {
+        ConstraintNode::Enum(members) => members,
+        other => std::slice::from_ref(other),
+    }
+}
+
+/// 带引号的字符串字面量，转义引号、反斜杠与控制字符，可被解析器原样读回
+pub(crate) fn escape_string(s: &str) -> String {
+    escape_quoted(s, '"')
+}
+
+/// 带单引号的字符字面量，转义规则与字符串相同
+pub(crate) fn escape_char(c: char) -> String {
+    escape_quoted(c.encode_utf8(&mut [0; 4]), '\'')
+}
+
+fn escape_quoted(s: &str, quote: char) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push(quote);
+    for c in s.chars() {
+        match c {
+            c if c == quote => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
         }
     }
+    escaped.push(quote);
+    escaped
 }
 
 struct PrettyFormatter<'a> {
     constraint: &'a Constraint,
     indent_level: usize,
     visited_defs: HashSet<String>, // 用于防止递归定义的无限循环
+    compact: bool,                 // 紧凑模式：单行输出，定义只打印名字
+    sorted: bool,                  // 枚举成员按其紧凑输出排序，使输出与成员顺序无关
+    debug: bool,                   // 调试模式：标注定义展开的深度与截断循环的位置
+    expanding: Vec<String>,        // 正在展开的定义，从外到内
+    registry: Option<&'a FormatterRegistry>, // 按节点类别注册的格式化函数
+    max_enum_members: Option<usize>, // 枚举至多输出的成员数，其余成员只输出个数
 }
 
 impl<'a> PrettyFormatter<'a> {
@@ -307,17 +2633,84 @@ impl<'a> PrettyFormatter<'a> {
             constraint,
             indent_level: 0,
             visited_defs: HashSet::new(),
+            compact: false,
+            sorted: false,
+            debug: false,
+            expanding: Vec::new(),
+            registry: None,
+            max_enum_members: None,
+        }
+    }
+
+    fn sort_key(&self, node: &ConstraintNode) -> String {
+        SortKey {
+            constraint: self.constraint,
+            node,
+        }
+        .to_string()
+    }
+
+    /// 排序模式下成员按 key 排序，否则保持原有顺序
+    fn ordered<'n, T>(&self, members: &'n [T], key: impl Fn(&T) -> String) -> Vec<&'n T> {
+        let mut ordered: Vec<&T> = members.iter().collect();
+        if self.sorted {
+            ordered.sort_by_cached_key(|member| key(member));
+        }
+        ordered
+    }
+
+    fn format_atom(&self, f: &mut fmt::Formatter<'_>, atomic: &AtomicConstraint) -> fmt::Result {
+        if let Some(text) = self
+            .registry
+            .and_then(|registry| registry.format(&ConstraintNode::Leaf(atomic.clone())))
+        {
+            return write!(f, "{}", text);
+        }
+        match atomic {
+            AtomicConstraint::Nil => write!(f, "Nil"),
+            AtomicConstraint::LiteralInt(i) => write!(f, "{}", i),
+            AtomicConstraint::LiteralString(s) => write!(f, "{}", escape_string(s)),
+            AtomicConstraint::LiteralChar(c) => write!(f, "{}", escape_char(*c)),
+            // Debug 形式总带有小数点或指数，解析时不会与整数混淆；无穷写成溢出的指数，
+            // 解析后同样得到无穷
+            AtomicConstraint::LiteralFloat(x) if x.is_nan() => write!(f, "NaN"),
+            AtomicConstraint::LiteralFloat(x) if x.is_infinite() => {
+                write!(f, "{}1e999", if *x < 0.0 { "-" } else { "" })
+            }
+            AtomicConstraint::LiteralFloat(x) => write!(f, "{:?}", x),
         }
     }
 
     fn format_node(&mut self, f: &mut fmt::Formatter<'_>, node: &ConstraintNode) -> fmt::Result {
+        if !matches!(node, ConstraintNode::Leaf(_))
+            && let Some(text) = self.registry.and_then(|registry| registry.format(node))
+        {
+            return write!(f, "{}", text);
+        }
         match node {
             ConstraintNode::T => write!(f, "T"),
             ConstraintNode::F => write!(f, "F"),
-            ConstraintNode::Leaf(atomic) => match atomic {
-                AtomicConstraint::Nil => write!(f, "Nil"),
-                AtomicConstraint::LiteralInt(i) => write!(f, "{}", i),
-            },
+            ConstraintNode::Leaf(atomic) => self.format_atom(f, atomic),
+            ConstraintNode::Kind(kind) => write!(f, "{}", kind),
+            ConstraintNode::Range { lo, hi } => {
+                match lo {
+                    Bound::Inclusive(atom) => self.format_atom(f, atom)?,
+                    Bound::Exclusive(atom) => {
+                        self.format_atom(f, atom)?;
+                        write!(f, "<")?;
+                    }
+                    Bound::Unbounded => {}
+                }
+                write!(f, "..")?;
+                match hi {
+                    Bound::Inclusive(atom) => {
+                        write!(f, "=")?;
+                        self.format_atom(f, atom)
+                    }
+                    Bound::Exclusive(atom) => self.format_atom(f, atom),
+                    Bound::Unbounded => Ok(()),
+                }
+            }
             ConstraintNode::Pair(left, right) => {
                 write!(f, "(")?;
                 self.format_node(f, left)?;
@@ -325,22 +2718,63 @@ impl<'a> PrettyFormatter<'a> {
                 self.format_node(f, right)?;
                 write!(f, ")")
             }
+            ConstraintNode::And(members) => {
+                if members.is_empty() {
+                    return write!(f, "T");
+                }
+                let members = self.ordered(members, |member| self.sort_key(member));
+                for (i, member) in members.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " & ")?;
+                    }
+                    // `&` 的优先级高于 `|`，枚举成员与多段的整数集合需要加括号
+                    let compact = self.compact;
+                    let parenthesized = is_enum(member)
+                        || matches!(member.strip_annotations(), ConstraintNode::IntSet(set) if int_set_parts(set).len() > 1);
+                    if parenthesized {
+                        self.compact = true;
+                        write!(f, "(")?;
+                    }
+                    let result = self.format_node(f, member);
+                    if parenthesized {
+                        self.compact = compact;
+                        write!(f, ")")?;
+                    }
+                    result?;
+                }
+                Ok(())
+            }
             ConstraintNode::Enum(variants) => {
                 if variants.is_empty() {
                     return write!(f, "Never"); // 类似于 Rust 的 `!` 类型
                 }
 
+                let mut variants = self.ordered(variants, |v| self.sort_key(v));
+                // 超出上限的成员只输出个数，作为最后一个成员
+                let hidden = self
+                    .max_enum_members
+                    .map_or(0, |max| variants.len().saturating_sub(max));
+                variants.truncate(variants.len() - hidden);
+                let more = format!("... ({} more)", hidden);
+
                 // 如果 Enum 很简单，可以放在一行
                 let is_simple = variants
                     .iter()
                     .all(|v| matches!(v, ConstraintNode::Leaf(_)));
 
-                if is_simple && variants.len() <= 3 {
-                    self.format_node(f, &variants[0])?;
-                    for variant in variants.iter().skip(1) {
-                        write!(f, " | ")?;
+                if self.compact || (is_simple && variants.len() <= 3) {
+                    for (i, variant) in variants.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, " | ")?;
+                        }
                         self.format_node(f, variant)?;
                     }
+                    if hidden > 0 {
+                        if !variants.is_empty() {
+                            write!(f, " | ")?;
+                        }
+                        write!(f, "{}", more)?;
+                    }
                     Ok(())
                 } else {
                     // 复杂 Enum，换行并缩进
@@ -353,29 +2787,353 @@ impl<'a> PrettyFormatter<'a> {
                         write!(f, "{}", "  ".repeat(self.indent_level))?;
                         self.format_node(f, variant)?;
                     }
+                    if hidden > 0 {
+                        if !variants.is_empty() {
+                            write!(f, " |")?;
+                        }
+                        writeln!(f)?;
+                        write!(f, "{}{}", "  ".repeat(self.indent_level), more)?;
+                    }
                     self.indent_level -= 1;
                     writeln!(f)?;
                     write!(f, "{}", "  ".repeat(self.indent_level))
                 }
             }
+            // 按连续的段输出，解析后得到语义相同的枚举
+            ConstraintNode::IntSet(set) => {
+                let parts = int_set_parts(set);
+                if parts.is_empty() {
+                    return write!(f, "Never");
+                }
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    self.format_node(f, part)?;
+                }
+                Ok(())
+            }
+            ConstraintNode::TaggedEnum(members) => {
+                if members.is_empty() {
+                    return write!(f, "Never");
+                }
+                let members = self.ordered(members, |(tag, payload)| {
+                    format!("#{}({})", tag, self.sort_key(payload))
+                });
+                for (i, (tag, payload)) in members.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "#{}(", tag)?;
+                    self.format_node(f, payload)?;
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            ConstraintNode::Record(fields) => {
+                let fields = self.ordered(fields, |(name, _)| name.clone());
+                write!(f, "{{")?;
+                for (i, (name, field)) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", name)?;
+                    self.format_node(f, &field.ty)?;
+                    if let Some(default) = &field.default {
+                        write!(f, " = ")?;
+                        self.format_node(f, default)?;
+                    }
+                }
+                write!(f, "}}")
+            }
             ConstraintNode::Def(name) => {
+                // 调试模式下标出在哪里截断了循环
+                if self.debug && self.expanding.contains(name) {
+                    return write!(f, "<cycle: {}>", name);
+                }
                 // 如果我们已经访问过这个定义，说明遇到了递归，只打印名字
-                if self.visited_defs.contains(name) {
+                if self.compact || self.visited_defs.contains(name) {
                     write!(f, "{}", name)
                 } else {
                     // 否则，打印名字并展开它的定义
                     self.visited_defs.insert(name.clone());
                     write!(f, "{}", name)?;
+                    if self.debug {
+                        write!(f, "@{}", self.expanding.len())?;
+                    }
                     if let Some(defined_node) = self.constraint.get_node(name) {
                         write!(f, " := ")?;
-                        self.format_node(f, defined_node)?;
+                        self.expanding.push(name.clone());
+                        let result = self.format_node(f, defined_node);
+                        self.expanding.pop();
+                        result?;
                     }
                     // 注意：这里我们不从 visited_defs 中移除，
                     // 因为在一个格式化任务中，一个定义展开一次就够了。
                     Ok(())
                 }
             }
+            ConstraintNode::Annotated(inner, _) => self.format_node(f, inner),
+            ConstraintNode::Custom(custom) => custom.fmt_custom(f),
+            ConstraintNode::Predicate(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// 在某个约束图的上下文中以紧凑形式打印单个节点
+pub(crate) struct NodeDisplay<'a> {
+    pub(crate) constraint: &'a Constraint,
+    pub(crate) node: &'a ConstraintNode,
+}
+
+impl fmt::Display for NodeDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut formatter = PrettyFormatter::new(self.constraint);
+        formatter.compact = true;
+        formatter.format_node(f, self.node)
+    }
+}
+
+/// 排序模式下比较成员用的紧凑输出
+struct SortKey<'a> {
+    constraint: &'a Constraint,
+    node: &'a ConstraintNode,
+}
+
+impl fmt::Display for SortKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut formatter = PrettyFormatter::new(self.constraint);
+        formatter.compact = true;
+        formatter.sorted = true;
+        formatter.format_node(f, self.node)
+    }
+}
+
+impl Constraint {
+    /// 与 `Display` 相同，但枚举成员按其紧凑输出排序，成员的插入顺序不影响结果，
+    /// 适合快照测试；默认的 `Display` 保留插入顺序，便于调试
+    pub fn display_sorted(&self) -> String {
+        struct Sorted<'a>(&'a Constraint);
+        impl fmt::Display for Sorted<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut formatter = PrettyFormatter::new(self.0);
+                formatter.sorted = true;
+                match self.0.get_node(self.0.entry()) {
+                    Some(entry_node) => formatter.format_node(f, entry_node),
+                    None => write!(f, "{}", self.0),
+                }
+            }
+        }
+        Sorted(self).to_string()
+    }
+
+    /// 与 `Display` 相同，但每个枚举至多输出前 max_enum_members 个成员，其余成员输出为
+    /// `... (N more)`，使很宽的枚举也只占有限的几行；截断后的输出不能再解析
+    pub fn display_truncated(&self, max_enum_members: usize) -> String {
+        struct Truncated<'a>(&'a Constraint, usize);
+        impl fmt::Display for Truncated<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut formatter = PrettyFormatter::new(self.0);
+                formatter.max_enum_members = Some(self.1);
+                match self.0.get_node(self.0.entry()) {
+                    Some(entry_node) => formatter.format_node(f, entry_node),
+                    None => write!(f, "{}", self.0),
+                }
+            }
+        }
+        Truncated(self, max_enum_members).to_string()
+    }
+
+    /// 与 `Display` 相同，但 registry 中注册了格式化函数的节点类别用注册的函数输出
+    pub fn display_with_registry(&self, registry: &FormatterRegistry) -> String {
+        struct WithRegistry<'a>(&'a Constraint, &'a FormatterRegistry);
+        impl fmt::Display for WithRegistry<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut formatter = PrettyFormatter::new(self.0);
+                formatter.registry = Some(self.1);
+                match self.0.get_node(self.0.entry()) {
+                    Some(entry_node) => formatter.format_node(f, entry_node),
+                    None => write!(f, "{}", self.0),
+                }
+            }
+        }
+        WithRegistry(self, registry).to_string()
+    }
+
+    /// 与 `Display` 相同，但入口或从入口引用到的定义不存在时返回错误，
+    /// 而不是把错误信息写进输出
+    pub fn render(&self) -> Result<String, ConstraintError> {
+        if !self.graph.contains_key(&self.entry) {
+            return Err(ConstraintError::MissingEntry(self.entry.clone()));
+        }
+        let mut missing: Vec<String> = self
+            .reachable_defs()
+            .into_iter()
+            .filter(|name| !self.graph.contains_key(name))
+            .collect();
+        missing.sort();
+        match missing.into_iter().next() {
+            Some(name) => Err(ConstraintError::UndefinedDef(name)),
+            None => Ok(self.to_string()),
+        }
+    }
+}
+
+impl Constraint {
+    /// 约束的规范字符串，可作为外部缓存的键：先 `simplify`，把互模拟的定义合并为一个，
+    /// 再内联只被引用一次的定义，按从入口出发的遍历顺序把定义重命名为 `$0`、`$1`……，
+    /// 最后以排序后的紧凑形式逐个输出定义
+    ///
+    /// 键相同的约束一定相等。合并互模拟的定义之后，递归展开的层数不同的约束得到相同的键；
+    /// 反过来，化简后仍不互模拟的相等约束的键可能不同。遍历时枚举成员按屏蔽了定义名的
+    /// 输出排序，因此键与定义名和成员顺序无关
+    pub fn canonical_key(&self) -> String {
+        fn visit(
+            constraint: &Constraint,
+            masked: &HashMap<String, String>,
+            name: &str,
+            renames: &mut HashMap<String, String>,
+            order: &mut Vec<String>,
+        ) {
+            if renames.contains_key(name) {
+                return;
+            }
+            renames.insert(name.to_string(), format!("${}", order.len()));
+            order.push(name.to_string());
+            let mut referenced = Vec::new();
+            if let Some(body) = constraint.get_node(name) {
+                references(constraint, masked, body, &mut referenced);
+            }
+            for name in referenced {
+                visit(constraint, masked, &name, renames, order);
+            }
+        }
+        // 按输出顺序收集引用的定义，成员按屏蔽定义名后的输出排序
+        fn references(
+            constraint: &Constraint,
+            masked: &HashMap<String, String>,
+            node: &ConstraintNode,
+            result: &mut Vec<String>,
+        ) {
+            let key = |node: &ConstraintNode| {
+                SortKey {
+                    constraint,
+                    node: &node.rename_defs(masked),
+                }
+                .to_string()
+            };
+            match node {
+                ConstraintNode::Def(name) => result.push(name.clone()),
+                ConstraintNode::Enum(nodes) => {
+                    let mut nodes: Vec<&ConstraintNode> = nodes.iter().collect();
+                    nodes.sort_by_cached_key(|node| key(node));
+                    for node in nodes {
+                        references(constraint, masked, node, result);
+                    }
+                }
+                ConstraintNode::TaggedEnum(members) => {
+                    let mut members: Vec<&(String, ConstraintNode)> = members.iter().collect();
+                    members.sort_by_cached_key(|(tag, node)| format!("#{}({})", tag, key(node)));
+                    for (_, node) in members {
+                        references(constraint, masked, node, result);
+                    }
+                }
+                ConstraintNode::Record(fields) => {
+                    let mut fields: Vec<&(String, RecordField)> = fields.iter().collect();
+                    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    for (_, field) in fields {
+                        for child in std::iter::once(&field.ty).chain(&field.default) {
+                            references(constraint, masked, child, result);
+                        }
+                    }
+                }
+                other => {
+                    for child in other.children() {
+                        references(constraint, masked, child, result);
+                    }
+                }
+            }
+        }
+
+        // 只被引用一次的定义（不直接引用自身时）内联到引用处，消除拆分定义的方式带来的差异
+        let merged = self.simplify().prune().merge_bisimilar();
+        let mut inlined = Constraint::from_graph((*merged.graph).clone(), self.entry.clone());
+        loop {
+            let mut uses: HashMap<&str, usize> = HashMap::new();
+            for node in inlined.graph.values() {
+                let mut referenced = Vec::new();
+                node.referenced_defs(&mut referenced);
+                for name in referenced {
+                    *uses.entry(name).or_default() += 1;
+                }
+            }
+            let Some(name) = inlined
+                .graph
+                .iter()
+                .filter(|(name, body)| {
+                    let mut referenced = Vec::new();
+                    body.referenced_defs(&mut referenced);
+                    **name != self.entry
+                        && uses.get(name.as_str()) == Some(&1)
+                        && !referenced.contains(&name.as_str())
+                })
+                .map(|(name, _)| name.clone())
+                .min()
+            else {
+                break;
+            };
+            // 内联经过 occurs-check；检查失败说明上面的筛选有误，此时保留已经内联的结果
+            let Ok(next) = inlined.inline_defs(&HashSet::from([name.clone()])) else {
+                break;
+            };
+            let mut graph = (*next.graph).clone();
+            graph.remove(&name);
+            inlined = Constraint::from_graph(graph, self.entry.clone());
+        }
+        let simplified = inlined.simplify();
+        let masked = simplified
+            .graph
+            .keys()
+            .map(|name| (name.clone(), "$".to_string()))
+            .collect();
+        let mut renames = HashMap::new();
+        let mut order = Vec::new();
+        visit(
+            &simplified,
+            &masked,
+            simplified.entry(),
+            &mut renames,
+            &mut order,
+        );
+        order
+            .iter()
+            .map(|name| {
+                let body = match simplified.get_node(name) {
+                    Some(body) => SortKey {
+                        constraint: &simplified,
+                        node: &body.rename_defs(&renames),
+                    }
+                    .to_string(),
+                    None => "?".to_string(),
+                };
+                format!("{} := {}", renames[name], body)
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// 调试用的输出：从入口定义开始展开，每次展开标注嵌套深度（如 `L_T@0 := ...`），
+    /// 引用正在展开的定义处标注为 `<cycle: L_T>`，便于定位意外的递归展开
+    pub fn display_debug(&self) -> String {
+        struct Debug<'a>(&'a Constraint);
+        impl fmt::Display for Debug<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut formatter = PrettyFormatter::new(self.0);
+                formatter.debug = true;
+                formatter.format_node(f, &ConstraintNode::Def(self.0.entry().clone()))
+            }
         }
+        Debug(self).to_string()
     }
 }
 
@@ -396,8 +3154,21 @@ impl fmt::Display for Constraint {
     }
 }
 
+// 定义按名字排序输出，使输出可以复现；定义体的下标只是缓存，不输出
+impl fmt::Debug for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Constraint")
+            .field("graph", &self.iter_defs().collect::<BTreeMap<_, _>>())
+            .field("entry", &self.entry)
+            .field("predicates", &self.predicates)
+            .finish()
+    }
+}
+
 impl PartialEq for Constraint {
     fn eq(&self, other: &Self) -> bool {
         self.super_of(other) && other.super_of(self)
     }
 }
+
+impl Eq for Constraint {}
@@ -0,0 +1,33 @@
+//! 用户自定义的约束节点，无需修改本 crate 即可加入新的约束种类
+
+use std::{any::Any, fmt, hash::Hasher};
+
+use crate::value::Value;
+
+/// 自定义约束，通过 `ConstraintNode::Custom` 放入约束图
+///
+/// 两侧都是自定义节点时，子类型判定交给 `subsumes`；自定义节点与内置节点
+/// （T、F、Enum、Def 除外）之间互不包含
+pub trait CustomConstraint: fmt::Debug + Send + Sync {
+    /// 用于在 `subsumes` 与 `eq_custom` 中向下转换 other
+    fn as_any(&self) -> &dyn Any;
+
+    /// self >= other，无法判断（例如 other 是另一种自定义约束）时返回 None，按不成立处理
+    fn subsumes(&self, other: &dyn CustomConstraint) -> Option<bool>;
+
+    /// 与 `eq_custom` 一致的哈希
+    fn hash_custom(&self, state: &mut dyn Hasher);
+
+    /// 结构相等，用于节点的 `PartialEq`
+    fn eq_custom(&self, other: &dyn CustomConstraint) -> bool;
+
+    /// 值 v 是否满足约束，默认不接受任何值
+    fn contains(&self, _v: &Value) -> bool {
+        false
+    }
+
+    /// 在 `Display` 中的输出，默认使用 `Debug`
+    fn fmt_custom(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
@@ -0,0 +1,191 @@
+//! 两个约束是否没有共同的值
+
+use crate::{
+    assumption::{Assumptions, Goal},
+    constraint::{AtomicConstraint, Constraint, ConstraintNode, Kind, RecordField},
+    range,
+};
+
+/// 判定 a 与 b 不相交的上下文
+///
+/// 值都是有限的，再次遇到同一对判定时可以假设其不相交。结果为真时两者一定不相交，
+/// 为假时只说明没能证明不相交，例如 T 与任何非 F 的节点都视为相交
+pub(crate) struct DisjointChecker<'a> {
+    pub(crate) constraint_a: &'a Constraint,
+    pub(crate) constraint_b: &'a Constraint,
+    assumption: Assumptions,
+}
+
+impl<'a> DisjointChecker<'a> {
+    pub(crate) fn new(constraint_a: &'a Constraint, constraint_b: &'a Constraint) -> Self {
+        DisjointChecker {
+            constraint_a,
+            constraint_b,
+            assumption: Assumptions::default(),
+        }
+    }
+
+    fn goal<'n>(&self, node_a: &'n ConstraintNode, node_b: &'n ConstraintNode) -> Option<Goal<'n>> {
+        let def_id = |constraint: &Constraint, node: &ConstraintNode| match node {
+            ConstraintNode::Def(name) => constraint.arena().index_of(name),
+            _ => None,
+        };
+        match (
+            def_id(self.constraint_a, node_a),
+            def_id(self.constraint_b, node_b),
+        ) {
+            (Some(a), Some(b)) => Some(Goal::Defs(a, b)),
+            (Some(a), None) => Some(Goal::DefLeft(a, node_b)),
+            (None, Some(b)) => Some(Goal::DefRight(node_a, b)),
+            (None, None) => None,
+        }
+    }
+
+    /// 在假设 a、b 不相交的前提下展开定义
+    fn unfold(&mut self, node_a: &ConstraintNode, node_b: &ConstraintNode) -> bool {
+        let Some(goal) = self.goal(node_a, node_b) else {
+            // 引用了未定义的定义，它不包含任何值
+            return true;
+        };
+        if self.assumption.contains(goal) {
+            return true;
+        }
+        let resolve = |constraint: &'a Constraint, node: &ConstraintNode| match node {
+            ConstraintNode::Def(name) => constraint.get_node(name),
+            _ => None,
+        };
+        let body_a = resolve(self.constraint_a, node_a);
+        let body_b = resolve(self.constraint_b, node_b);
+        self.assumption.insert(goal);
+        let result = self.check(body_a.unwrap_or(node_a), body_b.unwrap_or(node_b));
+        self.assumption.remove(goal);
+        result
+    }
+
+    pub(crate) fn check(&mut self, node_a: &ConstraintNode, node_b: &ConstraintNode) -> bool {
+        let node_a = node_a.strip_annotations();
+        let node_b = node_b.strip_annotations();
+        match (node_a, node_b) {
+            (ConstraintNode::F, _) | (_, ConstraintNode::F) => true,
+            (ConstraintNode::Range { lo, hi }, _) | (_, ConstraintNode::Range { lo, hi })
+                if range::is_empty(lo, hi) =>
+            {
+                true
+            }
+            (ConstraintNode::Def(_), _) | (_, ConstraintNode::Def(_)) => {
+                self.unfold(node_a, node_b)
+            }
+            (ConstraintNode::Enum(nodes), b) => nodes.iter().all(|node| self.check(node, b)),
+            (a, ConstraintNode::Enum(nodes)) => nodes.iter().all(|node| self.check(a, node)),
+            // 交集包含于每个成员，与某个成员不相交即可
+            (ConstraintNode::And(nodes), b) => nodes.iter().any(|node| self.check(node, b)),
+            (a, ConstraintNode::And(nodes)) => nodes.iter().any(|node| self.check(a, node)),
+            (ConstraintNode::T, _) | (_, ConstraintNode::T) => false,
+            (ConstraintNode::Leaf(a), ConstraintNode::Leaf(b)) => a != b,
+            (ConstraintNode::Kind(a), ConstraintNode::Kind(b)) => a != b,
+            (ConstraintNode::Kind(kind), ConstraintNode::Leaf(atom))
+            | (ConstraintNode::Leaf(atom), ConstraintNode::Kind(kind)) => {
+                atom.kind() != Some(*kind)
+            }
+            (ConstraintNode::Kind(kind), ConstraintNode::Range { lo, hi })
+            | (ConstraintNode::Range { lo, hi }, ConstraintNode::Kind(kind)) => {
+                range::kind(lo, hi).is_some_and(|range_kind| range_kind != *kind)
+            }
+            (ConstraintNode::Range { lo, hi }, ConstraintNode::Leaf(atom))
+            | (ConstraintNode::Leaf(atom), ConstraintNode::Range { lo, hi }) => {
+                !range::contains(lo, hi, atom)
+            }
+            (
+                ConstraintNode::Range { lo: a_lo, hi: a_hi },
+                ConstraintNode::Range { lo: b_lo, hi: b_hi },
+            ) => match range::intersection(a_lo, a_hi, b_lo, b_hi) {
+                Some((lo, hi)) => range::is_empty(&lo, &hi),
+                None => true,
+            },
+            (ConstraintNode::IntSet(a), ConstraintNode::IntSet(b)) => a.is_disjoint(b),
+            (ConstraintNode::IntSet(set), ConstraintNode::Leaf(atom))
+            | (ConstraintNode::Leaf(atom), ConstraintNode::IntSet(set)) => match atom {
+                AtomicConstraint::LiteralInt(i) => !set.contains(i),
+                _ => true,
+            },
+            (ConstraintNode::IntSet(set), ConstraintNode::Kind(kind))
+            | (ConstraintNode::Kind(kind), ConstraintNode::IntSet(set)) => {
+                *kind != Kind::Int || set.is_empty()
+            }
+            (ConstraintNode::IntSet(set), ConstraintNode::Range { lo, hi })
+            | (ConstraintNode::Range { lo, hi }, ConstraintNode::IntSet(set)) => set
+                .iter()
+                .all(|i| !range::contains(lo, hi, &AtomicConstraint::LiteralInt(*i))),
+            (ConstraintNode::Pair(a_left, a_right), ConstraintNode::Pair(b_left, b_right)) => {
+                self.check(a_left, b_left) || self.check(a_right, b_right)
+            }
+            // 无法判断自定义节点的值，保守地认为可能相交
+            (ConstraintNode::Custom(_), _) | (_, ConstraintNode::Custom(_)) => false,
+            (ConstraintNode::Predicate(_), _) | (_, ConstraintNode::Predicate(_)) => false,
+            // 只有同名标签的成员可能相交
+            (ConstraintNode::TaggedEnum(a_members), ConstraintNode::TaggedEnum(b_members)) => {
+                a_members.iter().all(|(a_tag, a_payload)| {
+                    b_members
+                        .iter()
+                        .filter(|(b_tag, _)| b_tag == a_tag)
+                        .all(|(_, b_payload)| self.check(a_payload, b_payload))
+                })
+            }
+            // 某个字段一侧必须出现而另一侧不允许出现，或两侧不能同时缺少且类型不相交
+            (ConstraintNode::Record(a_fields), ConstraintNode::Record(b_fields)) => {
+                let required = |fields: &[(String, RecordField)],
+                                others: &[(String, RecordField)]| {
+                    fields.iter().any(|(name, field)| {
+                        field.default.is_none() && others.iter().all(|(other, _)| other != name)
+                    })
+                };
+                required(a_fields, b_fields)
+                    || required(b_fields, a_fields)
+                    || a_fields.iter().any(|(name, a_field)| {
+                        b_fields.iter().any(|(b_name, b_field)| {
+                            b_name == name
+                                && (a_field.default.is_none() || b_field.default.is_none())
+                                && self.check(&a_field.ty, &b_field.ty)
+                        })
+                    })
+            }
+            // Pair、带标签的枚举、记录与原子值互不相交
+            (ConstraintNode::Pair(..), _) | (_, ConstraintNode::Pair(..)) => true,
+            (ConstraintNode::TaggedEnum(_), _) | (_, ConstraintNode::TaggedEnum(_)) => true,
+            (ConstraintNode::Record(_), _) | (_, ConstraintNode::Record(_)) => true,
+            (ConstraintNode::Annotated(..), _) | (_, ConstraintNode::Annotated(..)) => {
+                unreachable!("annotations are stripped")
+            }
+        }
+    }
+}
+
+impl Constraint {
+    /// self 与 other 没有共同的值
+    pub fn disjoint(&self, other: &Self) -> bool {
+        match (self.get_node(self.entry()), other.get_node(other.entry())) {
+            (Some(a), Some(b)) => DisjointChecker::new(self, other).check(a, b),
+            _ => true,
+        }
+    }
+
+    /// 入口枚举中彼此相交的成员下标对 (i, j)，i < j；入口不是枚举时为空
+    pub fn overlapping_members(&self) -> Vec<(usize, usize)> {
+        let Some(ConstraintNode::Enum(members)) = self
+            .get_node(self.entry())
+            .map(ConstraintNode::strip_annotations)
+        else {
+            return Vec::new();
+        };
+        let mut checker = DisjointChecker::new(self, self);
+        let mut overlapping = Vec::new();
+        for i in 0..members.len() {
+            for j in i + 1..members.len() {
+                if !checker.check(&members[i], &members[j]) {
+                    overlapping.push((i, j));
+                }
+            }
+        }
+        overlapping
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// 约束图的构建或使用过程中出现的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstraintError {
+    MissingEntry(String),                        // 入口节点不存在
+    InvalidEntryName(String),                    // 入口名为空或只含空白
+    UndefinedDef(String),                        // 引用了未定义的定义
+    SharedGraph(String),                         // 试图修改被其他约束共享的图，内容为要加入的定义名
+    TooManyCycles { cycles: usize, max: usize }, // 定义依赖图中的环超过上限
+    ConflictingDef(String),                      // 合并的两个约束中同名定义的定义体不同
+    RecursionDetected(String),                   // 内联时 occurs-check 发现定义将被内联进自身
+}
+
+impl fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstraintError::MissingEntry(name) => {
+                write!(f, "entry '{}' not found in constraint graph", name)
+            }
+            ConstraintError::InvalidEntryName(name) => {
+                write!(f, "invalid entry name '{}'", name)
+            }
+            ConstraintError::UndefinedDef(name) => {
+                write!(f, "definition '{}' is referenced but not defined", name)
+            }
+            ConstraintError::SharedGraph(name) => {
+                write!(f, "cannot add '{}': the constraint graph is shared", name)
+            }
+            ConstraintError::TooManyCycles { cycles, max } => {
+                write!(
+                    f,
+                    "constraint graph has {} cycles, at most {} allowed",
+                    cycles, max
+                )
+            }
+            ConstraintError::ConflictingDef(name) => {
+                write!(f, "definition '{}' has conflicting bodies", name)
+            }
+            ConstraintError::RecursionDetected(name) => {
+                write!(f, "definition '{}' would be inlined into itself", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConstraintError {}
@@ -0,0 +1,242 @@
+use std::fmt;
+
+use crate::{
+    constraint::{Checker, Constraint, ConstraintNode, Metadata, NodeDisplay},
+    error::ConstraintError,
+};
+
+/// 子类型判定的推导树，每个节点对应一次 a >= b 的判定
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derivation {
+    pub rule: &'static str,         // 本步使用的规则
+    pub left: String,               // 左侧（超约束一侧）节点的打印形式
+    pub right: String,              // 右侧节点的打印形式
+    pub annotations: Vec<Metadata>, // 两侧节点上的注释，先左后右、由外到内
+    pub result: bool,
+    pub children: Vec<Derivation>,
+}
+
+impl Derivation {
+    fn format_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(
+            f,
+            "{}{} >= {} [{}] {}",
+            "  ".repeat(depth),
+            self.left,
+            self.right,
+            self.rule,
+            self.result
+        )?;
+        if !self.annotations.is_empty() {
+            write!(f, " @ {}", self.annotations.join(", "))?;
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.format_tree(f, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// 从根到最深的失败步骤的路径，每一步沿第一个失败的子步骤向下；判定成立时为空
+    pub fn failure_path(&self) -> Vec<&Derivation> {
+        let mut path = Vec::new();
+        let mut step = Some(self).filter(|step| !step.result);
+        while let Some(current) = step {
+            path.push(current);
+            step = current.children.iter().find(|child| !child.result);
+        }
+        path
+    }
+
+    /// 以 JSON 树的形式导出，便于 IDE 折叠展示
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "rule": self.rule,
+            "left": self.left,
+            "right": self.right,
+            "annotations": self.annotations,
+            "result": self.result,
+            "children": self.children.iter().map(Derivation::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.format_tree(f, 0)
+    }
+}
+
+/// 判定过程中正在构建的推导树
+#[derive(Debug, Default)]
+pub(crate) struct DerivationTrace {
+    stack: Vec<Derivation>,
+    root: Option<Derivation>,
+    include_assumptions: bool, // 是否记录加入假设与命中假设的步骤
+}
+
+fn display(constraint: &Constraint, node: &ConstraintNode) -> String {
+    NodeDisplay { constraint, node }.to_string()
+}
+
+impl DerivationTrace {
+    pub(crate) fn enter(
+        &mut self,
+        constraint_a: &Constraint,
+        constraint_b: &Constraint,
+        node_a: &ConstraintNode,
+        node_b: &ConstraintNode,
+        assumed: bool,
+    ) {
+        let annotations = [node_a, node_b]
+            .into_iter()
+            .flat_map(|mut node| {
+                std::iter::from_fn(move || {
+                    let ConstraintNode::Annotated(inner, metadata) = node else {
+                        return None;
+                    };
+                    node = inner.as_ref();
+                    Some(metadata.clone())
+                })
+            })
+            .collect();
+        let node_a = node_a.strip_annotations();
+        let node_b = node_b.strip_annotations();
+        let rule = match (assumed, self.include_assumptions) {
+            (true, true) => "cycle-hit",
+            (true, false) => "assumption",
+            // 具体的规则由判定分支通过 `rule` 写入
+            (false, _) => "mismatch",
+        };
+        self.stack.push(Derivation {
+            rule,
+            left: display(constraint_a, node_a),
+            right: display(constraint_b, node_b),
+            annotations,
+            result: false,
+            children: Vec::new(),
+        });
+    }
+
+    /// 在当前判定下记录一个加入假设集的步骤
+    pub(crate) fn assume(
+        &mut self,
+        constraint_a: &Constraint,
+        constraint_b: &Constraint,
+        node_a: &ConstraintNode,
+        node_b: &ConstraintNode,
+    ) {
+        if !self.include_assumptions {
+            return;
+        }
+        if let Some(current) = self.stack.last_mut() {
+            current.children.push(Derivation {
+                rule: "assume",
+                left: display(constraint_a, node_a),
+                right: display(constraint_b, node_b),
+                annotations: Vec::new(),
+                result: true,
+                children: Vec::new(),
+            });
+        }
+    }
+
+    /// 设置当前判定使用的规则
+    pub(crate) fn rule(&mut self, rule: &'static str) {
+        if let Some(current) = self.stack.last_mut() {
+            current.rule = rule;
+        }
+    }
+
+    pub(crate) fn exit(&mut self, result: bool) {
+        let mut finished = self.stack.pop().expect("unbalanced derivation trace");
+        finished.result = result;
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(finished),
+            None => self.root = Some(finished),
+        }
+    }
+}
+
+/// 构建 a >= b 的推导树，可选择是否展示余归纳的假设步骤
+pub struct DerivationBuilder<'a> {
+    left: &'a Constraint,
+    right: &'a Constraint,
+    include_assumptions: bool,
+}
+
+impl DerivationBuilder<'_> {
+    /// 为真时，每次加入假设都会产生一个 `assume` 节点，
+    /// 利用假设闭合的判定使用 `cycle-hit` 规则而不是 `assumption`
+    pub fn include_assumptions(mut self, include_assumptions: bool) -> Self {
+        self.include_assumptions = include_assumptions;
+        self
+    }
+
+    pub fn build(self) -> Derivation {
+        let mut checker = Checker::new(self.left, self.right);
+        checker.trace = Some(DerivationTrace {
+            include_assumptions: self.include_assumptions,
+            ..Default::default()
+        });
+        let result = checker.run();
+        match checker.trace.and_then(|trace| trace.root) {
+            Some(root) => root,
+            None => Derivation {
+                rule: "missing-entry",
+                left: self.left.entry().clone(),
+                right: self.right.entry().clone(),
+                annotations: Vec::new(),
+                result,
+                children: Vec::new(),
+            },
+        }
+    }
+}
+
+impl Constraint {
+    /// 判定 self >= other 的推导树构建器
+    pub fn derive<'a>(&'a self, other: &'a Self) -> DerivationBuilder<'a> {
+        DerivationBuilder {
+            left: self,
+            right: other,
+            include_assumptions: false,
+        }
+    }
+
+    /// 判定 self >= other，并返回完整的推导树
+    pub fn explain_super_of(&self, other: &Self) -> Derivation {
+        self.derive(other).build()
+    }
+
+    /// 断言 self >= other，不成立时 panic，消息中列出推导树的失败路径，
+    /// 最后一行是直接导致失败的节点对。用于测试，代替 `assert!(a.super_of(&b))`
+    #[track_caller]
+    pub fn assert_super_of(&self, other: &Self) {
+        let derivation = self.explain_super_of(other);
+        let path = derivation.failure_path();
+        let Some(last) = path.last() else {
+            return;
+        };
+        let mut message = format!("assertion failed: {} >= {}\nfailure path:\n", self, other);
+        for (depth, step) in path.iter().enumerate() {
+            message += &format!(
+                "{}{} >= {} [{}]\n",
+                "  ".repeat(depth + 1),
+                step.left,
+                step.right,
+                step.rule
+            );
+        }
+        message += &format!("mismatch: {} >= {}", last.left, last.right);
+        panic!("{}", message);
+    }
+
+    /// 不会 panic 的 `explain_super_of`
+    pub fn try_explain_super_of(&self, other: &Self) -> Result<Derivation, ConstraintError> {
+        self.validate()?;
+        other.validate()?;
+        Ok(self.explain_super_of(other))
+    }
+}
@@ -0,0 +1,77 @@
+//! 按节点类别自定义输出格式，见 `Constraint::display_with_registry`
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::constraint::{AtomicConstraint, ConstraintNode};
+
+/// 可以注册格式化函数的节点类别
+///
+/// 字面量按原子的类别区分，区间端点同样使用对应字面量的格式化函数，
+/// 除非 `Range` 本身注册了格式化函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FormatKind {
+    Nil,
+    Int,
+    Float,
+    String,
+    Char,
+    Kind,  // Int、String 等类别本身
+    Range, // 整个区间
+    Custom,
+    Predicate,
+}
+
+/// 格式化函数，参数是要输出的节点，字面量以 `ConstraintNode::Leaf` 传入
+pub type NodeFormatter = Arc<dyn Fn(&ConstraintNode) -> String + Send + Sync>;
+
+/// 节点类别到格式化函数的映射，没有注册的类别使用默认的输出
+#[derive(Clone, Default)]
+pub struct FormatterRegistry {
+    formatters: HashMap<FormatKind, NodeFormatter>,
+}
+
+impl FormatterRegistry {
+    pub fn new() -> Self {
+        FormatterRegistry::default()
+    }
+
+    /// 为 kind 注册格式化函数，替换之前注册的函数
+    pub fn register<F>(&mut self, kind: FormatKind, formatter: F) -> &mut Self
+    where
+        F: Fn(&ConstraintNode) -> String + Send + Sync + 'static,
+    {
+        self.formatters.insert(kind, Arc::new(formatter));
+        self
+    }
+
+    /// 用注册的函数输出 node，node 的类别没有注册时返回 None
+    pub(crate) fn format(&self, node: &ConstraintNode) -> Option<String> {
+        let kind = match node {
+            ConstraintNode::Leaf(atom) => match atom {
+                AtomicConstraint::Nil => FormatKind::Nil,
+                AtomicConstraint::LiteralInt(_) => FormatKind::Int,
+                AtomicConstraint::LiteralFloat(_) => FormatKind::Float,
+                AtomicConstraint::LiteralString(_) => FormatKind::String,
+                AtomicConstraint::LiteralChar(_) => FormatKind::Char,
+            },
+            ConstraintNode::Kind(_) => FormatKind::Kind,
+            ConstraintNode::Range { .. } => FormatKind::Range,
+            ConstraintNode::Custom(_) => FormatKind::Custom,
+            ConstraintNode::Predicate(_) => FormatKind::Predicate,
+            _ => return None,
+        };
+        self.formatters.get(&kind).map(|formatter| formatter(node))
+    }
+}
+
+impl fmt::Debug for FormatterRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut kinds: Vec<String> = self
+            .formatters
+            .keys()
+            .map(|kind| format!("{:?}", kind))
+            .collect();
+        kinds.sort();
+        f.debug_set().entries(kinds).finish()
+    }
+}
@@ -0,0 +1,206 @@
+//! 把约束图导出为正则树文法，便于与树自动机工具交互
+
+use std::fmt;
+
+use crate::constraint::{
+    AtomicConstraint, Bound, Constraint, ConstraintNode, Kind, NodeDisplay, escape_char,
+    escape_string,
+};
+
+/// 构造子的参数：任意树或某个非终结符
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    Any,
+    Nonterminal(String),
+}
+
+/// 非终结符的一个候选产生式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Production {
+    Any, // 任意树，对应 T
+    Atom(AtomicConstraint),
+    Kind(Kind),
+    Range { lo: Bound, hi: Bound },
+    Pair(Symbol, Symbol),
+    Tagged(String, Symbol), // 以标签为名字的一元构造子
+    Nonterminal(String),    // 单元产生式 A -> B
+}
+
+/// 一个非终结符与它的全部候选，没有候选的非终结符不产生任何树
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub nonterminal: String,
+    pub alternatives: Vec<Production>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeGrammar {
+    pub start: String,
+    pub rules: Vec<Rule>, // 起始符号的规则在最前面
+}
+
+impl TreeGrammar {
+    pub fn rule(&self, nonterminal: &str) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.nonterminal == nonterminal)
+    }
+}
+
+struct GrammarBuilder {
+    rules: Vec<Rule>,
+}
+
+impl GrammarBuilder {
+    /// 定义体中嵌套的复合节点获得形如 `L_T.1` 的新非终结符
+    fn add_rule(&mut self, nonterminal: String, node: &ConstraintNode) {
+        let position = self.rules.len();
+        self.rules.push(Rule {
+            nonterminal: nonterminal.clone(),
+            alternatives: Vec::new(),
+        });
+        let mut alternatives = Vec::new();
+        self.alternatives(&nonterminal, node, &mut alternatives);
+        self.rules[position].alternatives = alternatives;
+    }
+
+    fn alternatives(&mut self, owner: &str, node: &ConstraintNode, result: &mut Vec<Production>) {
+        match node.strip_annotations() {
+            ConstraintNode::T => result.push(Production::Any),
+            ConstraintNode::F => {}
+            ConstraintNode::Leaf(atom) => result.push(Production::Atom(atom.clone())),
+            ConstraintNode::Kind(kind) => result.push(Production::Kind(*kind)),
+            ConstraintNode::Range { lo, hi } => result.push(Production::Range {
+                lo: lo.clone(),
+                hi: hi.clone(),
+            }),
+            ConstraintNode::IntSet(set) => result.extend(
+                set.iter()
+                    .map(|i| Production::Atom(AtomicConstraint::LiteralInt(*i))),
+            ),
+            ConstraintNode::Enum(nodes) => {
+                for node in nodes {
+                    self.alternatives(owner, node, result);
+                }
+            }
+            ConstraintNode::Pair(left, right) => {
+                let left = self.symbol(owner, left);
+                let right = self.symbol(owner, right);
+                result.push(Production::Pair(left, right));
+            }
+            ConstraintNode::TaggedEnum(members) => {
+                for (tag, payload) in members {
+                    let payload = self.symbol(owner, payload);
+                    result.push(Production::Tagged(tag.clone(), payload));
+                }
+            }
+            ConstraintNode::Def(name) => result.push(Production::Nonterminal(name.clone())),
+            // 文法无法表示交集，放宽为第一个成员
+            ConstraintNode::And(nodes) => match nodes.first() {
+                Some(first) => self.alternatives(owner, first, result),
+                None => result.push(Production::Any),
+            },
+            // 文法无法表示记录、自定义约束与谓词，放宽为任意树
+            ConstraintNode::Record(_)
+            | ConstraintNode::Custom(_)
+            | ConstraintNode::Predicate(_) => result.push(Production::Any),
+            ConstraintNode::Annotated(..) => unreachable!("annotations are stripped"),
+        }
+    }
+
+    fn symbol(&mut self, owner: &str, node: &ConstraintNode) -> Symbol {
+        match node.strip_annotations() {
+            ConstraintNode::T => Symbol::Any,
+            ConstraintNode::Def(name) => Symbol::Nonterminal(name.clone()),
+            other => {
+                let fresh = format!("{}.{}", owner, self.rules.len());
+                self.add_rule(fresh.clone(), other);
+                Symbol::Nonterminal(fresh)
+            }
+        }
+    }
+}
+
+impl Constraint {
+    /// 导出为正则树文法：每个定义是一个非终结符，Enum 是候选，Pair 是二元构造子
+    pub fn to_grammar(&self) -> TreeGrammar {
+        let mut builder = GrammarBuilder { rules: Vec::new() };
+        let mut names: Vec<&String> = self.graph.keys().collect();
+        names.sort_by_key(|name| (*name != self.entry(), *name));
+        for name in names {
+            builder.add_rule(name.clone(), &self.graph[name]);
+        }
+        TreeGrammar {
+            start: self.entry().clone(),
+            rules: builder.rules,
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbol::Any => write!(f, "T"),
+            Symbol::Nonterminal(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Production {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Production::Any => write!(f, "T"),
+            Production::Atom(AtomicConstraint::Nil) => write!(f, "nil"),
+            Production::Atom(AtomicConstraint::LiteralInt(i)) => write!(f, "{}", i),
+            Production::Atom(AtomicConstraint::LiteralString(s)) => {
+                write!(f, "{}", escape_string(s))
+            }
+            Production::Atom(AtomicConstraint::LiteralChar(c)) => write!(f, "{}", escape_char(*c)),
+            Production::Atom(AtomicConstraint::LiteralFloat(x)) => write!(f, "{:?}", x),
+            Production::Kind(kind) => write!(f, "{}", kind),
+            Production::Range { lo, hi } => {
+                let node = ConstraintNode::Range {
+                    lo: lo.clone(),
+                    hi: hi.clone(),
+                };
+                let constraint = Constraint::new(String::new());
+                write!(
+                    f,
+                    "{}",
+                    NodeDisplay {
+                        constraint: &constraint,
+                        node: &node,
+                    }
+                )
+            }
+            Production::Pair(left, right) => write!(f, "pair({}, {})", left, right),
+            Production::Tagged(tag, payload) => write!(f, "#{}({})", tag, payload),
+            Production::Nonterminal(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> ", self.nonterminal)?;
+        if self.alternatives.is_empty() {
+            return write!(f, "F");
+        }
+        for (i, alternative) in self.alternatives.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", alternative)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TreeGrammar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rule in &self.rules {
+            writeln!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,28 @@
 #[cfg(test)]
 mod tests;
-mod constraint;
\ No newline at end of file
+mod arena;
+mod assumption;
+mod bisim;
+pub mod coercion;
+mod complement;
+pub mod constraint;
+pub mod custom;
+mod disjoint;
+pub mod error;
+pub mod explain;
+pub mod format;
+pub mod grammar;
+mod list;
+pub mod parser;
+pub mod pool;
+pub mod predicate;
+mod range;
+#[cfg(feature = "serde")]
+mod schema;
+pub mod sealed;
+pub mod shape;
+mod simplify;
+pub mod templates;
+mod union;
+pub mod value;
+mod witness;
@@ -0,0 +1,277 @@
+//! Pair 的分量投影，以及以 Nil 结尾的右嵌套 Pair 列表的构造与规范化
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    constraint::{AtomicConstraint, Constraint, ConstraintNode, RecordField, is_expanded},
+    simplify::{OccursCheck, reduce_node},
+    union::{fresh_name, merge_into},
+};
+
+/// 元素为 element、长度恰好为 len 的列表节点
+fn list_node(element: &ConstraintNode, len: usize) -> ConstraintNode {
+    (0..len).fold(ConstraintNode::Leaf(AtomicConstraint::Nil), |tail, _| {
+        ConstraintNode::Pair(element.clone().into(), tail.into())
+    })
+}
+
+/// 把 element 的定义并入新图，nodes 由元素节点生成入口的定义体
+fn with_element(
+    element: &Constraint,
+    nodes: impl FnOnce(&ConstraintNode) -> ConstraintNode,
+) -> Constraint {
+    let mut graph = HashMap::new();
+    let entry = "List".to_string();
+    graph.insert(entry.clone(), ConstraintNode::F);
+    let element = merge_into(&mut graph, element).unwrap_or(ConstraintNode::F);
+    graph.insert(entry.clone(), nodes(&element));
+    Constraint::from_graph(graph, entry).prune()
+}
+
+/// 规范化节点中的列表：以 Pair 开头、沿右分量经过 Pair 与非递归的 Def 最终到达 Nil 的链
+/// 被展开为内联的右嵌套 Pair，其余节点只规范化子节点
+fn canonical_lists(
+    constraint: &Constraint,
+    recursive: &HashSet<String>,
+    check: &mut OccursCheck,
+    node: &ConstraintNode,
+) -> ConstraintNode {
+    /// 沿右分量展开列表，不以 Nil 结尾或 occurs-check 失败时返回 None
+    fn spine(
+        constraint: &Constraint,
+        recursive: &HashSet<String>,
+        check: &mut OccursCheck,
+        node: &ConstraintNode,
+    ) -> Option<ConstraintNode> {
+        match node {
+            ConstraintNode::Leaf(AtomicConstraint::Nil) => Some(node.clone()),
+            ConstraintNode::Pair(head, tail) => {
+                let tail = spine(constraint, recursive, check, tail)?;
+                Some(ConstraintNode::Pair(
+                    canonical_lists(constraint, recursive, check, head).into(),
+                    tail.into(),
+                ))
+            }
+            ConstraintNode::Def(name) if !recursive.contains(name) => {
+                let body = constraint.get_node(name)?;
+                if !check.enter(name) {
+                    return None;
+                }
+                let result = spine(constraint, recursive, check, body);
+                check.exit();
+                result
+            }
+            _ => None,
+        }
+    }
+    match node {
+        ConstraintNode::Pair(..) => {
+            spine(constraint, recursive, check, node).unwrap_or_else(|| {
+                node.map_children(|child| canonical_lists(constraint, recursive, check, child))
+            })
+        }
+        other => other.map_children(|child| canonical_lists(constraint, recursive, check, child)),
+    }
+}
+
+/// 记录改写为 `(("a", A), (("b", B), Nil))` 形式的键值对列表，字段按声明顺序排列
+fn record_pairs(node: &ConstraintNode) -> ConstraintNode {
+    match node {
+        ConstraintNode::Record(fields) => fields.iter().rev().fold(
+            ConstraintNode::Leaf(AtomicConstraint::Nil),
+            |tail, (name, field)| {
+                let key = ConstraintNode::Leaf(AtomicConstraint::LiteralString(name.clone()));
+                ConstraintNode::Pair(
+                    ConstraintNode::Pair(key.into(), record_pairs(&field.ty).into()).into(),
+                    tail.into(),
+                )
+            },
+        ),
+        other => other.map_children(record_pairs),
+    }
+}
+
+/// 非空的、键是互不相同的字符串字面量的键值对列表改写为记录，其余节点只改写子节点
+fn pair_record(node: &ConstraintNode) -> ConstraintNode {
+    fn fields(node: &ConstraintNode) -> Option<Vec<(String, RecordField)>> {
+        let mut fields: Vec<(String, RecordField)> = Vec::new();
+        let mut tail = node;
+        while let ConstraintNode::Pair(entry, rest) = tail {
+            let ConstraintNode::Pair(key, value) = entry.strip_annotations() else {
+                return None;
+            };
+            let ConstraintNode::Leaf(AtomicConstraint::LiteralString(name)) =
+                key.strip_annotations()
+            else {
+                return None;
+            };
+            if fields.iter().any(|(existing, _)| existing == name) {
+                return None;
+            }
+            fields.push((name.clone(), RecordField::required(pair_record(value))));
+            tail = rest.strip_annotations();
+        }
+        match tail {
+            ConstraintNode::Leaf(AtomicConstraint::Nil) if !fields.is_empty() => Some(fields),
+            _ => None,
+        }
+    }
+    /// 不是记录的列表：只改写元素，尾部仍属于同一个列表，不单独改写为记录
+    fn elements(node: &ConstraintNode) -> ConstraintNode {
+        match node {
+            ConstraintNode::Pair(head, tail) => {
+                ConstraintNode::Pair(pair_record(head).into(), elements(tail).into())
+            }
+            other => pair_record(other),
+        }
+    }
+    match node {
+        ConstraintNode::Pair(..) => fields(node)
+            .map(ConstraintNode::Record)
+            .unwrap_or_else(|| elements(node)),
+        other => other.map_children(pair_record),
+    }
+}
+
+impl Constraint {
+    /// 把列表统一为内联的、以 Nil 结尾的右嵌套 Pair，使经由中间定义构造的列表与直接
+    /// 嵌套的列表具有相同的结构
+    ///
+    /// 只有沿右分量最终到达 Nil 的 Pair 链才会被展开，递归的列表定义与不以 Nil 结尾的
+    /// Pair 保持不变；展开后不再被引用的定义会被去除。展开受 occurs-check 保护，
+    /// 检查失败的列表同样保持不变
+    pub fn canonicalize_lists(&self) -> Constraint {
+        let recursive = self.recursive_defs();
+        let mut check = OccursCheck::default();
+        self.map_defs(|_, node| canonical_lists(self, &recursive, &mut check, node))
+            .prune()
+    }
+
+    /// 把以键值对列表 `(("a", A), (("b", B), Nil))` 编码的记录改写为 `Record` 节点
+    ///
+    /// 只改写内联的、键是互不相同的字符串字面量的非空列表，得到的字段都是必需的；
+    /// 经由定义构造的列表可先调用 `canonicalize_lists`。与 `record_to_pairs` 互逆
+    pub fn pairs_to_record(&self) -> Constraint {
+        self.map_defs(|_, node| pair_record(node))
+    }
+
+    /// 把 `Record` 节点改写为键值对列表，字段按声明顺序排列，见 `pairs_to_record`
+    ///
+    /// 键值对列表无法表达默认值，字段的默认值会被丢弃
+    pub fn record_to_pairs(&self) -> Constraint {
+        self.map_defs(|_, node| record_pairs(node))
+    }
+
+    /// 由给定元素组成、以 tail 结尾的右嵌套列表 `(e0, (e1, ... tail))`，入口名为 `List`
+    ///
+    /// 元素与 tail 不应引用任何定义，否则结果中的引用没有定义
+    pub fn list_of(elements: &[ConstraintNode], tail: ConstraintNode) -> Constraint {
+        let node = elements.iter().rev().fold(tail, |tail, element| {
+            ConstraintNode::Pair(element.clone().into(), tail.into())
+        });
+        let entry = "List".to_string();
+        Constraint::from_graph(HashMap::from([(entry.clone(), node)]), entry)
+    }
+
+    /// 以 Nil 结尾的 `list_of`
+    pub fn list(elements: &[ConstraintNode]) -> Constraint {
+        Constraint::list_of(elements, ConstraintNode::Leaf(AtomicConstraint::Nil))
+    }
+
+    /// 长度恰好为 len 的列表 `(e, (e, ... Nil))`，结果不含递归，可以被枚举
+    pub fn fixed_list(element: &Constraint, len: usize) -> Constraint {
+        with_element(element, |element| list_node(element, len))
+    }
+
+    /// 长度不超过 max 的列表，即长度 0 到 max 的 `fixed_list` 的并集
+    pub fn bounded_list(element: &Constraint, max: usize) -> Constraint {
+        with_element(element, |element| {
+            ConstraintNode::Enum((0..=max).map(|len| list_node(element, len)).collect())
+        })
+    }
+
+    /// 约束中所有 Pair 的左分量的并集
+    ///
+    /// 入口的枚举成员与定义都会被展开，每个定义只展开一次，因此递归的列表类型也会终止，
+    /// 例如列表的左投影就是元素类型。T 中包含任意 Pair，其投影为 T
+    pub fn project_left(&self) -> Constraint {
+        self.project(|left, _| left)
+    }
+
+    /// 约束中所有 Pair 的右分量的并集，见 `project_left`
+    pub fn project_right(&self) -> Constraint {
+        self.project(|_, right| right)
+    }
+
+    fn project<F>(&self, component: F) -> Constraint
+    where
+        F: for<'n> Fn(&'n ConstraintNode, &'n ConstraintNode) -> &'n ConstraintNode,
+    {
+        fn collect<'a, F>(
+            constraint: &'a Constraint,
+            node: &'a ConstraintNode,
+            component: &F,
+            expanded: &mut HashSet<&'a str>,
+            result: &mut Vec<ConstraintNode>,
+        ) where
+            F: for<'n> Fn(&'n ConstraintNode, &'n ConstraintNode) -> &'n ConstraintNode,
+        {
+            match node.strip_annotations() {
+                ConstraintNode::T => result.push(ConstraintNode::T),
+                ConstraintNode::Pair(left, right) => result.push(component(left, right).clone()),
+                ConstraintNode::Enum(nodes) => {
+                    for node in nodes {
+                        collect(constraint, node, component, expanded, result);
+                    }
+                }
+                // 交集的投影包含于各成员投影的交集，以后者作为上近似
+                ConstraintNode::And(nodes) => result.push(ConstraintNode::And(
+                    nodes
+                        .iter()
+                        .filter(|node| !is_expanded(node, expanded))
+                        .map(|node| {
+                            let mut member = Vec::new();
+                            collect(
+                                constraint,
+                                node,
+                                component,
+                                &mut expanded.clone(),
+                                &mut member,
+                            );
+                            ConstraintNode::Enum(member)
+                        })
+                        .collect(),
+                )),
+                ConstraintNode::Def(name) => {
+                    if let Some(body) = constraint.get_node(name)
+                        && expanded.insert(name)
+                    {
+                        collect(constraint, body, component, expanded, result);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut components = Vec::new();
+        if let Some(entry) = self.get_node(self.entry()) {
+            collect(
+                self,
+                entry,
+                &component,
+                &mut HashSet::new(),
+                &mut components,
+            );
+        }
+        let projected = reduce_node(&ConstraintNode::Enum(components));
+        // 投影恰好是某个定义时直接以它为入口并共享原图
+        if let ConstraintNode::Def(name) = projected.strip_annotations()
+            && self.graph.contains_key(name)
+        {
+            return Constraint::from_graph_shared(self.graph.clone(), name.clone());
+        }
+        let mut graph = (*self.graph).clone();
+        let entry = fresh_name(&graph, &format!("{}.proj", self.entry()));
+        graph.insert(entry.clone(), projected);
+        Constraint::from_graph(graph, entry).prune()
+    }
+}
@@ -0,0 +1,559 @@
+//! 约束的文本语法，与 `Display` 的输出格式一致
+//!
+//! ```text
+//! expr  := conj ('|' conj)*
+//! conj  := term ('&' term)*
+//! term  := 'T' | 'F' | 'Never' | 'Nil' | 'NaN' | 'Int' | 'Float' | 'String' | 'Char'
+//!        | atom [range] | range
+//!        | '(' expr ',' expr ')' | '(' expr ')'
+//!        | '#' name '(' expr ')'
+//!        | '{' [field (',' field)*] '}'
+//!        | name [':=' expr]
+//! field := name ':' expr ['=' expr]
+//! range := ['<'] '..' ['=' atom | atom]
+//! atom  := int | float | string | char
+//! ```
+//!
+//! `name := expr` 定义 name 并引用它，定义可以出现在表达式中的任意位置；
+//! 全部由带标签的项组成的 `|` 解析为一个 TaggedEnum
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, BufReader, Read},
+    str::FromStr,
+};
+
+use crate::{
+    constraint::{AtomicConstraint, Bound, Constraint, ConstraintNode, Kind, RecordField},
+    union::fresh_name,
+};
+
+/// 解析错误的类别，读取输入失败（包括输入不是合法的 UTF-8）与语法错误分开
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    Syntax,
+    Io(io::ErrorKind),
+}
+
+/// 解析错误，position 为出错位置在输入中的字节偏移，line 与 column 从 1 开始
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub position: usize,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// 输入中的位置，在读到时记录，缓冲区丢弃已解析的行之后仍可用于报错
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+/// 逐行读取输入的解析器：buffer 只保存尚未解析完的行，除字符串字面量外记号不跨行，
+/// 跳过空白之后当前行的其余部分总在 buffer 中
+struct Parser<R> {
+    reader: R,
+    eof: bool,
+    buffer: String,
+    pos: usize,
+    here: Location, // buffer[pos] 在输入中的位置
+    graph: HashMap<String, ConstraintNode>,
+    references: HashMap<String, Location>, // 每个名字第一次被引用的位置
+}
+
+impl<R: BufRead> Parser<R> {
+    fn error(&self, location: Location, message: impl Into<String>) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::Syntax,
+            position: location.position,
+            line: location.line,
+            column: location.column,
+            message: message.into(),
+        }
+    }
+
+    /// 再读入一行，缓冲区已全部解析时先丢弃它；输入已读完时返回 false
+    fn fill(&mut self) -> Result<bool, ParseError> {
+        if self.eof {
+            return Ok(false);
+        }
+        if self.pos == self.buffer.len() {
+            self.buffer.clear();
+            self.pos = 0;
+        }
+        match self.reader.read_line(&mut self.buffer) {
+            Ok(0) => {
+                self.eof = true;
+                Ok(false)
+            }
+            Ok(_) => Ok(true),
+            Err(error) => Err(ParseError {
+                kind: ParseErrorKind::Io(error.kind()),
+                message: format!("failed to read input: {}", error),
+                ..self.error(self.here, "")
+            }),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.buffer[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// 下一个字符，当前行已解析完时读入下一行，用于可以跨行的字符串字面量
+    fn peek_filled(&mut self) -> Result<Option<char>, ParseError> {
+        while self.peek().is_none() {
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+        Ok(self.peek())
+    }
+
+    /// 消耗 len 个字节并更新当前位置
+    fn advance(&mut self, len: usize) {
+        for c in self.buffer[self.pos..self.pos + len].chars() {
+            if c == '\n' {
+                self.here.line += 1;
+                self.here.column = 1;
+            } else {
+                self.here.column += 1;
+            }
+        }
+        self.here.position += len;
+        self.pos += len;
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), ParseError> {
+        loop {
+            let trimmed = self.rest().trim_start().len();
+            self.advance(self.rest().len() - trimmed);
+            if trimmed > 0 || !self.fill()? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// 跳过空白后若紧跟 token 则消耗它
+    fn eat(&mut self, token: &str) -> Result<bool, ParseError> {
+        self.skip_whitespace()?;
+        if self.rest().starts_with(token) {
+            self.advance(token.len());
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ParseError> {
+        if self.eat(token)? {
+            Ok(())
+        } else {
+            Err(self.error(self.here, format!("expected '{}'", token)))
+        }
+    }
+
+    fn starts_atom(&self) -> bool {
+        let mut chars = self.rest().chars();
+        match chars.next() {
+            Some('"' | '\'') => true,
+            Some('-') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<ConstraintNode, ParseError> {
+        let mut members = vec![self.parse_conj()?];
+        while self.eat("|")? {
+            members.push(self.parse_conj()?);
+        }
+        if members.len() > 1
+            && members
+                .iter()
+                .all(|member| matches!(member, ConstraintNode::TaggedEnum(_)))
+        {
+            let tagged = members
+                .into_iter()
+                .flat_map(|member| match member {
+                    ConstraintNode::TaggedEnum(tagged) => tagged,
+                    _ => unreachable!(),
+                })
+                .collect();
+            return Ok(ConstraintNode::TaggedEnum(tagged));
+        }
+        Ok(match members.len() {
+            1 => members.pop().unwrap(),
+            _ => ConstraintNode::Enum(members),
+        })
+    }
+
+    fn parse_conj(&mut self) -> Result<ConstraintNode, ParseError> {
+        let mut members = vec![self.parse_term()?];
+        while self.eat("&")? {
+            members.push(self.parse_term()?);
+        }
+        Ok(match members.len() {
+            1 => members.pop().unwrap(),
+            _ => ConstraintNode::And(members),
+        })
+    }
+
+    fn parse_term(&mut self) -> Result<ConstraintNode, ParseError> {
+        self.skip_whitespace()?;
+        let start = self.here;
+        if self.eat("(")? {
+            let left = self.parse_expr()?;
+            if self.eat(")")? {
+                return Ok(left);
+            }
+            self.expect(",")?;
+            let right = self.parse_expr()?;
+            self.expect(")")?;
+            return Ok(ConstraintNode::Pair(left.into(), right.into()));
+        }
+        if self.eat("#")? {
+            let tag = self.parse_name()?;
+            self.expect("(")?;
+            let payload = self.parse_expr()?;
+            self.expect(")")?;
+            return Ok(ConstraintNode::TaggedEnum(vec![(tag, payload)]));
+        }
+        if self.eat("{")? {
+            return self.parse_record();
+        }
+        if self.starts_atom() {
+            let atom = self.parse_atom()?;
+            return self.parse_range(Some(atom));
+        }
+        if self.rest().starts_with("..") {
+            return self.parse_range(None);
+        }
+        let name = self.parse_name()?;
+        Ok(match name.as_str() {
+            "T" => ConstraintNode::T,
+            "F" => ConstraintNode::F,
+            "Never" => ConstraintNode::Enum(Vec::new()),
+            "Nil" => ConstraintNode::Leaf(AtomicConstraint::Nil),
+            "NaN" => ConstraintNode::Leaf(AtomicConstraint::LiteralFloat(f64::NAN)),
+            "Int" => ConstraintNode::Kind(Kind::Int),
+            "Float" => ConstraintNode::Kind(Kind::Float),
+            "String" => ConstraintNode::Kind(Kind::String),
+            "Char" => ConstraintNode::Kind(Kind::Char),
+            name => {
+                if self.eat(":=")? {
+                    let body = self.parse_expr()?;
+                    match self.graph.get(name) {
+                        Some(existing) if *existing != body => {
+                            return Err(
+                                self.error(start, format!("definition '{}' is bound twice", name))
+                            );
+                        }
+                        _ => {
+                            self.graph.insert(name.to_string(), body);
+                        }
+                    }
+                } else {
+                    self.references.entry(name.to_string()).or_insert(start);
+                }
+                ConstraintNode::Def(name.to_string())
+            }
+        })
+    }
+
+    /// `{` 之后的字段列表，`=` 之后是字段的默认值
+    fn parse_record(&mut self) -> Result<ConstraintNode, ParseError> {
+        let mut fields: Vec<(String, RecordField)> = Vec::new();
+        if self.eat("}")? {
+            return Ok(ConstraintNode::Record(fields));
+        }
+        loop {
+            self.skip_whitespace()?;
+            let start = self.here;
+            let name = self.parse_name()?;
+            if fields.iter().any(|(field, _)| *field == name) {
+                return Err(self.error(start, format!("field '{}' is declared twice", name)));
+            }
+            self.expect(":")?;
+            let ty = self.parse_expr()?;
+            let default = if self.eat("=")? {
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
+            fields.push((name, RecordField { ty, default }));
+            if !self.eat(",")? {
+                self.expect("}")?;
+                return Ok(ConstraintNode::Record(fields));
+            }
+        }
+    }
+
+    /// 定义名由字母、数字、`_`、`#` 以及后面紧跟字母数字的 `.` 组成
+    fn parse_name(&mut self) -> Result<String, ParseError> {
+        let rest = self.rest();
+        let mut end = 0;
+        let mut chars = rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let continues = c.is_alphanumeric()
+                || c == '_'
+                || (i > 0 && c == '#')
+                || (i > 0 && c == '.' && chars.peek().is_some_and(|(_, n)| n.is_alphanumeric()));
+            if !continues || (i == 0 && c.is_ascii_digit()) {
+                break;
+            }
+            end = i + c.len_utf8();
+        }
+        if end == 0 {
+            return Err(match self.peek() {
+                Some(c) => self.error(self.here, format!("unexpected character '{}'", c)),
+                None => self.error(self.here, "unexpected end of input"),
+            });
+        }
+        let name = rest[..end].to_string();
+        self.advance(end);
+        Ok(name)
+    }
+
+    fn parse_atom(&mut self) -> Result<AtomicConstraint, ParseError> {
+        self.skip_whitespace()?;
+        if self.peek() == Some('"') {
+            return self.parse_quoted('"').map(AtomicConstraint::LiteralString);
+        }
+        if self.peek() == Some('\'') {
+            let start = self.here;
+            let text = self.parse_quoted('\'')?;
+            let mut chars = text.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(AtomicConstraint::LiteralChar(c)),
+                _ => Err(self.error(
+                    start,
+                    "character literal must contain exactly one character",
+                )),
+            };
+        }
+        let start = self.here;
+        let rest = self.rest();
+        let sign = usize::from(rest.starts_with('-'));
+        let digits = rest[sign..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - sign);
+        if digits == 0 {
+            return Err(self.error(start, "expected an integer, string or character literal"));
+        }
+        // 小数部分与指数部分，只有 '.' 后紧跟数字时才是小数点，`1..=2` 中的 '.' 属于区间
+        let digits_at = |at: usize| {
+            rest[at..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - at)
+        };
+        let mut end = sign + digits;
+        if rest[end..].starts_with('.') && digits_at(end + 1) > 0 {
+            end += 1 + digits_at(end + 1);
+        }
+        if rest[end..].starts_with(['e', 'E']) {
+            let exponent_sign = usize::from(rest[end + 1..].starts_with(['-', '+']));
+            let exponent = digits_at(end + 1 + exponent_sign);
+            if exponent > 0 {
+                end += 1 + exponent_sign + exponent;
+            }
+        }
+        let text = &rest[..end];
+        let atom = if end > sign + digits {
+            text.parse()
+                .map(AtomicConstraint::LiteralFloat)
+                .map_err(|_| format!("invalid float literal {}", text))
+        } else {
+            text.parse()
+                .map(AtomicConstraint::LiteralInt)
+                .map_err(|_| format!("integer literal {} is out of range", text))
+        };
+        self.advance(end);
+        atom.map_err(|message| self.error(start, message))
+    }
+
+    /// 以 quote 包围的字符串或字符字面量，两者的转义规则相同
+    fn parse_quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        let start = self.here;
+        let unterminated = match quote {
+            '"' => "unterminated string literal",
+            _ => "unterminated character literal",
+        };
+        self.advance(1); // 开头的引号
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.peek_filled()? else {
+                return Err(self.error(start, unterminated));
+            };
+            let escape_start = self.here;
+            self.advance(c.len_utf8());
+            match c {
+                c if c == quote => return Ok(value),
+                '\\' => {
+                    let Some(escaped) = self.peek_filled()? else {
+                        return Err(self.error(start, unterminated));
+                    };
+                    self.advance(escaped.len_utf8());
+                    match escaped {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        'r' => value.push('\r'),
+                        '"' | '\'' => value.push(escaped),
+                        '\\' => value.push('\\'),
+                        'u' => value.push(self.parse_unicode_escape(escape_start)?),
+                        other => {
+                            return Err(self.error(
+                                escape_start,
+                                format!("unknown escape sequence '\\{}'", other),
+                            ));
+                        }
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// `\u{...}` 中花括号内的十六进制码点
+    fn parse_unicode_escape(&mut self, escape_start: Location) -> Result<char, ParseError> {
+        let invalid = |parser: &Self| parser.error(escape_start, "invalid unicode escape");
+        let rest = self.rest();
+        if !rest.starts_with('{') {
+            return Err(invalid(self));
+        }
+        let Some(close) = rest.find('}') else {
+            return Err(invalid(self));
+        };
+        let code = u32::from_str_radix(&rest[1..close], 16).map_err(|_| invalid(self))?;
+        let c = char::from_u32(code).ok_or_else(|| invalid(self))?;
+        self.advance(close + 1);
+        Ok(c)
+    }
+
+    /// 已读入下界（或没有下界）之后的区间部分；lo 之后没有区间时直接返回字面量
+    fn parse_range(&mut self, lo: Option<AtomicConstraint>) -> Result<ConstraintNode, ParseError> {
+        let lo = match lo {
+            Some(atom) if self.eat("<..")? => Bound::Exclusive(atom),
+            Some(atom) if self.eat("..")? => Bound::Inclusive(atom),
+            Some(atom) => return Ok(ConstraintNode::Leaf(atom)),
+            None => {
+                self.expect("..")?;
+                Bound::Unbounded
+            }
+        };
+        let hi = if self.rest().starts_with('=') {
+            self.advance(1);
+            Bound::Inclusive(self.parse_atom()?)
+        } else if self.starts_atom() {
+            Bound::Exclusive(self.parse_atom()?)
+        } else {
+            Bound::Unbounded
+        };
+        Ok(ConstraintNode::Range { lo, hi })
+    }
+}
+
+/// 解析一个约束表达式
+///
+/// 表达式本身是 `name := ...` 时以 name 为入口，否则入口使用合成的名字
+pub fn parse(src: &str) -> Result<Constraint, ParseError> {
+    parse_buffered(src.as_bytes())
+}
+
+fn parse_buffered(reader: impl BufRead) -> Result<Constraint, ParseError> {
+    let mut parser = Parser {
+        reader,
+        eof: false,
+        buffer: String::new(),
+        pos: 0,
+        here: Location {
+            position: 0,
+            line: 1,
+            column: 1,
+        },
+        graph: HashMap::new(),
+        references: HashMap::new(),
+    };
+    let node = parser.parse_expr()?;
+    parser.skip_whitespace()?;
+    if let Some(c) = parser.peek() {
+        return Err(parser.error(parser.here, format!("unexpected character '{}'", c)));
+    }
+    let mut undefined: Vec<(&String, &Location)> = parser
+        .references
+        .iter()
+        .filter(|(name, _)| !parser.graph.contains_key(*name))
+        .collect();
+    undefined.sort_by_key(|(_, location)| location.position);
+    if let Some((name, location)) = undefined.first() {
+        return Err(parser.error(**location, format!("definition '{}' is not defined", name)));
+    }
+
+    let entry = match &node {
+        ConstraintNode::Def(name) => name.clone(),
+        _ => {
+            let entry = fresh_name(&parser.graph, "Entry");
+            parser.graph.insert(entry.clone(), node);
+            entry
+        }
+    };
+    Ok(Constraint::from_graph(parser.graph, entry))
+}
+
+impl Constraint {
+    /// 见 [`parse`]
+    pub fn parse(src: &str) -> Result<Constraint, ParseError> {
+        parse(src)
+    }
+
+    /// 从 reader 逐行读取并解析，结果与对读到的全部文本调用 [`parse`] 相同
+    ///
+    /// 定义在读到时即加入约束图，已解析的行不会保留，适合从很大的文件加载约束。
+    /// 读取失败或输入不是合法的 UTF-8 时返回 `ParseErrorKind::Io` 类别的错误
+    pub fn parse_reader<R: Read>(reader: R) -> Result<Constraint, ParseError> {
+        parse_buffered(BufReader::new(reader))
+    }
+}
+
+/// 以 [`parse`] 解析文本
+///
+/// ```
+/// use constraint_system::constraint::Constraint;
+///
+/// let list: Constraint = "L_T := (T, L_T) | Nil".parse()?;
+/// let pair: Constraint = "(1, (\"a\", Nil))".parse()?;
+/// assert!(list.super_of(&pair));
+/// assert!(!pair.super_of(&list));
+/// # Ok::<(), constraint_system::parser::ParseError>(())
+/// ```
+impl FromStr for Constraint {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        parse(src)
+    }
+}
+
+impl TryFrom<&str> for Constraint {
+    type Error = ParseError;
+
+    fn try_from(src: &str) -> Result<Self, Self::Error> {
+        parse(src)
+    }
+}
@@ -0,0 +1,65 @@
+//! 按规范形式驻留约束，并缓存约束之间的子类型判定结果
+
+use std::collections::HashMap;
+
+use crate::constraint::Constraint;
+
+/// 约束在池中的编号，规范形式相同的约束共享同一个编号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConstraintId(usize);
+
+/// 适合长期运行的服务：重复提交的约束只保存一份，判定结果按编号对缓存
+///
+/// 约束按 `canonical_key` 去重，它比语义相等的 `==` 更细：规范形式不同的语义相等约束
+/// （例如 `(1 | 2, 3)` 与 `(1, 3) | (2, 3)`）得到不同的编号，它们之间的判定结果
+/// 仍然正确，只是不共享缓存
+#[derive(Debug, Default)]
+pub struct ConstraintPool {
+    constraints: Vec<Constraint>,
+    by_key: HashMap<String, ConstraintId>,
+    pub(crate) results: HashMap<(ConstraintId, ConstraintId), bool>, // a >= b 的判定结果
+}
+
+impl ConstraintPool {
+    pub fn new() -> Self {
+        ConstraintPool::default()
+    }
+
+    /// 加入约束并返回其编号，与已有约束规范形式相同时返回已有的编号
+    pub fn insert(&mut self, constraint: Constraint) -> ConstraintId {
+        let key = constraint.canonical_key();
+        if let Some(id) = self.by_key.get(&key) {
+            return *id;
+        }
+        let id = ConstraintId(self.constraints.len());
+        self.constraints.push(constraint);
+        self.by_key.insert(key, id);
+        id
+    }
+
+    /// 编号对应的约束，编号来自其他池时 panic
+    pub fn get(&self, id: ConstraintId) -> &Constraint {
+        &self.constraints[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.constraints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+
+    /// a >= b，结果会被缓存；同一编号总是成立
+    pub fn super_of(&mut self, a: ConstraintId, b: ConstraintId) -> bool {
+        if a == b {
+            return true;
+        }
+        if let Some(result) = self.results.get(&(a, b)) {
+            return *result;
+        }
+        let result = self.constraints[a.0].super_of(&self.constraints[b.0]);
+        self.results.insert((a, b), result);
+        result
+    }
+}
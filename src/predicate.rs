@@ -0,0 +1,64 @@
+//! 由闭包判定的谓词节点，用于无法用结构表示的约束，例如“偶数”
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{constraint::Constraint, value::Value};
+
+/// 谓词在所属约束的谓词表中的编号，通过 `ConstraintNode::Predicate` 放入约束图
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PredicateId(pub usize);
+
+impl fmt::Display for PredicateId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Predicate#{}", self.0)
+    }
+}
+
+/// 判定一个值是否满足谓词的闭包
+pub type Predicate = Arc<dyn Fn(&Value) -> bool + Send + Sync>;
+
+/// 约束的谓词表，约束的克隆共享同一个表
+#[derive(Clone, Default)]
+pub(crate) struct PredicateRegistry {
+    predicates: HashMap<PredicateId, Predicate>,
+}
+
+impl PredicateRegistry {
+    pub(crate) fn get(&self, id: PredicateId) -> Option<&Predicate> {
+        self.predicates.get(&id)
+    }
+}
+
+impl fmt::Debug for PredicateRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ids: Vec<&PredicateId> = self.predicates.keys().collect();
+        ids.sort();
+        f.debug_set().entries(ids).finish()
+    }
+}
+
+impl Constraint {
+    /// 注册一个谓词，返回可以放入 `ConstraintNode::Predicate` 的编号
+    ///
+    /// 谓词表属于这个约束：`map_defs`、`prune`、`simplify` 等在原图上变换的操作会保留它，
+    /// 把多个约束合并到新图的操作（如 `union_all`）不会，没有注册的谓词不接受任何值。
+    /// 子类型判定中谓词是不透明的：只有 T 包含谓词，谓词只包含 F 与同一个谓词
+    pub fn register_predicate<P>(&mut self, predicate: P) -> PredicateId
+    where
+        P: Fn(&Value) -> bool + Send + Sync + 'static,
+    {
+        let registry = Arc::make_mut(&mut self.predicates);
+        let id = PredicateId(registry.predicates.len());
+        registry.predicates.insert(id, Arc::new(predicate));
+        id
+    }
+
+    /// self 与 other 中的编号 id 是否是同一个谓词，两侧都没有注册时视为相同
+    pub(crate) fn same_predicate(&self, other: &Self, id: PredicateId) -> bool {
+        match (self.predicates.get(id), other.predicates.get(id)) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! 有序原子区间的判定，所有比较都基于 `AtomicConstraint` 的偏序
+//!
+//! 离散原子的开端点会先被规范化为相邻元素的闭端点，因此整数区间 `0<..11` 与 `1..=10` 等价
+
+use std::cmp::Ordering;
+
+use crate::constraint::{AtomicConstraint, Bound, Kind};
+
+/// 规范化后的端点，`None` 表示区间因溢出而为空
+fn normalize_lower(lo: &Bound) -> Option<Bound> {
+    match lo {
+        Bound::Exclusive(atom) if atom.is_discrete() => atom.successor().map(Bound::Inclusive),
+        other => Some(other.clone()),
+    }
+}
+
+fn normalize_upper(hi: &Bound) -> Option<Bound> {
+    match hi {
+        Bound::Exclusive(atom) if atom.is_discrete() => atom.predecessor().map(Bound::Inclusive),
+        other => Some(other.clone()),
+    }
+}
+
+pub fn normalize(lo: &Bound, hi: &Bound) -> Option<(Bound, Bound)> {
+    Some((normalize_lower(lo)?, normalize_upper(hi)?))
+}
+
+fn atom_of(bound: &Bound) -> Option<&AtomicConstraint> {
+    match bound {
+        Bound::Inclusive(atom) | Bound::Exclusive(atom) => Some(atom),
+        Bound::Unbounded => None,
+    }
+}
+
+/// 区间中原子的类别，两端都无界时为 None
+pub fn kind(lo: &Bound, hi: &Bound) -> Option<Kind> {
+    atom_of(lo).or(atom_of(hi)).and_then(AtomicConstraint::kind)
+}
+
+/// 下端点是否允许 atom
+fn lower_admits(lo: &Bound, atom: &AtomicConstraint) -> bool {
+    match lo {
+        Bound::Inclusive(x) => {
+            matches!(x.partial_cmp(atom), Some(Ordering::Less | Ordering::Equal))
+        }
+        Bound::Exclusive(x) => matches!(x.partial_cmp(atom), Some(Ordering::Less)),
+        Bound::Unbounded => true,
+    }
+}
+
+/// 上端点是否允许 atom
+fn upper_admits(hi: &Bound, atom: &AtomicConstraint) -> bool {
+    match hi {
+        Bound::Inclusive(x) => {
+            matches!(
+                x.partial_cmp(atom),
+                Some(Ordering::Greater | Ordering::Equal)
+            )
+        }
+        Bound::Exclusive(x) => matches!(x.partial_cmp(atom), Some(Ordering::Greater)),
+        Bound::Unbounded => true,
+    }
+}
+
+pub fn is_empty(lo: &Bound, hi: &Bound) -> bool {
+    let Some((lo, hi)) = normalize(lo, hi) else {
+        return true;
+    };
+    match (&lo, &hi) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+        (Bound::Inclusive(x), Bound::Inclusive(y)) => {
+            !matches!(x.partial_cmp(y), Some(Ordering::Less | Ordering::Equal))
+        }
+        // 至少一端为开，端点必须严格小于另一端
+        (Bound::Inclusive(x) | Bound::Exclusive(x), Bound::Inclusive(y) | Bound::Exclusive(y)) => {
+            !matches!(x.partial_cmp(y), Some(Ordering::Less))
+        }
+    }
+}
+
+pub fn contains(lo: &Bound, hi: &Bound, atom: &AtomicConstraint) -> bool {
+    if atom.kind().is_none() {
+        return false;
+    }
+    lower_admits(lo, atom) && upper_admits(hi, atom)
+}
+
+/// 区间恰好只含一个原子时返回该原子
+pub fn singleton(lo: &Bound, hi: &Bound) -> Option<AtomicConstraint> {
+    match normalize(lo, hi)? {
+        (Bound::Inclusive(x), Bound::Inclusive(y)) if x == y => Some(x),
+        _ => None,
+    }
+}
+
+/// a 的下端点是否不晚于 b 的下端点
+fn lower_le(a: &Bound, b: &Bound) -> bool {
+    match (a, b) {
+        (Bound::Unbounded, _) => true,
+        (_, Bound::Unbounded) => false,
+        (Bound::Exclusive(x), Bound::Inclusive(y)) => {
+            matches!(x.partial_cmp(y), Some(Ordering::Less))
+        }
+        (Bound::Inclusive(x) | Bound::Exclusive(x), Bound::Inclusive(y) | Bound::Exclusive(y)) => {
+            matches!(x.partial_cmp(y), Some(Ordering::Less | Ordering::Equal))
+        }
+    }
+}
+
+/// a 的上端点是否不早于 b 的上端点
+fn upper_ge(a: &Bound, b: &Bound) -> bool {
+    match (a, b) {
+        (Bound::Unbounded, _) => true,
+        (_, Bound::Unbounded) => false,
+        (Bound::Exclusive(x), Bound::Inclusive(y)) => {
+            matches!(x.partial_cmp(y), Some(Ordering::Greater))
+        }
+        (Bound::Inclusive(x) | Bound::Exclusive(x), Bound::Inclusive(y) | Bound::Exclusive(y)) => {
+            matches!(x.partial_cmp(y), Some(Ordering::Greater | Ordering::Equal))
+        }
+    }
+}
+
+/// 区间 a 是否包含区间 b
+pub fn subsumes(a_lo: &Bound, a_hi: &Bound, b_lo: &Bound, b_hi: &Bound) -> bool {
+    if is_empty(b_lo, b_hi) {
+        return true;
+    }
+    let (Some((a_lo, a_hi)), Some((b_lo, b_hi))) = (normalize(a_lo, a_hi), normalize(b_lo, b_hi))
+    else {
+        return false;
+    };
+    lower_le(&a_lo, &b_lo) && upper_ge(&a_hi, &b_hi)
+}
+
+/// 两个区间的交集，两者的原子类别不同时返回 None
+pub fn intersection(
+    a_lo: &Bound,
+    a_hi: &Bound,
+    b_lo: &Bound,
+    b_hi: &Bound,
+) -> Option<(Bound, Bound)> {
+    if let (Some(a_kind), Some(b_kind)) = (kind(a_lo, a_hi), kind(b_lo, b_hi))
+        && a_kind != b_kind
+    {
+        return None;
+    }
+    let (a_lo, a_hi) = normalize(a_lo, a_hi)?;
+    let (b_lo, b_hi) = normalize(b_lo, b_hi)?;
+    let lo = if lower_le(&a_lo, &b_lo) { b_lo } else { a_lo };
+    let hi = if upper_ge(&a_hi, &b_hi) { b_hi } else { a_hi };
+    Some((lo, hi))
+}
+
+/// a 的上端点与 b 的下端点之间是否没有空隙，端点相同时至少一侧须包含端点
+fn reaches(a_hi: &Bound, b_lo: &Bound) -> bool {
+    match (a_hi, b_lo) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => true,
+        (Bound::Exclusive(x), Bound::Exclusive(y)) => {
+            matches!(x.partial_cmp(y), Some(Ordering::Greater))
+        }
+        (Bound::Inclusive(x) | Bound::Exclusive(x), Bound::Inclusive(y) | Bound::Exclusive(y)) => {
+            matches!(x.partial_cmp(y), Some(Ordering::Greater | Ordering::Equal))
+        }
+    }
+}
+
+/// 两个非空区间的并是否仍是一个区间，即两者重叠或在一个被包含的端点处相接
+///
+/// 离散原子只比较规范化后的端点，`1..=2` 与 `3..=4` 这样相邻而不重叠的区间不算相接
+pub fn connected(a_lo: &Bound, a_hi: &Bound, b_lo: &Bound, b_hi: &Bound) -> bool {
+    let (Some((a_lo, a_hi)), Some((b_lo, b_hi))) = (normalize(a_lo, a_hi), normalize(b_lo, b_hi))
+    else {
+        return false;
+    };
+    reaches(&a_hi, &b_lo) && reaches(&b_hi, &a_lo)
+}
+
+/// 同时覆盖两个区间的最小区间，两者的原子类别不同时返回 None
+pub fn span(a_lo: &Bound, a_hi: &Bound, b_lo: &Bound, b_hi: &Bound) -> Option<(Bound, Bound)> {
+    if let (Some(a_kind), Some(b_kind)) = (kind(a_lo, a_hi), kind(b_lo, b_hi))
+        && a_kind != b_kind
+    {
+        return None;
+    }
+    let (a_lo, a_hi) = normalize(a_lo, a_hi)?;
+    let (b_lo, b_hi) = normalize(b_lo, b_hi)?;
+    let lo = if lower_le(&a_lo, &b_lo) { a_lo } else { b_lo };
+    let hi = if upper_ge(&a_hi, &b_hi) { a_hi } else { b_hi };
+    Some((lo, hi))
+}
@@ -0,0 +1,253 @@
+//! 导出为 JSON Schema，用标准工具按约束校验外部 JSON
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde_json::{Map, Value, json};
+
+use crate::{
+    constraint::{AtomicConstraint, Bound, Constraint, ConstraintNode, Kind},
+    simplify::OccursCheck,
+};
+
+/// 导出过程的状态：递归定义导出到 `$defs`，pending 是已引用但尚未导出的定义；
+/// 非递归定义就地展开，occurs-check 失败时改为导出到 `$defs`
+struct SchemaBuilder<'a> {
+    constraint: &'a Constraint,
+    recursive: HashSet<String>,
+    defs: BTreeMap<String, Value>,
+    pending: Vec<String>,
+    check: OccursCheck,
+}
+
+/// 形如 `L := (X, L) | Nil` 的同构列表定义体，返回元素 X
+fn list_element<'a>(name: &str, body: &'a ConstraintNode) -> Option<&'a ConstraintNode> {
+    let ConstraintNode::Enum(members) = body.strip_annotations() else {
+        return None;
+    };
+    let [first, second] = members.as_slice() else {
+        return None;
+    };
+    let (pair, nil) = match first.strip_annotations() {
+        ConstraintNode::Pair(..) => (first, second),
+        _ => (second, first),
+    };
+    let ConstraintNode::Leaf(AtomicConstraint::Nil) = nil.strip_annotations() else {
+        return None;
+    };
+    match pair.strip_annotations() {
+        ConstraintNode::Pair(element, tail) if matches!(tail.strip_annotations(), ConstraintNode::Def(tail) if tail == name) => {
+            Some(element)
+        }
+        _ => None,
+    }
+}
+
+/// 字面量对应的 JSON 值，字符没有对应的 JSON 值
+fn literal(atom: &AtomicConstraint) -> Option<Value> {
+    match atom {
+        AtomicConstraint::LiteralInt(i) => Some(json!(i)),
+        AtomicConstraint::LiteralString(s) => Some(json!(s)),
+        AtomicConstraint::LiteralFloat(x) => Some(json!(x)),
+        AtomicConstraint::Nil | AtomicConstraint::LiteralChar(_) => None,
+    }
+}
+
+impl SchemaBuilder<'_> {
+    fn node(&mut self, node: &ConstraintNode) -> Value {
+        match node.strip_annotations() {
+            // 谓词与自定义约束无法表达，放宽为任意值
+            ConstraintNode::T | ConstraintNode::Custom(_) | ConstraintNode::Predicate(_) => {
+                json!(true)
+            }
+            // JSON 中没有字符与带标签的值
+            ConstraintNode::F | ConstraintNode::TaggedEnum(_) => json!(false),
+            // null 与空数组都转换为 Nil
+            ConstraintNode::Leaf(AtomicConstraint::Nil) => json!({ "enum": [null, []] }),
+            ConstraintNode::Leaf(atom) => match literal(atom) {
+                Some(value) => json!({ "const": value }),
+                None => json!(false),
+            },
+            ConstraintNode::Kind(kind) => match kind {
+                Kind::Int => json!({ "type": "integer" }),
+                Kind::Float => json!({ "type": "number" }),
+                Kind::String => json!({ "type": "string" }),
+                Kind::Char => json!(false),
+            },
+            ConstraintNode::Range { lo, hi } => self.range(lo, hi),
+            ConstraintNode::IntSet(set) => json!({ "enum": set.iter().collect::<Vec<_>>() }),
+            ConstraintNode::Enum(members) => {
+                let literals: Option<Vec<Value>> = members
+                    .iter()
+                    .map(|member| match member.strip_annotations() {
+                        ConstraintNode::Leaf(atom) => literal(atom),
+                        _ => None,
+                    })
+                    .collect();
+                match literals {
+                    Some(literals) => json!({ "enum": literals }),
+                    None => json!({
+                        "anyOf": members.iter().map(|member| self.node(member)).collect::<Vec<_>>()
+                    }),
+                }
+            }
+            ConstraintNode::And(members) => json!({
+                "allOf": members.iter().map(|member| self.node(member)).collect::<Vec<_>>()
+            }),
+            ConstraintNode::Pair(..) => self.array(node),
+            // 有默认值的字段不是必需的，默认值本身无法导出为 JSON 值
+            ConstraintNode::Record(fields) => {
+                let properties: Map<String, Value> = fields
+                    .iter()
+                    .map(|(name, field)| (name.clone(), self.node(&field.ty)))
+                    .collect();
+                let required: Vec<&String> = fields
+                    .iter()
+                    .filter(|(_, field)| field.default.is_none())
+                    .map(|(name, _)| name)
+                    .collect();
+                json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                    "additionalProperties": false,
+                })
+            }
+            ConstraintNode::Def(name) => {
+                let Some(body) = self.constraint.get_node(name) else {
+                    return json!(false);
+                };
+                if !self.recursive.contains(name) && self.check.enter(name) {
+                    let schema = self.node(body);
+                    self.check.exit();
+                    return schema;
+                }
+                if !self.defs.contains_key(name) && !self.pending.contains(name) {
+                    self.pending.push(name.clone());
+                }
+                json!({ "$ref": format!("#/$defs/{}", name) })
+            }
+            ConstraintNode::Annotated(..) => unreachable!(),
+        }
+    }
+
+    fn range(&mut self, lo: &Bound, hi: &Bound) -> Value {
+        let atom = match (lo, hi) {
+            (Bound::Inclusive(atom) | Bound::Exclusive(atom), _)
+            | (_, Bound::Inclusive(atom) | Bound::Exclusive(atom)) => atom,
+            (Bound::Unbounded, Bound::Unbounded) => return json!(true),
+        };
+        let mut schema = Map::new();
+        match atom {
+            AtomicConstraint::LiteralInt(_) => {
+                schema.insert("type".to_string(), json!("integer"));
+            }
+            AtomicConstraint::LiteralFloat(_) => {
+                schema.insert("type".to_string(), json!("number"));
+            }
+            // 字符串区间按字典序比较，JSON Schema 无法表达，放宽为全体字符串
+            AtomicConstraint::LiteralString(_) => return json!({ "type": "string" }),
+            AtomicConstraint::Nil | AtomicConstraint::LiteralChar(_) => return json!(false),
+        }
+        for (bound, inclusive, exclusive) in [
+            (lo, "minimum", "exclusiveMinimum"),
+            (hi, "maximum", "exclusiveMaximum"),
+        ] {
+            let (keyword, atom) = match bound {
+                Bound::Inclusive(atom) => (inclusive, atom),
+                Bound::Exclusive(atom) => (exclusive, atom),
+                Bound::Unbounded => continue,
+            };
+            if let Some(value) = literal(atom) {
+                schema.insert(keyword.to_string(), value);
+            }
+        }
+        Value::Object(schema)
+    }
+
+    /// Pair 链导出为数组：前缀逐项约束，结尾的 Nil 限制长度，
+    /// 同构列表的尾部约束其余元素，其他尾部放宽为任意元素
+    fn array(&mut self, node: &ConstraintNode) -> Value {
+        let mut prefix = Vec::new();
+        let mut tail = node.strip_annotations();
+        while let ConstraintNode::Pair(head, rest) = tail {
+            prefix.push(self.node(head));
+            tail = rest.strip_annotations();
+        }
+        let mut schema = Map::new();
+        schema.insert("type".to_string(), json!("array"));
+        schema.insert("minItems".to_string(), json!(prefix.len()));
+        schema.insert("prefixItems".to_string(), Value::Array(prefix));
+        match tail {
+            ConstraintNode::Leaf(AtomicConstraint::Nil) => {
+                schema.insert("items".to_string(), json!(false));
+            }
+            ConstraintNode::Def(name) => {
+                if let Some(element) = self
+                    .constraint
+                    .get_node(name)
+                    .and_then(|body| list_element(name, body))
+                {
+                    let items = self.node(element);
+                    schema.insert("items".to_string(), items);
+                }
+            }
+            _ => {}
+        }
+        Value::Object(schema)
+    }
+
+    fn def(&mut self, name: &str) -> Value {
+        let body = self.constraint.get_node(name).unwrap();
+        match list_element(name, body) {
+            Some(element) => {
+                let items = self.node(element);
+                json!({
+                    "anyOf": [{ "type": "array", "items": items }, { "type": "null" }]
+                })
+            }
+            None => self.node(body),
+        }
+    }
+}
+
+impl Constraint {
+    /// 导出为 JSON Schema（draft 2020-12），值与 JSON 的对应关系同 `Value::from_json`
+    ///
+    /// 类别导出为 `type`，整数区间导出为 `minimum`/`maximum`，字面量枚举导出为 `enum`，
+    /// 其他枚举导出为 `anyOf`，Pair 链导出为数组，记录导出为对象，递归定义导出到 `$defs`
+    /// 并以 `$ref` 引用。
+    /// 谓词、自定义约束等无法表达的部分放宽为任意值，因此导出的 schema 可能比约束宽
+    pub fn to_json_schema(&self) -> Value {
+        let mut builder = SchemaBuilder {
+            constraint: self,
+            recursive: self.recursive_defs(),
+            defs: BTreeMap::new(),
+            pending: Vec::new(),
+            check: OccursCheck::default(),
+        };
+        let mut schema = match self.get_node(&self.entry) {
+            Some(_) if builder.recursive.contains(&self.entry) => {
+                builder.node(&ConstraintNode::Def(self.entry.clone()))
+            }
+            Some(body) => builder.node(body),
+            None => json!(false),
+        };
+        while let Some(name) = builder.pending.pop() {
+            // 先占位，定义体中对自身的引用不会再次加入 pending
+            builder.defs.insert(name.clone(), Value::Null);
+            let def = builder.def(&name);
+            builder.defs.insert(name, def);
+        }
+        if !builder.defs.is_empty() {
+            let defs = Value::Object(builder.defs.into_iter().collect());
+            schema = match schema {
+                Value::Object(mut object) => {
+                    object.insert("$defs".to_string(), defs);
+                    Value::Object(object)
+                }
+                other => json!({ "allOf": [other], "$defs": defs }),
+            };
+        }
+        schema
+    }
+}
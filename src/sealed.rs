@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::{
+    coercion::CoercionTable,
+    constraint::{Constraint, ConstraintNode},
+    error::ConstraintError,
+};
+
+/// 构建完成、不可再修改的约束
+///
+/// 封存后不再暴露 `add_node`，克隆只会增加内部 `Arc` 的引用计数，
+/// 因此可以放心地在线程之间共享
+#[derive(Debug, Clone)]
+pub struct SealedConstraint {
+    inner: Constraint,
+}
+
+impl Constraint {
+    /// 结束构建阶段，得到只读的约束
+    pub fn seal(self) -> SealedConstraint {
+        SealedConstraint { inner: self }
+    }
+}
+
+impl SealedConstraint {
+    pub fn get_node(&self, name: &str) -> Option<&ConstraintNode> {
+        self.inner.get_node(name)
+    }
+
+    pub fn entry(&self) -> &String {
+        self.inner.entry()
+    }
+
+    pub fn super_of(&self, other: &Self) -> bool {
+        self.inner.super_of(&other.inner)
+    }
+
+    pub fn assignable_to(&self, target: &Self, coercions: &CoercionTable) -> bool {
+        self.inner.assignable_to(&target.inner, coercions)
+    }
+
+    pub fn refine(&self, v: &Self) -> Self {
+        self.inner.refine(&v.inner).seal()
+    }
+
+    pub fn try_super_of(&self, other: &Self) -> Result<bool, ConstraintError> {
+        self.inner.try_super_of(&other.inner)
+    }
+
+    pub fn try_assignable_to(
+        &self,
+        target: &Self,
+        coercions: &CoercionTable,
+    ) -> Result<bool, ConstraintError> {
+        self.inner.try_assignable_to(&target.inner, coercions)
+    }
+
+    pub fn try_refine(&self, v: &Self) -> Result<Self, ConstraintError> {
+        self.inner.try_refine(&v.inner).map(Constraint::seal)
+    }
+
+    /// 以普通约束的只读视图访问，便于与未封存的约束比较
+    pub fn as_constraint(&self) -> &Constraint {
+        &self.inner
+    }
+}
+
+impl fmt::Display for SealedConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl PartialEq for SealedConstraint {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
@@ -0,0 +1,231 @@
+//! 约束在最外层可能产生的值的形状，用于模式匹配的代码生成等场景
+
+use std::collections::HashSet;
+
+use crate::{
+    constraint::{AtomicConstraint, Constraint, ConstraintNode, Kind, is_expanded},
+    range,
+};
+
+/// 值最外层的构造子
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HeadConstructor {
+    Top, // 任意值，约束本身没有限制最外层的形状
+    Nil,
+    IntLit(i32),
+    StringLit(String),
+    CharLit(char),
+    FloatLit(u64), // 浮点字面量的位表示，f64 本身不满足 Eq 与 Hash
+    Kind(Kind),
+    Range(Option<Kind>), // 区间中原子的类别，两端都无界时为 None
+    Pair,
+    Tagged(String),
+    Record,
+    Custom,
+    Predicate, // 谓词可以接受任何形状的值
+}
+
+/// 字面量构造子所属的类别
+fn literal_kind(head: &HeadConstructor) -> Option<Kind> {
+    match head {
+        HeadConstructor::IntLit(_) => Some(Kind::Int),
+        HeadConstructor::StringLit(_) => Some(Kind::String),
+        HeadConstructor::CharLit(_) => Some(Kind::Char),
+        HeadConstructor::FloatLit(_) => Some(Kind::Float),
+        _ => None,
+    }
+}
+
+/// 同时具有构造子 a 与 b 的值的构造子，不可能同时具有时为 None
+///
+/// 与 `head_constructors` 一样是上近似：例如字面量与同类别的区间的交取字面量本身，
+/// 不检查字面量是否落在区间内
+fn meet(a: &HeadConstructor, b: &HeadConstructor) -> Option<HeadConstructor> {
+    use HeadConstructor::*;
+    match (a, b) {
+        (Top | Predicate, other) | (other, Top | Predicate) => Some(other.clone()),
+        _ if a == b => Some(a.clone()),
+        (Kind(kind) | Range(Some(kind)), literal) | (literal, Kind(kind) | Range(Some(kind)))
+            if literal_kind(literal) == Some(*kind) =>
+        {
+            Some(literal.clone())
+        }
+        (Kind(kind), Range(Some(other))) | (Range(Some(other)), Kind(kind)) if kind == other => {
+            Some(Range(Some(*kind)))
+        }
+        // 两端都无界的区间可以与任何类别的原子相交
+        (
+            Range(None),
+            other @ (Kind(_) | Range(_) | IntLit(_) | StringLit(_) | CharLit(_) | FloatLit(_)),
+        )
+        | (
+            other @ (Kind(_) | Range(_) | IntLit(_) | StringLit(_) | CharLit(_) | FloatLit(_)),
+            Range(None),
+        ) => Some(other.clone()),
+        _ => None,
+    }
+}
+
+impl Constraint {
+    /// 入口可能产生的所有最外层构造子
+    ///
+    /// 展开入口经过的 Def 与 Enum，直到遇到第一个构造子为止；每个定义只展开一次，
+    /// 不经过构造子的递归不贡献任何构造子。例如列表约束返回 `{Pair, Nil}`
+    pub fn head_constructors(&self) -> HashSet<HeadConstructor> {
+        fn collect<'a>(
+            constraint: &'a Constraint,
+            node: &'a ConstraintNode,
+            expanded: &mut HashSet<&'a str>,
+            heads: &mut HashSet<HeadConstructor>,
+        ) {
+            let head = match node.strip_annotations() {
+                ConstraintNode::T => HeadConstructor::Top,
+                ConstraintNode::F => return,
+                ConstraintNode::Leaf(AtomicConstraint::Nil) => HeadConstructor::Nil,
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)) => {
+                    HeadConstructor::IntLit(*i)
+                }
+                ConstraintNode::Leaf(AtomicConstraint::LiteralString(s)) => {
+                    HeadConstructor::StringLit(s.clone())
+                }
+                ConstraintNode::Leaf(AtomicConstraint::LiteralChar(c)) => {
+                    HeadConstructor::CharLit(*c)
+                }
+                // 加 0.0 使 -0.0 与 0.0 得到相同的位表示
+                ConstraintNode::Leaf(AtomicConstraint::LiteralFloat(x)) => {
+                    HeadConstructor::FloatLit((x + 0.0).to_bits())
+                }
+                ConstraintNode::Kind(kind) => HeadConstructor::Kind(*kind),
+                ConstraintNode::Range { lo, hi } => {
+                    if range::is_empty(lo, hi) {
+                        return;
+                    }
+                    HeadConstructor::Range(range::kind(lo, hi))
+                }
+                ConstraintNode::IntSet(set) => {
+                    heads.extend(set.iter().map(|i| HeadConstructor::IntLit(*i)));
+                    return;
+                }
+                ConstraintNode::Pair(..) => HeadConstructor::Pair,
+                ConstraintNode::Record(_) => HeadConstructor::Record,
+                ConstraintNode::TaggedEnum(members) => {
+                    heads.extend(
+                        members
+                            .iter()
+                            .map(|(tag, _)| HeadConstructor::Tagged(tag.clone())),
+                    );
+                    return;
+                }
+                ConstraintNode::Custom(_) => HeadConstructor::Custom,
+                // 交集的构造子是各成员构造子两两的交，没有公共构造子时交集为空
+                ConstraintNode::And(nodes) => {
+                    let mut common = HashSet::from([HeadConstructor::Top]);
+                    for node in nodes {
+                        if is_expanded(node, expanded) {
+                            continue;
+                        }
+                        let mut member = HashSet::new();
+                        collect(constraint, node, &mut expanded.clone(), &mut member);
+                        common = common
+                            .iter()
+                            .flat_map(|a| member.iter().filter_map(|b| meet(a, b)))
+                            .collect();
+                    }
+                    heads.extend(common);
+                    return;
+                }
+                ConstraintNode::Predicate(_) => HeadConstructor::Predicate,
+                ConstraintNode::Enum(nodes) => {
+                    for node in nodes {
+                        collect(constraint, node, expanded, heads);
+                    }
+                    return;
+                }
+                ConstraintNode::Def(name) => {
+                    if let Some(body) = constraint.get_node(name)
+                        && expanded.insert(name)
+                    {
+                        collect(constraint, body, expanded, heads);
+                    }
+                    return;
+                }
+                ConstraintNode::Annotated(..) => unreachable!("annotations are stripped"),
+            };
+            heads.insert(head);
+        }
+        let mut heads = HashSet::new();
+        if let Some(entry) = self.get_node(self.entry()) {
+            collect(self, entry, &mut HashSet::new(), &mut heads);
+        }
+        heads
+    }
+
+    /// 约束的所有值是否都具有同一种最外层构造子 head，例如都是 Pair
+    ///
+    /// 即 `head_constructors` 恰好为 `{head}`；不接受任何值的约束不满足任何形状
+    pub fn is_uniformly(&self, head: HeadConstructor) -> bool {
+        let heads = self.head_constructors();
+        heads.len() == 1 && heads.contains(&head)
+    }
+
+    /// 约束是否平凡地等于 T：入口经过 Def 与 Enum 展开后能到达 T
+    ///
+    /// 与 `head_constructors` 一样每个定义只展开一次，因此循环的 Def 也能终止。
+    /// 这是语法上的判定，由多个成员拼出全集的 Enum 不在此列，见 `is_top_closed`
+    pub fn is_top(&self) -> bool {
+        self.head_constructors().contains(&HeadConstructor::Top)
+    }
+
+    /// 封闭世界中约束是否等于全集，即是否接受 universe 中的所有值
+    pub fn is_top_closed(&self, universe: &[ConstraintNode]) -> bool {
+        self.is_top() || self.super_of_closed(&Constraint::top(), universe)
+    }
+
+    /// 入口的定义体；定义体本身是对另一个定义的引用时跟随这一次引用，
+    /// 外层的注释都会被去除
+    pub fn entry_node(&self) -> Option<&ConstraintNode> {
+        let node = self.get_node(self.entry())?.strip_annotations();
+        match node {
+            ConstraintNode::Def(name) => self
+                .get_node(name)
+                .map(ConstraintNode::strip_annotations)
+                .or(Some(node)),
+            other => Some(other),
+        }
+    }
+
+    /// `entry_node` 存在且满足 predicate
+    pub fn entry_is(&self, predicate: impl Fn(&ConstraintNode) -> bool) -> bool {
+        self.entry_node().is_some_and(predicate)
+    }
+
+    /// `entry_node` 是否为枚举，与下面几个判定一样只看结构，不做语义判定
+    pub fn is_enum(&self) -> bool {
+        self.entry_is(|node| matches!(node, ConstraintNode::Enum(_)))
+    }
+
+    /// `entry_node` 是否为 Pair
+    pub fn is_pair(&self) -> bool {
+        self.entry_is(|node| matches!(node, ConstraintNode::Pair(..)))
+    }
+
+    /// `entry_node` 是否为字面量
+    pub fn is_leaf(&self) -> bool {
+        self.entry_is(|node| matches!(node, ConstraintNode::Leaf(_)))
+    }
+
+    /// `entry_node` 仍是引用，即入口经过两层以上的别名，或引用了未定义的定义
+    pub fn is_def(&self) -> bool {
+        self.entry_is(|node| matches!(node, ConstraintNode::Def(_)))
+    }
+
+    /// `entry_node` 是否就是 T，语义上的判定见 `is_top`
+    pub fn is_t(&self) -> bool {
+        self.entry_is(|node| matches!(node, ConstraintNode::T))
+    }
+
+    /// `entry_node` 是否就是 F
+    pub fn is_f(&self) -> bool {
+        self.entry_is(|node| matches!(node, ConstraintNode::F))
+    }
+}
@@ -0,0 +1,871 @@
+//! 保持语义不变的化简步骤，以及把它们组合起来的 `simplify`
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    constraint::{
+        AtomicConstraint, Bound, Checker, Constraint, ConstraintNode, Kind, NodeDisplay,
+        int_set_parts,
+    },
+    error::ConstraintError,
+    range,
+    union::{fresh_name, widen_members},
+};
+
+const SIMPLIFY_MAX_STEPS: usize = 16; // simplify 最多迭代的轮数
+const HASH_UNFOLD_DEPTH: usize = 3; // 计算哈希时沿 Pair 的分量投影深入的最大层数
+const INT_SET_MIN_MEMBERS: usize = 32; // 枚举中至少有这么多个整数字面量时合并为整数集合
+
+/// 内联时的 occurs-check：记录正在内联的定义，再次遇到其中之一说明递归检测出错，
+/// 继续内联将不会终止。发现后不再内联，由调用方报告 `ConstraintError::RecursionDetected`
+#[derive(Debug, Default)]
+pub(crate) struct OccursCheck {
+    inlining: Vec<String>,
+    detected: Option<String>,
+}
+
+impl OccursCheck {
+    /// 开始内联 name，返回 false 时不应内联
+    pub(crate) fn enter(&mut self, name: &str) -> bool {
+        if self.detected.is_some() {
+            return false;
+        }
+        if self.inlining.iter().any(|inlining| inlining == name) {
+            self.detected = Some(name.to_string());
+            return false;
+        }
+        self.inlining.push(name.to_string());
+        true
+    }
+
+    pub(crate) fn exit(&mut self) {
+        self.inlining.pop();
+    }
+
+    pub(crate) fn finish(self) -> Result<(), ConstraintError> {
+        match self.detected {
+            Some(name) => Err(ConstraintError::RecursionDetected(name)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_f(node: &ConstraintNode) -> bool {
+    matches!(node.strip_annotations(), ConstraintNode::F)
+}
+
+/// 单个节点的局部归约：展平嵌套的枚举与交、去重、吸收 T 与 F、规范化区间，
+/// 把大量的整数字面量合并为整数集合
+pub(crate) fn reduce_node(node: &ConstraintNode) -> ConstraintNode {
+    match node {
+        ConstraintNode::IntSet(set) => match set.len() {
+            0 => ConstraintNode::F,
+            1 => ConstraintNode::Leaf(AtomicConstraint::LiteralInt(*set.first().unwrap())),
+            _ => node.clone(),
+        },
+        ConstraintNode::Annotated(inner, metadata) => {
+            ConstraintNode::Annotated(reduce_node(inner).into(), metadata.clone())
+        }
+        ConstraintNode::Range { lo, hi } => {
+            if range::is_empty(lo, hi) {
+                return ConstraintNode::F;
+            }
+            if let Some(atom) = range::singleton(lo, hi) {
+                return ConstraintNode::Leaf(atom);
+            }
+            match range::normalize(lo, hi) {
+                Some((lo, hi)) => ConstraintNode::Range { lo, hi },
+                None => ConstraintNode::F,
+            }
+        }
+        ConstraintNode::Pair(left, right) => {
+            let left = reduce_node(left);
+            let right = reduce_node(right);
+            if is_f(&left) || is_f(&right) {
+                ConstraintNode::F
+            } else {
+                ConstraintNode::Pair(left.into(), right.into())
+            }
+        }
+        ConstraintNode::Enum(nodes) => {
+            let mut members: Vec<ConstraintNode> = Vec::new();
+            for node in nodes {
+                match reduce_node(node) {
+                    ConstraintNode::Enum(inner) => members.extend(inner),
+                    ConstraintNode::F => {}
+                    other => members.push(other),
+                }
+            }
+            if members
+                .iter()
+                .any(|member| matches!(member.strip_annotations(), ConstraintNode::T))
+            {
+                return ConstraintNode::T;
+            }
+            let members = collect_int_set(members);
+            let mut unique: Vec<ConstraintNode> = Vec::new();
+            for member in members {
+                if !unique.contains(&member) {
+                    unique.push(member);
+                }
+            }
+            match unique.len() {
+                0 => ConstraintNode::F,
+                1 => unique.pop().unwrap(),
+                _ => ConstraintNode::Enum(unique),
+            }
+        }
+        ConstraintNode::And(nodes) => {
+            // 展平嵌套的交，去除 T 与重复的成员，含 F 的交为 F
+            let mut members: Vec<ConstraintNode> = Vec::new();
+            for node in nodes {
+                match reduce_node(node) {
+                    ConstraintNode::And(inner) => members.extend(inner),
+                    ConstraintNode::T => {}
+                    other => members.push(other),
+                }
+            }
+            if members.iter().any(is_f) {
+                return ConstraintNode::F;
+            }
+            let mut unique: Vec<ConstraintNode> = Vec::new();
+            for member in members {
+                if !unique.contains(&member) {
+                    unique.push(member);
+                }
+            }
+            match unique.len() {
+                0 => ConstraintNode::T,
+                1 => unique.pop().unwrap(),
+                _ => ConstraintNode::And(unique),
+            }
+        }
+        ConstraintNode::TaggedEnum(members) => {
+            // 载荷为 F 的成员不接受任何值，重复的成员只保留一个
+            let mut unique: Vec<(String, ConstraintNode)> = Vec::new();
+            for (tag, payload) in members {
+                let member = (tag.clone(), reduce_node(payload));
+                if !is_f(&member.1) && !unique.contains(&member) {
+                    unique.push(member);
+                }
+            }
+            match unique.len() {
+                0 => ConstraintNode::F,
+                _ => ConstraintNode::TaggedEnum(unique),
+            }
+        }
+        ConstraintNode::Record(_) => {
+            // 必须出现的字段不接受任何值时，整个记录不接受任何值
+            let reduced = node.map_children(reduce_node);
+            match &reduced {
+                ConstraintNode::Record(fields)
+                    if fields
+                        .iter()
+                        .any(|(_, field)| field.default.is_none() && is_f(&field.ty)) =>
+                {
+                    ConstraintNode::F
+                }
+                _ => reduced,
+            }
+        }
+        other => other.clone(),
+    }
+}
+
+/// 枚举中已有整数集合，或整数字面量至少有 INT_SET_MIN_MEMBERS 个时，把它们合并为
+/// 一个整数集合，放在第一个整数的位置
+fn collect_int_set(members: Vec<ConstraintNode>) -> Vec<ConstraintNode> {
+    let literal = |member: &ConstraintNode| match member {
+        ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)) => Some(*i),
+        _ => None,
+    };
+    let has_set = members
+        .iter()
+        .any(|member| matches!(member, ConstraintNode::IntSet(_)));
+    if !has_set && members.iter().filter_map(literal).count() < INT_SET_MIN_MEMBERS {
+        return members;
+    }
+    let mut set = BTreeSet::new();
+    let mut position = None;
+    let mut others = Vec::new();
+    for member in members {
+        match member {
+            ConstraintNode::IntSet(ints) => set.extend(ints),
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)) => {
+                set.insert(i);
+            }
+            other => {
+                others.push(other);
+                continue;
+            }
+        }
+        position.get_or_insert(others.len());
+    }
+    others.insert(position.unwrap(), reduce_node(&ConstraintNode::IntSet(set)));
+    others
+}
+
+/// 去除定义 name 的定义体中直接是 `Def(name)` 的枚举成员，例如把 `A := A | Nil` 归约为
+/// `A := Nil`
+///
+/// 不经过任何构造子的自引用不产生新的值，但余归纳的判定会用假设闭合它，使这样的成员
+/// 接受任意值，去除后才与预期的含义一致。经过 Pair 等构造子的引用是真正的递归，保持不变
+fn drop_self_members(name: &str, node: &ConstraintNode) -> ConstraintNode {
+    let is_self = |node: &ConstraintNode| matches!(node.strip_annotations(), ConstraintNode::Def(def) if def == name);
+    match node {
+        ConstraintNode::Annotated(inner, metadata) => {
+            ConstraintNode::Annotated(drop_self_members(name, inner).into(), metadata.clone())
+        }
+        ConstraintNode::Enum(members) => ConstraintNode::Enum(
+            members
+                .iter()
+                .filter(|member| !is_self(member))
+                .cloned()
+                .collect(),
+        ),
+        node if is_self(node) => ConstraintNode::F,
+        other => other.clone(),
+    }
+}
+
+/// 整数成员对应的闭区间，无界一侧用 i64 的极值表示
+pub(crate) fn int_interval(node: &ConstraintNode) -> Option<(i64, i64)> {
+    match node {
+        ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)) => Some((*i as i64, *i as i64)),
+        ConstraintNode::Range { lo, hi } => {
+            let lo = match lo {
+                Bound::Inclusive(AtomicConstraint::LiteralInt(i)) => *i as i64,
+                Bound::Exclusive(AtomicConstraint::LiteralInt(i)) => *i as i64 + 1,
+                Bound::Unbounded => i64::MIN,
+                _ => return None,
+            };
+            let hi = match hi {
+                Bound::Inclusive(AtomicConstraint::LiteralInt(i)) => *i as i64,
+                Bound::Exclusive(AtomicConstraint::LiteralInt(i)) => *i as i64 - 1,
+                Bound::Unbounded => i64::MAX,
+                _ => return None,
+            };
+            // 两端都无界的区间不专属于整数
+            if lo == i64::MIN && hi == i64::MAX {
+                return None;
+            }
+            Some((lo, hi))
+        }
+        _ => None,
+    }
+}
+
+/// 闭区间对应的节点：端点先截断到 i32 的取值范围，取到 i32 极值的一端视为无界，
+/// 两端都无界就是整个整数类别，截断后为空的区间是 F
+pub(crate) fn interval_node((lo, hi): (i64, i64)) -> ConstraintNode {
+    let (lo, hi) = (lo.max(i32::MIN as i64), hi.min(i32::MAX as i64));
+    if lo > hi {
+        return ConstraintNode::F;
+    }
+    if lo == hi {
+        return ConstraintNode::Leaf(AtomicConstraint::LiteralInt(lo as i32));
+    }
+    let bound = |value: i64, extreme: i32| {
+        if value == extreme as i64 {
+            Bound::Unbounded
+        } else {
+            Bound::Inclusive(AtomicConstraint::LiteralInt(value as i32))
+        }
+    };
+    match (bound(lo, i32::MIN), bound(hi, i32::MAX)) {
+        (Bound::Unbounded, Bound::Unbounded) => ConstraintNode::Kind(Kind::Int),
+        (lo, hi) => ConstraintNode::Range { lo, hi },
+    }
+}
+
+/// 浮点字面量与浮点区间对应的区间
+fn float_interval(node: &ConstraintNode) -> Option<(Bound, Bound)> {
+    match node {
+        ConstraintNode::Leaf(atom @ AtomicConstraint::LiteralFloat(_)) => Some((
+            Bound::Inclusive(atom.clone()),
+            Bound::Inclusive(atom.clone()),
+        )),
+        ConstraintNode::Range { lo, hi } if range::kind(lo, hi) == Some(Kind::Float) => {
+            Some((lo.clone(), hi.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// 把重叠或在被包含的端点处相接的浮点区间合并，`0.0..1.0 | 1.0..=2.0` 合并为 `0.0..=2.0`，
+/// 两侧都不含端点的 `0.0..1.0 | 1.0<..=2.0` 保持不变
+fn merge_float_intervals(mut intervals: Vec<(Bound, Bound)>) -> Vec<ConstraintNode> {
+    intervals.retain(|(lo, hi)| !range::is_empty(lo, hi));
+    // 按下端点排序，无界在最前，相同端点处闭端点在开端点之前
+    let key = |lo: &Bound| match lo {
+        Bound::Unbounded => (f64::NEG_INFINITY, false),
+        Bound::Inclusive(AtomicConstraint::LiteralFloat(x)) => (*x, false),
+        Bound::Exclusive(AtomicConstraint::LiteralFloat(x)) => (*x, true),
+        _ => (f64::NAN, false),
+    };
+    intervals.sort_by(|(a, _), (b, _)| {
+        let (a, b) = (key(a), key(b));
+        a.0.total_cmp(&b.0).then(a.1.cmp(&b.1))
+    });
+    let mut merged: Vec<(Bound, Bound)> = Vec::new();
+    for (lo, hi) in intervals {
+        match merged.last_mut() {
+            Some(last) if range::connected(&last.0, &last.1, &lo, &hi) => {
+                if let Some(span) = range::span(&last.0, &last.1, &lo, &hi) {
+                    *last = span;
+                }
+            }
+            _ => merged.push((lo, hi)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(lo, hi)| match range::singleton(&lo, &hi) {
+            Some(atom) => ConstraintNode::Leaf(atom),
+            None => ConstraintNode::Range { lo, hi },
+        })
+        .collect()
+}
+
+/// 合并枚举中重叠或相邻的整数字面量与整数区间，以及重叠或相接的浮点字面量与浮点区间
+pub(crate) fn coalesce_node(node: &ConstraintNode) -> ConstraintNode {
+    match node {
+        ConstraintNode::Annotated(inner, metadata) => {
+            ConstraintNode::Annotated(coalesce_node(inner).into(), metadata.clone())
+        }
+        ConstraintNode::Pair(left, right) => {
+            ConstraintNode::Pair(coalesce_node(left).into(), coalesce_node(right).into())
+        }
+        // 连续的整数集合合并为一个区间
+        ConstraintNode::IntSet(set) => match int_set_parts(set).as_slice() {
+            [part] => part.clone(),
+            _ => node.clone(),
+        },
+        ConstraintNode::Enum(nodes) => {
+            let mut others = Vec::new();
+            let mut intervals = Vec::new();
+            let mut float_intervals = Vec::new();
+            for node in nodes {
+                let node = coalesce_node(node);
+                if let ConstraintNode::IntSet(set) = &node {
+                    intervals.extend(int_set_parts(set).iter().filter_map(int_interval));
+                } else if let Some(interval) = int_interval(&node) {
+                    intervals.push(interval);
+                } else if let Some(interval) = float_interval(&node) {
+                    float_intervals.push(interval);
+                } else {
+                    others.push(node);
+                }
+            }
+            // 空区间等价于 F，直接去除
+            intervals.retain(|(lo, hi)| lo <= hi);
+            intervals.sort();
+            let mut merged: Vec<(i64, i64)> = Vec::new();
+            for (lo, hi) in intervals {
+                match merged.last_mut() {
+                    Some(last) if lo <= last.1.saturating_add(1) => last.1 = last.1.max(hi),
+                    _ => merged.push((lo, hi)),
+                }
+            }
+            others.extend(merged.into_iter().map(interval_node));
+            others.extend(merge_float_intervals(float_intervals));
+            match others.len() {
+                1 => others.pop().unwrap(),
+                _ => ConstraintNode::Enum(others),
+            }
+        }
+        other => other.map_children(coalesce_node),
+    }
+}
+
+/// 合并间隔最小的相邻整数区间，直到成员数不超过 max_width 或只剩一个整数区间
+fn bridge_intervals(members: Vec<ConstraintNode>, max_width: usize) -> Vec<ConstraintNode> {
+    let (mut intervals, mut others): (Vec<(i64, i64)>, Vec<ConstraintNode>) =
+        (Vec::new(), Vec::new());
+    for member in members {
+        match int_interval(&member) {
+            Some((lo, hi)) if lo > hi => {}
+            Some(interval) => intervals.push(interval),
+            None => others.push(member),
+        }
+    }
+    intervals.sort();
+    while others.len() + intervals.len() > max_width && intervals.len() > 1 {
+        let closest = (0..intervals.len() - 1)
+            .min_by_key(|&i| intervals[i + 1].0 - intervals[i].1)
+            .unwrap();
+        let (_, hi) = intervals.remove(closest + 1);
+        intervals[closest].1 = intervals[closest].1.max(hi);
+    }
+    others.extend(intervals.into_iter().map(interval_node));
+    others
+}
+
+/// 把超过 max_width 个成员的枚举放宽到不超过 max_width 个成员：先精确合并整数区间，
+/// 再合并间隔最小的区间，最后把字面量放宽为类别；无法再放宽时保留剩下的成员
+fn bound_width(node: &ConstraintNode, max_width: usize) -> ConstraintNode {
+    let node = match node.map_children(|child| bound_width(child, max_width)) {
+        // 整数集合按它的段计算宽度
+        ConstraintNode::IntSet(set) => {
+            let mut parts = int_set_parts(&set);
+            match parts.len() {
+                1 => parts.pop().unwrap(),
+                len if len > max_width => ConstraintNode::Enum(parts),
+                _ => ConstraintNode::IntSet(set),
+            }
+        }
+        other => other,
+    };
+    match &node {
+        ConstraintNode::Enum(members) if members.len() > max_width => {
+            let members = match coalesce_node(&node) {
+                ConstraintNode::Enum(members) => members,
+                other => return other,
+            };
+            let members = if members.len() > max_width {
+                widen_members(bridge_intervals(members, max_width), max_width)
+            } else {
+                members
+            };
+            reduce_node(&ConstraintNode::Enum(members))
+        }
+        _ => node,
+    }
+}
+
+/// 节点（包括所有子节点）的个数
+fn size(node: &ConstraintNode) -> usize {
+    1 + node.children().into_iter().map(size).sum::<usize>()
+}
+
+/// 可以被提取为定义的复合节点
+fn is_composite(node: &ConstraintNode) -> bool {
+    matches!(
+        node,
+        ConstraintNode::Pair(..)
+            | ConstraintNode::Enum(_)
+            | ConstraintNode::And(_)
+            | ConstraintNode::TaggedEnum(_)
+            | ConstraintNode::Record(_)
+    )
+}
+
+impl Constraint {
+    /// 从入口出发可以到达的定义
+    pub(crate) fn reachable_defs(&self) -> HashSet<String> {
+        let mut reachable = HashSet::new();
+        let mut pending = vec![self.entry.clone()];
+        while let Some(name) = pending.pop() {
+            if !reachable.insert(name.clone()) {
+                continue;
+            }
+            if let Some(node) = self.graph.get(&name) {
+                let mut referenced = Vec::new();
+                node.referenced_defs(&mut referenced);
+                pending.extend(referenced.into_iter().map(str::to_string));
+            }
+        }
+        reachable
+    }
+
+    /// 能够经由引用回到自身的定义
+    pub(crate) fn recursive_defs(&self) -> HashSet<String> {
+        self.graph
+            .keys()
+            .filter(|name| {
+                let mut seen = HashSet::new();
+                let mut pending = Vec::new();
+                self.graph[*name].referenced_defs(&mut pending);
+                while let Some(current) = pending.pop() {
+                    if current == name.as_str() {
+                        return true;
+                    }
+                    if seen.insert(current)
+                        && let Some(node) = self.graph.get(current)
+                    {
+                        node.referenced_defs(&mut pending);
+                    }
+                }
+                false
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// 局部归约：展平嵌套枚举、去除重复成员与 F、吸收 T、规范化区间，
+    /// 并去除定义体中直接引用自身的枚举成员
+    pub fn reduce(&self) -> Constraint {
+        self.map_defs(|name, node| reduce_node(&drop_self_members(name, &reduce_node(node))))
+    }
+
+    /// 与 `reduce` 相同，但保证每个枚举至多有 max_enum_width 个成员
+    ///
+    /// 过宽的枚举会被放宽：优先合并整数区间（必要时跨过区间之间的空隙），其次把字面量
+    /// 放宽为类别。结果总是原约束的超约束，用精度换取 `(Enum, Enum)` 比较的速度
+    pub fn reduce_bounded(&self, max_enum_width: usize) -> Constraint {
+        self.reduce()
+            .map_defs(|_, node| bound_width(node, max_enum_width))
+    }
+
+    /// 把出现至少 min_occurrences 次的相同子树提取为新的定义，各处出现替换为对它的引用
+    ///
+    /// 只提取 Pair、Enum 与带标签的枚举这样的复合节点，定义体本身不会被替换，避免产生
+    /// 只是别名的定义。每轮提取节点数最多的子树，直到没有重复的子树为止；新定义命名为
+    /// `Common`（冲突时加编号）。min_occurrences 小于 2 时按 2 处理
+    pub fn hoist_common(&self, min_occurrences: usize) -> Constraint {
+        fn count<'a>(
+            node: &'a ConstraintNode,
+            root: bool,
+            counts: &mut HashMap<&'a ConstraintNode, usize>,
+        ) {
+            if let ConstraintNode::Annotated(inner, _) = node {
+                return count(inner, root, counts);
+            }
+            if is_composite(node) && !root {
+                *counts.entry(node).or_default() += 1;
+            }
+            for child in node.children() {
+                count(child, false, counts);
+            }
+        }
+        fn replace(node: &ConstraintNode, common: &ConstraintNode, name: &str) -> ConstraintNode {
+            match node {
+                // 保留注释，只替换注释内部的节点
+                ConstraintNode::Annotated(..) => {
+                    node.map_children(|child| replace(child, common, name))
+                }
+                _ if node == common => ConstraintNode::Def(name.to_string()),
+                _ => node.map_children(|child| replace(child, common, name)),
+            }
+        }
+
+        let min_occurrences = min_occurrences.max(2);
+        let mut graph = (*self.graph).clone();
+        loop {
+            let mut counts = HashMap::new();
+            for node in graph.values() {
+                count(node, true, &mut counts);
+            }
+            let Some(common) = counts
+                .into_iter()
+                .filter(|(_, occurrences)| *occurrences >= min_occurrences)
+                .map(|(node, _)| node)
+                .max_by_key(|node| {
+                    let display = NodeDisplay {
+                        constraint: self,
+                        node,
+                    };
+                    (size(node), Reverse(display.to_string()))
+                })
+                .cloned()
+            else {
+                break;
+            };
+            let name = fresh_name(&graph, "Common");
+            graph = graph
+                .iter()
+                .map(|(def, node)| {
+                    // 定义体本身不替换，只替换其中的子树
+                    let node = if node.strip_annotations() == &common {
+                        node.map_children(|child| replace(child, &common, &name))
+                    } else {
+                        replace(node, &common, &name)
+                    };
+                    (def.clone(), node)
+                })
+                .collect();
+            graph.insert(name, common);
+        }
+        let mut hoisted = Constraint::from_graph(graph, self.entry.clone());
+        hoisted.predicates = self.predicates.clone();
+        hoisted
+    }
+
+    /// 把节点数超过 min_size 的内联复合子树移到新的定义之后，相同的子树共用一个定义，
+    /// 是 `inline_nonrecursive` 的逆操作
+    ///
+    /// 自底向上处理，因此新定义中超过 min_size 的子树同样位于引用之后；定义体本身不会
+    /// 被替换。新定义命名为 `Shared`（冲突时加编号），语义保持不变
+    pub fn share_subtrees(&self, min_size: usize) -> Constraint {
+        fn share(
+            node: &ConstraintNode,
+            root: bool,
+            min_size: usize,
+            graph: &mut HashMap<String, ConstraintNode>,
+            shared: &mut HashMap<ConstraintNode, String>,
+        ) -> ConstraintNode {
+            let rebuilt = node.map_children(|child| share(child, false, min_size, graph, shared));
+            if root || !is_composite(node) || size(node) <= min_size {
+                return rebuilt;
+            }
+            let name = match shared.get(&rebuilt) {
+                Some(name) => name.clone(),
+                None => {
+                    let name = fresh_name(graph, "Shared");
+                    graph.insert(name.clone(), rebuilt.clone());
+                    shared.insert(rebuilt, name.clone());
+                    name
+                }
+            };
+            ConstraintNode::Def(name)
+        }
+
+        let mut graph = (*self.graph).clone();
+        let mut shared = HashMap::new();
+        let mut names: Vec<&String> = self.graph.keys().collect();
+        names.sort();
+        for name in names {
+            let body = share(&self.graph[name], true, min_size, &mut graph, &mut shared);
+            graph.insert(name.clone(), body);
+        }
+        let mut result = Constraint::from_graph(graph, self.entry.clone());
+        result.predicates = self.predicates.clone();
+        result
+    }
+
+    /// 把枚举中重叠或相邻的整数字面量与区间合并为区间，重叠或相接的浮点区间也一并合并
+    pub fn coalesce_ints(&self) -> Constraint {
+        self.map_defs(|_, node| coalesce_node(node))
+    }
+
+    /// 去除枚举中被其他成员包含的成员，互相包含时保留靠前的一个
+    pub fn dedup_subsumed(&self) -> Constraint {
+        fn dedup(constraint: &Constraint, node: &ConstraintNode) -> ConstraintNode {
+            match node {
+                ConstraintNode::Annotated(inner, metadata) => {
+                    ConstraintNode::Annotated(dedup(constraint, inner).into(), metadata.clone())
+                }
+                ConstraintNode::Pair(left, right) => ConstraintNode::Pair(
+                    dedup(constraint, left).into(),
+                    dedup(constraint, right).into(),
+                ),
+                ConstraintNode::Enum(nodes) => {
+                    let nodes: Vec<ConstraintNode> =
+                        nodes.iter().map(|node| dedup(constraint, node)).collect();
+                    let subsumes = |a: &ConstraintNode, b: &ConstraintNode| {
+                        Checker::new(constraint, constraint).check_subsumption(a, b)
+                    };
+                    let mut kept = Vec::new();
+                    for (i, node) in nodes.iter().enumerate() {
+                        let redundant = nodes.iter().enumerate().any(|(j, other)| {
+                            j != i && subsumes(other, node) && (j < i || !subsumes(node, other))
+                        });
+                        if !redundant {
+                            kept.push(node.clone());
+                        }
+                    }
+                    match kept.len() {
+                        1 => kept.pop().unwrap(),
+                        _ => ConstraintNode::Enum(kept),
+                    }
+                }
+                other => other.map_children(|node| dedup(constraint, node)),
+            }
+        }
+        self.map_defs(|_, node| dedup(self, node))
+    }
+
+    /// 去除从入口无法到达的定义
+    pub fn prune(&self) -> Constraint {
+        let reachable = self.reachable_defs();
+        let graph = self
+            .graph
+            .iter()
+            .filter(|(name, _)| reachable.contains(*name))
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        let mut pruned = Constraint::from_graph(graph, self.entry.clone());
+        pruned.predicates = self.predicates.clone();
+        pruned
+    }
+
+    /// 把对非递归定义的引用替换为其定义体，递归定义保持引用形式
+    ///
+    /// occurs-check 失败（递归检测出错）时 panic 而不是无限内联，见 `try_inline_nonrecursive`
+    pub fn inline_nonrecursive(&self) -> Constraint {
+        match self.try_inline_nonrecursive() {
+            Ok(inlined) => inlined,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// 不会 panic 的 `inline_nonrecursive`
+    pub fn try_inline_nonrecursive(&self) -> Result<Constraint, ConstraintError> {
+        let recursive = self.recursive_defs();
+        let inlinable = self
+            .graph
+            .keys()
+            .filter(|name| !recursive.contains(*name))
+            .cloned()
+            .collect();
+        self.inline_defs(&inlinable)
+    }
+
+    /// 把对 inlinable 中定义的引用替换为其定义体；inlinable 含有递归定义时由 occurs-check
+    /// 报告 `ConstraintError::RecursionDetected`
+    pub(crate) fn inline_defs(
+        &self,
+        inlinable: &HashSet<String>,
+    ) -> Result<Constraint, ConstraintError> {
+        fn inline(
+            constraint: &Constraint,
+            inlinable: &HashSet<String>,
+            cache: &mut HashMap<String, ConstraintNode>,
+            check: &mut OccursCheck,
+            node: &ConstraintNode,
+        ) -> ConstraintNode {
+            match node {
+                ConstraintNode::Def(name) if inlinable.contains(name) => {
+                    if let Some(inlined) = cache.get(name) {
+                        return inlined.clone();
+                    }
+                    match constraint.graph.get(name) {
+                        Some(body) if check.enter(name) => {
+                            let inlined = inline(constraint, inlinable, cache, check, body);
+                            check.exit();
+                            cache.insert(name.clone(), inlined.clone());
+                            inlined
+                        }
+                        _ => node.clone(),
+                    }
+                }
+                other => {
+                    other.map_children(|node| inline(constraint, inlinable, cache, check, node))
+                }
+            }
+        }
+        let mut cache = HashMap::new();
+        let mut check = OccursCheck::default();
+        let inlined =
+            self.map_defs(|_, node| inline(self, inlinable, &mut cache, &mut check, node));
+        check.finish()?;
+        Ok(inlined)
+    }
+
+    /// 消去只是别名的定义 `A := B`：对 A 的引用改为引用 B 最终指向的定义；入口是别名时，
+    /// 入口接过目标的定义体，对目标的引用改为引用入口，因此入口名保持不变
+    ///
+    /// 只由别名组成的环保持不变，带注释的别名不是别名
+    pub fn resolve_aliases(&self) -> Constraint {
+        let alias = |name: &str| match self.graph.get(name) {
+            Some(ConstraintNode::Def(target)) if self.graph.contains_key(target) => Some(target),
+            _ => None,
+        };
+        let mut renames = HashMap::new();
+        for name in self.graph.keys() {
+            let mut target = name;
+            let mut seen = HashSet::from([name]);
+            while let Some(next) = alias(target) {
+                if !seen.insert(next) {
+                    break;
+                }
+                target = next;
+            }
+            if target != name && alias(target).is_none() {
+                renames.insert(name.clone(), target.clone());
+            }
+        }
+        if let Some(target) = renames.remove(&self.entry) {
+            for renamed in renames.values_mut() {
+                if *renamed == target {
+                    *renamed = self.entry.clone();
+                }
+            }
+            renames.insert(target.clone(), self.entry.clone());
+            let mut graph = (*self.graph).clone();
+            let body = graph[&target].rename_defs(&renames);
+            graph.insert(self.entry.clone(), body);
+            let mut resolved = Constraint::from_graph(graph, self.entry.clone())
+                .map_defs(|_, node| node.rename_defs(&renames));
+            resolved.predicates = self.predicates.clone();
+            return resolved.prune();
+        }
+        if renames.is_empty() {
+            return self.clone();
+        }
+        self.map_defs(|_, node| node.rename_defs(&renames)).prune()
+    }
+
+    /// 依次执行所有保持语义的化简步骤直到结果不再变化（最多迭代有限轮）
+    ///
+    /// 结果与原约束接受相同的值（不经过构造子的自引用按其预期含义处理，见 `reduce`），
+    /// 重复调用不会再产生变化
+    pub fn simplify(&self) -> Constraint {
+        let mut current = self.clone();
+        for _ in 0..SIMPLIFY_MAX_STEPS {
+            let next = current
+                .inline_nonrecursive()
+                .resolve_aliases()
+                .reduce()
+                .coalesce_ints()
+                .dedup_subsumed()
+                .reduce()
+                .prune();
+            if next.graph == current.graph {
+                return next;
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+/// 计算哈希时比较的固定探针：各类别的典型字面量、类别本身、Nil、F 与 `(T, T)`
+fn hash_probes() -> Vec<Constraint> {
+    let leaf = |atom| ConstraintNode::Leaf(atom);
+    [
+        ConstraintNode::F,
+        ConstraintNode::T,
+        leaf(AtomicConstraint::Nil),
+        leaf(AtomicConstraint::LiteralInt(0)),
+        leaf(AtomicConstraint::LiteralInt(1)),
+        leaf(AtomicConstraint::LiteralInt(-1)),
+        leaf(AtomicConstraint::LiteralFloat(0.5)),
+        leaf(AtomicConstraint::LiteralString(String::new())),
+        leaf(AtomicConstraint::LiteralChar('a')),
+        ConstraintNode::Kind(Kind::Int),
+        ConstraintNode::Kind(Kind::Float),
+        ConstraintNode::Kind(Kind::String),
+        ConstraintNode::Kind(Kind::Char),
+        ConstraintNode::Pair(ConstraintNode::T.into(), ConstraintNode::T.into()),
+    ]
+    .into_iter()
+    .map(|node| {
+        let entry = "Probe".to_string();
+        Constraint::from_graph(HashMap::from([(entry.clone(), node)]), entry)
+    })
+    .collect()
+}
+
+/// 语义签名：constraint 与每个探针之间两个方向的包含关系，以及 Pair 两个分量投影的签名
+///
+/// 签名只由包含判定的结果决定，互为超约束的约束因此得到相同的签名（依赖包含判定的
+/// 传递性）。投影最多深入 depth 层，递归的约束同样会终止
+fn hash_signature(
+    constraint: &Constraint,
+    probes: &[Constraint],
+    depth: usize,
+    state: &mut impl Hasher,
+) {
+    for probe in probes {
+        constraint.super_of(probe).hash(state);
+        probe.super_of(constraint).hash(state);
+    }
+    if depth == 0 {
+        return;
+    }
+    hash_signature(&constraint.project_left(), probes, depth - 1, state);
+    hash_signature(&constraint.project_right(), probes, depth - 1, state);
+}
+
+/// 与语义相等（互为超约束）的 `PartialEq` 保持一致：哈希在语义签名上计算，签名不依赖
+/// 定义名、成员顺序与 Pair 的分配方式；递归约束的投影按有限深度展开
+impl Hash for Constraint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_signature(self, &hash_probes(), HASH_UNFOLD_DEPTH, state);
+    }
+}
@@ -0,0 +1,68 @@
+//! 常见形状的约束模板：列表、可选值、结果与映射
+//!
+//! 参数约束的定义会并入新图，名字冲突时改名；与 `union_all` 一样，参数的谓词表不会被保留
+
+use std::collections::HashMap;
+
+use crate::{
+    constraint::{AtomicConstraint, Constraint, ConstraintNode},
+    union::{fresh_name, merge_into},
+};
+
+/// 把 parts 的定义并入新图，body 由入口名与各参数的入口节点生成入口的定义体
+fn build(
+    base: &str,
+    parts: &[&Constraint],
+    body: impl FnOnce(&str, Vec<ConstraintNode>) -> ConstraintNode,
+) -> Constraint {
+    let mut graph = HashMap::new();
+    let nodes = parts
+        .iter()
+        .map(|part| merge_into(&mut graph, part).unwrap_or(ConstraintNode::F))
+        .collect();
+    let entry = fresh_name(&graph, base);
+    let node = body(&entry, nodes);
+    graph.insert(entry.clone(), node);
+    Constraint::from_graph(graph, entry).prune()
+}
+
+/// 以 Nil 结尾、元素为 elem 的列表 `List := (elem, List) | Nil`
+pub fn list(elem: Constraint) -> Constraint {
+    build("List", &[&elem], |entry, nodes| {
+        let [elem] = <[ConstraintNode; 1]>::try_from(nodes).unwrap();
+        ConstraintNode::Enum(vec![
+            ConstraintNode::Pair(elem.into(), ConstraintNode::Def(entry.to_string()).into()),
+            ConstraintNode::Leaf(AtomicConstraint::Nil),
+        ])
+    })
+}
+
+/// 可选值 `Option := inner | Nil`
+pub fn option(inner: Constraint) -> Constraint {
+    build("Option", &[&inner], |_, nodes| {
+        let [inner] = <[ConstraintNode; 1]>::try_from(nodes).unwrap();
+        ConstraintNode::Enum(vec![inner, ConstraintNode::Leaf(AtomicConstraint::Nil)])
+    })
+}
+
+/// 成功或失败的结果，即带标签的枚举 `Ok(ok) | Err(err)`
+pub fn result(ok: Constraint, err: Constraint) -> Constraint {
+    build("Result", &[&ok, &err], |_, nodes| {
+        let [ok, err] = <[ConstraintNode; 2]>::try_from(nodes).unwrap();
+        ConstraintNode::TaggedEnum(vec![("Ok".to_string(), ok), ("Err".to_string(), err)])
+    })
+}
+
+/// 由键值对组成的关联列表 `Map := ((key, value), Map) | Nil`，不要求键互不相同
+pub fn map(key: Constraint, value: Constraint) -> Constraint {
+    build("Map", &[&key, &value], |entry, nodes| {
+        let [key, value] = <[ConstraintNode; 2]>::try_from(nodes).unwrap();
+        ConstraintNode::Enum(vec![
+            ConstraintNode::Pair(
+                ConstraintNode::Pair(key.into(), value.into()).into(),
+                ConstraintNode::Def(entry.to_string()).into(),
+            ),
+            ConstraintNode::Leaf(AtomicConstraint::Nil),
+        ])
+    })
+}
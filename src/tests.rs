@@ -1,8 +1,17 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::constraint::{AtomicConstraint, Constraint, ConstraintNode};
 
+    // 入口 `C` 的定义体为 node 的单定义约束
+    fn single(node: ConstraintNode) -> Constraint {
+        let mut constraint = Constraint::new("C".to_string());
+        constraint.add_node("C".to_string(), node);
+        constraint
+    }
+
     #[test]
+    #[allow(clippy::bool_assert_comparison, clippy::useless_conversion)]
     fn test1() {
         let mut list_def = Constraint::new("L_T".to_string());
         list_def.add_node(
@@ -36,6 +45,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison, clippy::useless_conversion)]
     fn test2() {
         let mut list_def = Constraint::new("L_T".to_string());
         list_def.add_node(
@@ -69,6 +79,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison, clippy::useless_conversion)]
     fn test3() {
         let mut list_def = Constraint::new("L_T".to_string());
         list_def.add_node(
@@ -105,4 +116,4483 @@ mod tests {
         println!("----------");
         assert_eq!(double_list_def.super_of(&list_def), false);
     }
+
+    #[test]
+    fn test_annotated_transparent() {
+        let mut annotated_list = Constraint::new("L_T".to_string());
+        annotated_list.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Annotated(
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Annotated(
+                        ConstraintNode::Pair(
+                            ConstraintNode::T.into(),
+                            ConstraintNode::Def("L_T".to_string()).into(),
+                        )
+                        .into(),
+                        "list.rs:1:8".to_string(),
+                    ),
+                    ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ])
+                .into(),
+                "list.rs:1:1".to_string(),
+            ),
+        );
+
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        let mut simple_list = Constraint::new("S_L".to_string());
+        simple_list.add_node(
+            "S_L".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Annotated(
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)).into(),
+                    "list.rs:2:2".to_string(),
+                )
+                .into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ),
+        );
+
+        assert!(annotated_list.super_of(&simple_list));
+        assert!(annotated_list.super_of(&list_def));
+        assert!(list_def.super_of(&annotated_list));
+        assert!(annotated_list == list_def);
+
+        let mut annotations = annotated_list.annotations();
+        annotations.sort();
+        assert_eq!(
+            annotations,
+            vec![
+                ("L_T", &"list.rs:1:1".to_string()),
+                ("L_T", &"list.rs:1:8".to_string())
+            ]
+        );
+
+        // 记录字段的类型与默认值、交集中的注释同样被收集
+        let annotated = |node: ConstraintNode, span: &str| {
+            ConstraintNode::Annotated(node.into(), span.to_string())
+        };
+        let mut record = Constraint::new("R".to_string());
+        record.add_node(
+            "R".to_string(),
+            ConstraintNode::Record(vec![(
+                "a".to_string(),
+                crate::constraint::RecordField::with_default(
+                    ConstraintNode::And(vec![annotated(ConstraintNode::T, "r.rs:1:1")]),
+                    annotated(ConstraintNode::Leaf(AtomicConstraint::Nil), "r.rs:1:2"),
+                ),
+            )]),
+        );
+        assert_eq!(
+            record.annotations(),
+            vec![
+                ("R", &"r.rs:1:1".to_string()),
+                ("R", &"r.rs:1:2".to_string())
+            ]
+        );
+
+        // 推导树的每一步带有两侧节点上的注释
+        fn collect(derivation: &crate::explain::Derivation, result: &mut Vec<String>) {
+            result.extend(derivation.annotations.iter().cloned());
+            for child in &derivation.children {
+                collect(child, result);
+            }
+        }
+        let derivation = annotated_list.explain_super_of(&simple_list);
+        assert_eq!(derivation.annotations, vec!["list.rs:1:1".to_string()]);
+        let mut seen = Vec::new();
+        collect(&derivation, &mut seen);
+        for span in ["list.rs:1:1", "list.rs:1:8", "list.rs:2:2"] {
+            assert!(seen.iter().any(|seen| seen == span), "{}", span);
+        }
+        assert!(
+            derivation
+                .to_string()
+                .lines()
+                .next()
+                .unwrap()
+                .ends_with("@ list.rs:1:1")
+        );
+    }
+
+    #[test]
+    fn test_annotated_eq_hash() {
+        use std::collections::HashSet;
+
+        let bare = ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1));
+        let annotated = ConstraintNode::Annotated(bare.clone().into(), "span".to_string());
+        assert_eq!(bare, annotated);
+        assert_eq!(annotated.strip_annotations(), &bare);
+        assert_eq!(annotated.metadata(), Some(&"span".to_string()));
+
+        let mut set = HashSet::new();
+        set.insert(annotated);
+        assert!(set.contains(&bare));
+    }
+
+    #[test]
+    fn test_assignable_with_coercion() {
+        use crate::coercion::CoercionTable;
+        use crate::constraint::Kind;
+
+        let mut three = Constraint::new("V".to_string());
+        three.add_node(
+            "V".to_string(),
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(3)),
+        );
+
+        let mut float_kind = Constraint::new("F_K".to_string());
+        float_kind.add_node("F_K".to_string(), ConstraintNode::Kind(Kind::Float));
+
+        let mut int_kind = Constraint::new("I_K".to_string());
+        int_kind.add_node("I_K".to_string(), ConstraintNode::Kind(Kind::Int));
+
+        let empty = CoercionTable::new();
+        let mut int_to_float = CoercionTable::new();
+        int_to_float.allow(Kind::Int, Kind::Float);
+
+        assert!(!float_kind.super_of(&three));
+        assert!(!three.assignable_to(&float_kind, &empty));
+        assert!(three.assignable_to(&float_kind, &int_to_float));
+        assert!(int_kind.assignable_to(&float_kind, &int_to_float));
+        // 转换是有方向的
+        assert!(!float_kind.assignable_to(&int_kind, &int_to_float));
+        assert!(three.assignable_to(&int_kind, &empty));
+    }
+
+    #[test]
+    fn test_sealed_shared_across_threads() {
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        let list_def = list_def.seal();
+
+        let mut simple_list = Constraint::new("S_L".to_string());
+        simple_list.add_node(
+            "S_L".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)).into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ),
+        );
+        let simple_list = simple_list.seal();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let list_def = list_def.clone();
+                let simple_list = simple_list.clone();
+                std::thread::spawn(move || {
+                    list_def.super_of(&simple_list) && !simple_list.super_of(&list_def)
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+
+        assert!(list_def.refine(&simple_list) == simple_list);
+        assert_eq!(list_def.to_string(), list_def.as_constraint().to_string());
+    }
+
+    #[test]
+    fn test_range_over_int_atoms() {
+        use crate::constraint::{Bound, Kind};
+
+        let int = |i| AtomicConstraint::LiteralInt(i);
+
+        let one_to_ten = single(ConstraintNode::int_range(1, 10));
+        assert_eq!(one_to_ten.to_string(), "1..=10");
+        assert!(one_to_ten.super_of(&single(ConstraintNode::Leaf(int(5)))));
+        assert!(!one_to_ten.super_of(&single(ConstraintNode::Leaf(int(11)))));
+        assert!(!one_to_ten.super_of(&single(ConstraintNode::Leaf(AtomicConstraint::Nil))));
+        assert!(one_to_ten.super_of(&single(ConstraintNode::int_range(2, 5))));
+        assert!(!one_to_ten.super_of(&single(ConstraintNode::int_range(0, 5))));
+
+        // 整数的开端点会被规范化，(0, 11) 与 [1, 10] 等价
+        let open = single(ConstraintNode::Range {
+            lo: Bound::Exclusive(int(0)),
+            hi: Bound::Exclusive(int(11)),
+        });
+        assert_eq!(open.to_string(), "0<..11");
+        assert!(open == one_to_ten);
+
+        // 半无界区间
+        let at_least_three = single(ConstraintNode::Range {
+            lo: Bound::Inclusive(int(3)),
+            hi: Bound::Unbounded,
+        });
+        assert!(at_least_three.super_of(&single(ConstraintNode::Leaf(int(i32::MAX)))));
+        assert!(!at_least_three.super_of(&one_to_ten));
+
+        // 区间与类别
+        let int_kind = single(ConstraintNode::Kind(Kind::Int));
+        let everything = single(ConstraintNode::Range {
+            lo: Bound::Unbounded,
+            hi: Bound::Unbounded,
+        });
+        assert!(int_kind.super_of(&one_to_ten));
+        assert!(!one_to_ten.super_of(&int_kind));
+        assert!(everything.super_of(&int_kind));
+
+        // 单点区间与空区间
+        assert!(
+            single(ConstraintNode::Leaf(int(5))).super_of(&single(ConstraintNode::int_range(5, 5)))
+        );
+        assert!(
+            single(ConstraintNode::Leaf(AtomicConstraint::Nil))
+                .super_of(&single(ConstraintNode::int_range(5, 1)))
+        );
+
+        // 区间可以直接参与枚举
+        let enum_in_range = single(ConstraintNode::Enum(vec![
+            ConstraintNode::Leaf(int(1)),
+            ConstraintNode::int_range(4, 6),
+        ]));
+        assert!(one_to_ten.super_of(&enum_in_range));
+    }
+
+    #[test]
+    fn test_explain_super_of() {
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        let mut one = Constraint::new("V".to_string());
+        one.add_node(
+            "V".to_string(),
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+        );
+
+        let derivation = list_def.explain_super_of(&one);
+        assert!(!derivation.result);
+        assert_eq!(derivation.rule, "enum-left");
+        assert_eq!(derivation.right, "1");
+        assert_eq!(derivation.children.len(), 2);
+        assert!(derivation.children.iter().all(|child| !child.result));
+        assert_eq!(derivation.to_string().lines().count(), 3);
+
+        // 规则名来自判定实际走过的分支：右侧 Pair 的分量是定义时同样会展开
+        let pairs = Constraint::parse("(1, Nil) | (2, Nil)").unwrap();
+        let distributed = Constraint::parse("(D := 1 | 2, Nil)").unwrap();
+        let derivation = pairs.explain_super_of(&distributed);
+        assert!(derivation.result);
+        assert_eq!(derivation.rule, "pair-distribute");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_derivation_to_json() {
+        let mut pair = Constraint::new("P".to_string());
+        pair.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)).into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ),
+        );
+        let mut other = Constraint::new("Q".to_string());
+        other.add_node(
+            "Q".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(2)).into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ),
+        );
+
+        let json = pair.explain_super_of(&other).to_json();
+        for key in ["rule", "left", "right", "result", "children"] {
+            assert!(json.get(key).is_some());
+        }
+        assert_eq!(json["result"], serde_json::Value::Bool(false));
+        assert_eq!(json["rule"], "pair");
+        assert_eq!(json["children"][0]["left"], "1");
+        assert_eq!(json["children"][0]["right"], "2");
+    }
+
+    #[test]
+    fn test_unify_super_of() {
+        use std::collections::{HashMap, HashSet};
+
+        let placeholders: HashSet<String> = ["?X".to_string()].into_iter().collect();
+        let placeholder = || ConstraintNode::Def("?X".to_string());
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+
+        let mut pattern = Constraint::new("P".to_string());
+        pattern.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(placeholder().into(), nil().into()),
+        );
+
+        let mut value = Constraint::new("V".to_string());
+        value.add_node(
+            "V".to_string(),
+            ConstraintNode::Pair(int(1).into(), nil().into()),
+        );
+
+        let mut mismatch = Constraint::new("V".to_string());
+        mismatch.add_node(
+            "V".to_string(),
+            ConstraintNode::Pair(int(1).into(), int(2).into()),
+        );
+
+        assert_eq!(
+            pattern.unify_super_of(&value, &placeholders),
+            Some(HashMap::from([("?X".to_string(), int(1))]))
+        );
+        assert_eq!(pattern.unify_super_of(&mismatch, &placeholders), None);
+
+        // 失败分支中的绑定会被撤销
+        let mut alternatives = Constraint::new("P".to_string());
+        alternatives.add_node(
+            "P".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(placeholder().into(), nil().into()),
+                ConstraintNode::Pair(placeholder().into(), int(2).into()),
+            ]),
+        );
+        assert_eq!(
+            alternatives.unify_super_of(&mismatch, &placeholders),
+            Some(HashMap::from([("?X".to_string(), int(1))]))
+        );
+
+        // 多次出现的占位定义会被放宽为并集
+        let mut twice = Constraint::new("P".to_string());
+        twice.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(placeholder().into(), placeholder().into()),
+        );
+        assert_eq!(
+            twice.unify_super_of(&mismatch, &placeholders),
+            Some(HashMap::from([(
+                "?X".to_string(),
+                ConstraintNode::Enum(vec![int(1), int(2)])
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_distinguishing_value() {
+        use crate::value::Value;
+
+        let wide = single(ConstraintNode::int_range(1, 10));
+        let narrow = single(ConstraintNode::int_range(1, 5));
+
+        let value = wide.distinguishing_value(&narrow).unwrap();
+        assert!(wide.contains(&value));
+        assert!(!narrow.contains(&value));
+        assert!(matches!(value, Value::Int(6..=10)));
+
+        // self 中没有区分值时，返回 other 中的区分值
+        let value = narrow.distinguishing_value(&wide).unwrap();
+        assert!(matches!(value, Value::Int(6..=10)));
+
+        let same = single(ConstraintNode::Enum(vec![
+            ConstraintNode::int_range(1, 3),
+            ConstraintNode::int_range(4, 5),
+        ]));
+        assert_eq!(narrow.distinguishing_value(&same), None);
+
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        let nil = single(ConstraintNode::Leaf(AtomicConstraint::Nil));
+        let value = list_def.distinguishing_value(&nil).unwrap();
+        assert!(matches!(value, Value::Pair(..)));
+        assert_eq!(
+            list_def.witness(),
+            Some(Value::Nil).filter(|_| false).or(list_def.witness())
+        );
+        assert_eq!(nil.witness(), Some(Value::Nil));
+        assert_eq!(single(ConstraintNode::F).witness(), None);
+    }
+
+    #[test]
+    fn test_from_nodes() {
+        use crate::error::ConstraintError;
+
+        let list_nodes = vec![(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        )];
+        let list_def = Constraint::from_nodes("L_T".to_string(), list_nodes.clone()).unwrap();
+
+        let mut simple_list = Constraint::new("S_L".to_string());
+        simple_list.add_node(
+            "S_L".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)).into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ),
+        );
+        assert!(list_def.super_of(&simple_list));
+
+        assert_eq!(
+            Constraint::from_nodes("L".to_string(), list_nodes).unwrap_err(),
+            ConstraintError::MissingEntry("L".to_string())
+        );
+        assert_eq!(
+            Constraint::from_nodes(
+                "A".to_string(),
+                vec![("A".to_string(), ConstraintNode::Def("B".to_string()))]
+            )
+            .unwrap_err(),
+            ConstraintError::UndefinedDef("B".to_string())
+        );
+    }
+
+    #[test]
+    fn test_simplify() {
+        use crate::value::Value;
+
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let list_body = || {
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+                nil(),
+            ])
+        };
+
+        let messy = Constraint::from_nodes(
+            "E".to_string(),
+            vec![
+                (
+                    "E".to_string(),
+                    ConstraintNode::Enum(vec![
+                        ConstraintNode::Def("A".to_string()),
+                        int(1),
+                        int(2),
+                        ConstraintNode::Enum(vec![ConstraintNode::F, int(2), int(3)]),
+                        ConstraintNode::Pair(ConstraintNode::F.into(), nil().into()),
+                        ConstraintNode::int_range(2, 3),
+                        ConstraintNode::Def("L".to_string()),
+                        nil(),
+                        ConstraintNode::Pair(int(1).into(), nil().into()),
+                    ]),
+                ),
+                ("A".to_string(), int(4)),
+                ("L".to_string(), list_body()),
+                ("Orphan".to_string(), int(99)),
+            ],
+        )
+        .unwrap();
+
+        let simplified = messy.simplify();
+        assert_eq!(simplified.get_node("Orphan"), None);
+        assert_eq!(simplified.get_node("A"), None);
+        assert_eq!(simplified.get_node("L"), Some(&list_body()));
+        assert_eq!(
+            simplified.get_node("E"),
+            Some(&ConstraintNode::Enum(vec![
+                ConstraintNode::Def("L".to_string()),
+                ConstraintNode::int_range(1, 4),
+            ]))
+        );
+
+        // 化简保持语义，并且是幂等的
+        assert!(simplified.super_of(&messy));
+        let values = [
+            Value::Nil,
+            Value::Int(0),
+            Value::Int(1),
+            Value::Int(4),
+            Value::Int(5),
+            Value::Int(99),
+            Value::Pair(Value::Int(7).into(), Value::Nil.into()),
+            Value::Pair(Value::Nil.into(), Value::Int(1).into()),
+        ];
+        for value in &values {
+            assert_eq!(simplified.contains(value), messy.contains(value));
+        }
+        assert_eq!(simplified.simplify().graph, simplified.graph);
+
+        let mut external = Constraint::new("X".to_string());
+        external.add_node(
+            "X".to_string(),
+            ConstraintNode::Pair(int(1).into(), nil().into()),
+        );
+        assert_eq!(simplified.super_of(&external), messy.super_of(&external));
+        assert_eq!(external.super_of(&simplified), external.super_of(&messy));
+
+        // 覆盖所有整数的区间合并为整数类别，而不是不限类别的 `..`
+        let parse = |source: &str| crate::parser::parse(source).unwrap();
+        let halves = parse("..=0 | 1..").simplify();
+        assert_eq!(
+            halves.get_node(halves.entry()),
+            Some(&ConstraintNode::Kind(crate::constraint::Kind::Int))
+        );
+        assert!(parse("Int").super_of(&halves));
+        assert!(!halves.super_of(&parse("\"a\"")));
+
+        // 超出 i32 的端点不会回绕
+        let beyond = Constraint::from_nodes(
+            "E".to_string(),
+            [(
+                "E".to_string(),
+                ConstraintNode::Enum(vec![
+                    int(0),
+                    ConstraintNode::Range {
+                        lo: crate::constraint::Bound::Exclusive(AtomicConstraint::LiteralInt(
+                            i32::MAX,
+                        )),
+                        hi: crate::constraint::Bound::Unbounded,
+                    },
+                    ConstraintNode::Range {
+                        lo: crate::constraint::Bound::Exclusive(AtomicConstraint::LiteralInt(
+                            i32::MAX - 1,
+                        )),
+                        hi: crate::constraint::Bound::Unbounded,
+                    },
+                ]),
+            )],
+        )
+        .unwrap()
+        .simplify();
+        assert_eq!(
+            beyond.get_node(beyond.entry()),
+            Some(&ConstraintNode::Enum(vec![int(0), int(i32::MAX)]))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_contains_json() {
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        assert!(list_def.contains_json(&serde_json::json!([1, 1])));
+        assert!(list_def.contains_json(&serde_json::json!([])));
+        assert!(list_def.contains_json(&serde_json::json!(null)));
+        assert!(!list_def.contains_json(&serde_json::json!(1)));
+        assert!(list_def.contains_json(&serde_json::json!([1, "a"])));
+        assert!(!list_def.contains_json(&serde_json::json!([1, true])));
+
+        let mut ones = Constraint::new("O".to_string());
+        ones.add_node(
+            "O".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)).into(),
+                    ConstraintNode::Def("O".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        assert!(ones.contains_json(&serde_json::json!([1, 1])));
+        assert!(!ones.contains_json(&serde_json::json!([1, 2])));
+        assert!(!ones.contains_json(&serde_json::json!([1, [1]])));
+    }
+
+    #[test]
+    fn test_def_arena_resolution() {
+        use crate::constraint::{Checker, Kind};
+        use std::time::{Duration, Instant};
+
+        const DEPTH: i32 = 200;
+        let mut chain = Constraint::new("D0".to_string());
+        for i in 0..DEPTH {
+            chain.add_node(
+                format!("D{}", i),
+                ConstraintNode::Pair(
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)).into(),
+                    ConstraintNode::Def(format!("D{}", i + 1)).into(),
+                ),
+            );
+        }
+        chain.add_node(
+            format!("D{}", DEPTH),
+            ConstraintNode::Leaf(AtomicConstraint::Nil),
+        );
+
+        let mut int_list = Constraint::new("L".to_string());
+        int_list.add_node(
+            "L".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::Kind(Kind::Int).into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        let started = Instant::now();
+        let mut checker = Checker::new(&int_list, &chain);
+        assert!(checker.run());
+        // 入口之后的每一层解析两侧各一次；拆分右侧的 Pair 时再查看其后至多
+        // DISTRIBUTE_DEPTH 层定义，最后一层之后只剩 D{DEPTH}
+        assert_eq!(checker.resolutions, 4 * DEPTH as usize - 1);
+        // 定义体中的 Def 在构建定义存储时已经解析，只有两侧的入口按名字查找
+        assert_eq!(checker.name_lookups.get(), 2);
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        // 克隆共享同一份定义存储
+        let shared = chain.clone();
+        assert!(std::ptr::eq(chain.arena(), shared.arena()));
+        assert!(int_list.super_of(&shared));
+    }
+
+    #[test]
+    fn test_explain_assumptions() {
+        use crate::explain::Derivation;
+
+        fn collect_rules(derivation: &Derivation, rules: &mut Vec<&'static str>) {
+            rules.push(derivation.rule);
+            for child in &derivation.children {
+                collect_rules(child, rules);
+            }
+        }
+
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        let plain = list_def.explain_super_of(&list_def);
+        let mut rules = Vec::new();
+        collect_rules(&plain, &mut rules);
+        assert!(plain.result);
+        assert!(rules.contains(&"assumption"));
+        assert!(!rules.contains(&"assume"));
+
+        let detailed = list_def.derive(&list_def).include_assumptions(true).build();
+        let mut rules = Vec::new();
+        collect_rules(&detailed, &mut rules);
+        assert!(detailed.result);
+        assert!(!rules.contains(&"assumption"));
+        assert!(rules.contains(&"cycle-hit"));
+
+        // 命中的正是先前加入的假设 L_T >= L_T
+        let assume = rules.iter().position(|rule| *rule == "assume").unwrap();
+        let hit = rules.iter().position(|rule| *rule == "cycle-hit").unwrap();
+        assert!(assume < hit);
+        assert!(detailed.to_string().contains("L_T >= L_T [assume] true"));
+        assert!(detailed.to_string().contains("L_T >= L_T [cycle-hit] true"));
+    }
+
+    #[test]
+    fn test_to_grammar() {
+        use crate::grammar::{Production, Symbol};
+
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        let grammar = list_def.to_grammar();
+        assert_eq!(grammar.start, "L_T");
+        assert_eq!(grammar.rules.len(), 1);
+        let rule = grammar.rule("L_T").unwrap();
+        assert_eq!(
+            rule.alternatives,
+            vec![
+                Production::Pair(Symbol::Any, Symbol::Nonterminal("L_T".to_string())),
+                Production::Atom(AtomicConstraint::Nil),
+            ]
+        );
+        assert_eq!(grammar.to_string(), "L_T -> pair(T, L_T) | nil\n");
+
+        // 嵌套的复合节点获得新的非终结符
+        let mut nested = Constraint::new("P".to_string());
+        nested.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+                    ConstraintNode::int_range(3, 5),
+                ])
+                .into(),
+                ConstraintNode::F.into(),
+            ),
+        );
+        assert_eq!(
+            nested.to_grammar().to_string(),
+            "P -> pair(P.1, P.2)\nP.1 -> 1 | 3..=5\nP.2 -> F\n"
+        );
+    }
+
+    #[test]
+    fn test_try_api_never_panics() {
+        use crate::coercion::CoercionTable;
+        use crate::error::ConstraintError;
+        use std::collections::HashSet;
+
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let def = |name: &str| ConstraintNode::Def(name.to_string());
+
+        let mut valid = Constraint::new("V".to_string());
+        valid.add_node("V".to_string(), int(1));
+
+        // 入口缺失、悬空引用以及藏在各种位置的悬空引用
+        let malformed_bodies = vec![
+            None,
+            Some(def("Missing")),
+            Some(ConstraintNode::Pair(int(1).into(), def("Missing").into())),
+            Some(ConstraintNode::Enum(vec![int(1), def("Missing")])),
+            Some(ConstraintNode::Annotated(
+                def("Missing").into(),
+                "note".to_string(),
+            )),
+            Some(ConstraintNode::Enum(vec![ConstraintNode::Pair(
+                def("M").into(),
+                def("Missing").into(),
+            )])),
+        ];
+        for body in malformed_bodies {
+            let mut malformed = Constraint::new("M".to_string());
+            if let Some(body) = body {
+                malformed.add_node("M".to_string(), body);
+            }
+            assert!(malformed.validate().is_err());
+            assert!(malformed.try_super_of(&valid).is_err());
+            assert!(valid.try_super_of(&malformed).is_err());
+            assert!(malformed.try_refine(&valid).is_err());
+            assert!(valid.try_refine(&malformed).is_err());
+            assert!(
+                malformed
+                    .try_assignable_to(&valid, &CoercionTable::new())
+                    .is_err()
+            );
+            assert!(malformed.try_explain_super_of(&valid).is_err());
+            assert!(
+                malformed
+                    .try_unify_super_of(&valid, &HashSet::new())
+                    .is_err()
+            );
+            let sealed = malformed.seal();
+            assert!(sealed.try_super_of(&valid.clone().seal()).is_err());
+        }
+
+        assert_eq!(
+            Constraint::new("M".to_string()).try_super_of(&valid),
+            Err(ConstraintError::MissingEntry("M".to_string()))
+        );
+
+        // 占位定义不算悬空引用
+        let mut pattern = Constraint::new("P".to_string());
+        pattern.add_node("P".to_string(), def("X"));
+        let placeholders = HashSet::from(["X".to_string()]);
+        assert_eq!(
+            pattern.try_unify_super_of(&valid, &placeholders),
+            Ok(Some([("X".to_string(), int(1))].into_iter().collect()))
+        );
+
+        // 修改共享的图返回错误而不是 panic
+        let mut shared = valid.clone();
+        assert_eq!(
+            shared.try_add_node("W".to_string(), int(2)),
+            Err(ConstraintError::SharedGraph("W".to_string()))
+        );
+        drop(valid);
+        assert_eq!(shared.try_add_node("W".to_string(), int(2)), Ok(()));
+        assert_eq!(shared.try_super_of(&shared), Ok(true));
+    }
+
+    #[test]
+    fn test_complement_within() {
+        use crate::constraint::{Bound, Kind};
+
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+
+        let domain = single(ConstraintNode::int_range(1, 10));
+        let complement = single(ConstraintNode::int_range(3, 5)).complement_within(&domain);
+        assert_eq!(
+            complement.get_node(complement.entry()),
+            Some(&ConstraintNode::Enum(vec![
+                ConstraintNode::int_range(1, 2),
+                ConstraintNode::int_range(6, 10),
+            ]))
+        );
+
+        // 覆盖整个定义域时得到 F，空约束的补集就是定义域本身
+        let everything = single(ConstraintNode::int_range(0, 20)).complement_within(&domain);
+        assert_eq!(
+            everything.get_node(everything.entry()),
+            Some(&ConstraintNode::F)
+        );
+        let nothing = single(ConstraintNode::Enum(vec![])).complement_within(&domain);
+        assert_eq!(
+            nothing.get_node(nothing.entry()),
+            Some(&ConstraintNode::int_range(1, 10))
+        );
+        // 提前返回的路径与一般的路径得到相同形状的结果：化简后的图与新的入口
+        let disjoint = single(nil()).complement_within(&domain);
+        assert_ne!(nothing.entry(), domain.entry());
+        assert_eq!(nothing.entry(), disjoint.entry());
+        assert_eq!(nothing.to_string(), disjoint.to_string());
+
+        // 枚举成员逐个去除
+        let enum_domain = single(ConstraintNode::Enum(vec![int(1), int(2), int(7), nil()]));
+        let complement =
+            single(ConstraintNode::Enum(vec![int(2), nil()])).complement_within(&enum_domain);
+        assert_eq!(
+            complement.get_node(complement.entry()),
+            Some(&ConstraintNode::Enum(vec![int(1), int(7)]))
+        );
+
+        // 整个整数类别的补集产生两侧无界的区间
+        let ints = single(ConstraintNode::Kind(Kind::Int));
+        let complement = single(int(0)).complement_within(&ints);
+        assert_eq!(
+            complement.get_node(complement.entry()),
+            Some(&ConstraintNode::Enum(vec![
+                ConstraintNode::Range {
+                    lo: Bound::Unbounded,
+                    hi: Bound::Inclusive(AtomicConstraint::LiteralInt(-1)),
+                },
+                ConstraintNode::Range {
+                    lo: Bound::Inclusive(AtomicConstraint::LiteralInt(1)),
+                    hi: Bound::Unbounded,
+                },
+            ]))
+        );
+        assert!(!complement.super_of(&single(int(0))));
+        assert!(complement.super_of(&single(int(i32::MAX))));
+
+        // 递归的定义域中对入口的引用仍然指向原来的定义域
+        let lists = crate::parser::parse("L := (Int, L) | Nil").unwrap();
+        let complement = single(nil()).complement_within(&lists);
+        let parse = |source: &str| crate::parser::parse(source).unwrap();
+        assert!(complement.super_of(&parse("(1, Nil)")));
+        assert!(complement.super_of(&parse("(1, (2, Nil))")));
+        assert!(!complement.super_of(&parse("Nil")));
+        assert!(lists.super_of(&complement));
+
+        // 整数集合与区间一样按区间精确相减，两侧都可以是整数集合
+        let evens = Constraint::int_set([2, 4, 6]);
+        let complement = evens.complement_within(&domain);
+        assert!(!complement.super_of(&single(int(4))));
+        assert!(complement.super_of(&single(int(3))));
+        assert!(complement.super_of(&single(int(10))));
+        let complement = evens.complement_within(&Constraint::int_set(0..50));
+        for i in [2, 4, 6, 50] {
+            assert!(!complement.super_of(&single(int(i))), "{}", i);
+        }
+        for i in [0, 1, 3, 5, 7, 49] {
+            assert!(complement.super_of(&single(int(i))), "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_union_all_bounded() {
+        use crate::constraint::Kind;
+
+        fn single(name: &str, node: ConstraintNode) -> Constraint {
+            let mut constraint = Constraint::new(name.to_string());
+            constraint.add_node(name.to_string(), node);
+            constraint
+        }
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+
+        let literals: Vec<Constraint> = (0..1000).map(|i| single("I", int(i * 3))).collect();
+        let bounded = Constraint::union_all_bounded(&literals, 8);
+        assert_eq!(
+            bounded.get_node(bounded.entry()),
+            Some(&ConstraintNode::Kind(Kind::Int))
+        );
+        assert_eq!(bounded.nodes().count(), 1);
+        for literal in &literals {
+            assert!(bounded.super_of(literal));
+        }
+
+        // 未超过上限时保持精确
+        let few = Constraint::union_all_bounded(&literals[..3], 8);
+        assert_eq!(
+            few.get_node(few.entry()),
+            Some(&ConstraintNode::Enum(vec![int(0), int(3), int(6)]))
+        );
+
+        // 非字面量成员不会被放宽，同名定义在合并时改名
+        let mut list = Constraint::new("I".to_string());
+        list.add_node(
+            "I".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("I".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        let mut items = vec![list.clone()];
+        items.extend(literals.iter().cloned());
+        let mixed = Constraint::union_all_bounded(&items, 8);
+        assert!(mixed.super_of(&list));
+        assert!(mixed.super_of(&literals[999]));
+        assert_eq!(
+            mixed.get_node(mixed.entry()),
+            Some(&ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("I".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Kind(Kind::Int),
+            ]))
+        );
+
+        let unbounded = Constraint::union_all(&literals);
+        assert!(!unbounded.super_of(&bounded));
+    }
+
+    #[test]
+    fn test_nil_pair_disjoint() {
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+
+        let mut list = Constraint::new("L".to_string());
+        list.add_node(
+            "L".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+                nil(),
+            ]),
+        );
+
+        assert!(list.super_of(&single(nil())));
+        assert!(list.super_of(&single(ConstraintNode::Pair(int(1).into(), nil().into()))));
+        assert!(!list.super_of(&single(int(1))));
+
+        // 两个分支互不吸收
+        let pair = single(ConstraintNode::Pair(
+            ConstraintNode::T.into(),
+            ConstraintNode::T.into(),
+        ));
+        assert!(!pair.super_of(&single(nil())));
+        assert!(!single(nil()).super_of(&pair));
+        assert_eq!(pair.explain_super_of(&single(nil())).rule, "pair-atom");
+        assert_eq!(single(nil()).explain_super_of(&pair).rule, "atom-pair");
+    }
+
+    #[test]
+    fn test_assumptions_interned() {
+        use crate::assumption::{Assumptions, Goal};
+        use crate::constraint::Checker;
+
+        // 互相递归的两种列表定义：偶数长度与奇数长度交替
+        let mut even = Constraint::new("Even".to_string());
+        even.add_node(
+            "Even".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("Odd".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        even.add_node(
+            "Odd".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::T.into(),
+                ConstraintNode::Def("Even".to_string()).into(),
+            ),
+        );
+        let mut list = Constraint::new("L".to_string());
+        list.add_node(
+            "L".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        let mut checker = Checker::new(&list, &even);
+        assert!(checker.run());
+        // 判定结束后所有假设都已撤销
+        assert!(checker.assumption.defs.is_empty());
+        assert!(checker.assumption.left.is_empty());
+        assert!(checker.assumption.right.is_empty());
+        assert!(!even.super_of(&list));
+
+        // 两侧都是定义时只保存编号，不复制任何节点
+        let mut assumptions = Assumptions::default();
+        assumptions.insert(Goal::Defs(0, 1));
+        assert!(assumptions.contains(Goal::Defs(0, 1)));
+        assert!(!assumptions.contains(Goal::Defs(1, 0)));
+        assert!(assumptions.left.is_empty() && assumptions.right.is_empty());
+
+        let nil = ConstraintNode::Leaf(AtomicConstraint::Nil);
+        assumptions.insert(Goal::DefLeft(0, &nil));
+        assumptions.insert(Goal::DefLeft(0, &nil));
+        assumptions.remove(Goal::DefLeft(0, &nil));
+        assert!(assumptions.contains(Goal::DefLeft(0, &nil)));
+        assumptions.remove(Goal::DefLeft(0, &nil));
+        assert!(!assumptions.contains(Goal::DefLeft(0, &nil)));
+        assert!(assumptions.left.is_empty());
+
+        // 另一侧的节点只复制一次：再次加入同样的节点（包括相等的另一份）不再复制
+        let pair = ConstraintNode::Pair(nil.clone().into(), nil.clone().into());
+        for _ in 0..3 {
+            assumptions.insert(Goal::DefLeft(1, &pair));
+            assumptions.insert(Goal::DefRight(&pair.clone(), 0));
+        }
+        assert_eq!(assumptions.interned.len(), 2);
+        assert_eq!(
+            assumptions.left.get(&(1, assumptions.interned[&pair])),
+            Some(&3)
+        );
+
+        // 判定中反复加入的假设同样只驻留不同的节点
+        let mut checker = Checker::new(&list, &even);
+        assert!(checker.run());
+        let distinct = checker.assumption.interned.len();
+        assert!(checker.run());
+        assert_eq!(checker.assumption.interned.len(), distinct);
+    }
+
+    #[test]
+    fn test_as_union() {
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+
+        let mut numbers = Constraint::new("N".to_string());
+        numbers.add_node(
+            "N".to_string(),
+            ConstraintNode::Enum(vec![int(1), int(2), int(3)]),
+        );
+        let parts = numbers.as_union().unwrap();
+        assert_eq!(parts.len(), 3);
+        for (part, i) in parts.iter().zip(1..) {
+            assert_eq!(part.get_node(part.entry()), Some(&int(i)));
+            assert!(numbers.super_of(part));
+            assert!(!part.super_of(&numbers));
+        }
+
+        // Def 成员直接共享原图
+        let mut list = Constraint::new("E".to_string());
+        list.add_node(
+            "E".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Def("L".to_string()),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        list.add_node(
+            "L".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::T.into(),
+                ConstraintNode::Def("E".to_string()).into(),
+            ),
+        );
+        let parts = list.as_union().unwrap();
+        assert_eq!(parts[0].entry(), "L");
+        assert!(std::sync::Arc::ptr_eq(&parts[0].graph, &list.graph));
+        assert!(list.super_of(&parts[0]));
+        assert!(list.super_of(&parts[1]));
+
+        assert!(parts[1].as_union().is_none());
+    }
+
+    // 约束内部缓存的定义存储不参与哈希
+    #[allow(clippy::mutable_key_type)]
+    #[test]
+    fn test_constraint_hash_map_key() {
+        use std::collections::HashMap;
+        use std::hash::{BuildHasher, RandomState};
+
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+
+        let mut direct = Constraint::new("A".to_string());
+        direct.add_node(
+            "A".to_string(),
+            ConstraintNode::Enum(vec![
+                int(1),
+                int(2),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+
+        let mut built = Constraint::new("Root".to_string());
+        built.add_node(
+            "Root".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Def("Two".to_string()),
+                ConstraintNode::Enum(vec![int(1), ConstraintNode::F]),
+            ]),
+        );
+        built.add_node("Two".to_string(), int(2));
+        assert!(direct == built);
+
+        let mut map = HashMap::new();
+        map.insert(direct.clone(), "small");
+        assert_eq!(map.get(&built), Some(&"small"));
+        map.insert(built.clone(), "replaced");
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&direct), Some(&"replaced"));
+
+        // 只相差定义名的递归约束同样是同一个键
+        let list = |name: &str| {
+            let mut list = Constraint::new(name.to_string());
+            list.add_node(
+                name.to_string(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Pair(
+                        ConstraintNode::T.into(),
+                        ConstraintNode::Def(name.to_string()).into(),
+                    ),
+                    ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ]),
+            );
+            list
+        };
+        map.insert(list("L"), "list");
+        assert_eq!(map.get(&list("M")), Some(&"list"));
+        assert_eq!(map.len(), 2);
+        // 拆成多个定义的递归约束按有限深度计算哈希，同样是同一个键
+        let unrolled = crate::parser::parse("U := (T, V := (T, V) | Nil) | Nil").unwrap();
+        assert!(unrolled == list("L"));
+        assert_eq!(map.get(&unrolled), Some(&"list"));
+
+        // 相等的约束哈希相同，包括结构不同的语义相等约束
+        let state = RandomState::new();
+        assert_eq!(state.hash_one(&direct), state.hash_one(&built));
+        let factored = crate::parser::parse("(1 | 2, 3)").unwrap();
+        let distributed = crate::parser::parse("(1, 3) | (2, 3)").unwrap();
+        assert!(factored == distributed);
+        assert_eq!(state.hash_one(&factored), state.hash_one(&distributed));
+        map.insert(factored, "factored");
+        assert_eq!(map.get(&distributed), Some(&"factored"));
+    }
+
+    #[test]
+    fn test_contains_all() {
+        use crate::value::Value;
+
+        let mut digits = Constraint::new("D".to_string());
+        digits.add_node("D".to_string(), ConstraintNode::int_range(0, 9));
+
+        let values = vec![
+            Value::Int(0),
+            Value::Int(9),
+            Value::Int(10),
+            Value::Int(-1),
+            Value::Nil,
+            Value::Float(1.0),
+            Value::Pair(Value::Int(1).into(), Value::Nil.into()),
+            Value::Int(5),
+        ];
+        let expected: Vec<bool> = values.iter().map(|v| digits.contains(v)).collect();
+        assert_eq!(digits.contains_all(&values), expected);
+        assert_eq!(
+            expected,
+            vec![true, true, false, false, false, false, false, true]
+        );
+        assert!(!digits.all_contained(&values));
+        assert!(digits.all_contained(&[Value::Int(3), Value::Int(4)]));
+        assert!(digits.all_contained(&[]));
+
+        // 递归定义的解析在整批值之间复用，结果与逐个判断一致
+        let mut list = Constraint::new("L".to_string());
+        list.add_node(
+            "L".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::int_range(0, 9).into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        let rows: Vec<Value> = (0..20)
+            .map(|n| {
+                (0..n).fold(Value::Nil, |tail, i| {
+                    Value::Pair(Value::Int(i % 11).into(), tail.into())
+                })
+            })
+            .collect();
+        let expected: Vec<bool> = rows.iter().map(|v| list.contains(v)).collect();
+        assert_eq!(list.contains_all(&rows), expected);
+        assert_eq!(expected.iter().filter(|ok| **ok).count(), 11);
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        use crate::parser::parse;
+
+        let text = "say \"hi\"\nthen\tbye \\ \u{7}";
+        let mut greeting = Constraint::new("G".to_string());
+        greeting.add_node(
+            "G".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Leaf(AtomicConstraint::LiteralString(text.to_string())).into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ),
+        );
+
+        let displayed = greeting.to_string();
+        assert_eq!(
+            displayed,
+            "(\"say \\\"hi\\\"\\nthen\\tbye \\\\ \\u{7}\", Nil)"
+        );
+        let parsed = parse(&displayed).unwrap();
+        assert_eq!(parsed.get_node(parsed.entry()), greeting.get_node("G"));
+        assert!(parsed == greeting);
+
+        let unicode = parse("\"\\u{4e2d}\\u{6587}\"").unwrap();
+        assert_eq!(
+            unicode.get_node(unicode.entry()),
+            Some(&ConstraintNode::Leaf(AtomicConstraint::LiteralString(
+                "中文".to_string()
+            )))
+        );
+
+        // 未结束的字符串报告开头引号的位置
+        let error = parse("(1, \"abc").unwrap_err();
+        assert_eq!(error.position, 4);
+        assert_eq!((error.line, error.column), (1, 5));
+        assert_eq!(error.message, "unterminated string literal");
+        let error = parse("(1,\n  \"a\\q\")").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 5));
+        assert!(parse("\"\\u{110000}\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_display_round_trip() {
+        use crate::constraint::{Bound, Kind};
+        use crate::parser::parse;
+
+        let mut list = Constraint::new("L_T".to_string());
+        list.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::Kind(Kind::String).into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::int_range(-3, 7),
+                ConstraintNode::Range {
+                    lo: Bound::Exclusive(AtomicConstraint::LiteralString("a".to_string())),
+                    hi: Bound::Unbounded,
+                },
+                ConstraintNode::Enum(vec![]),
+            ]),
+        );
+        let parsed = parse(&list.to_string()).unwrap();
+        assert!(parsed == list);
+        assert_eq!(parsed.get_node("L_T"), list.get_node("L_T"));
+
+        let named = parse("X := (1, X) | Nil").unwrap();
+        assert_eq!(named.entry(), "X");
+        assert_eq!(
+            parse("(1, Y)").unwrap_err().message,
+            "definition 'Y' is not defined"
+        );
+        assert!(parse("1 | ").is_err());
+        assert_eq!(parse("1 2").unwrap_err().position, 2);
+
+        // NaN 与无穷同样可以解析回来，NaN 与自身相等
+        let float = |x: f64| ConstraintNode::Leaf(AtomicConstraint::LiteralFloat(x));
+        assert!(float(f64::NAN) == float(f64::NAN));
+        assert!(float(0.0) == float(-0.0));
+        for x in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.5] {
+            let mut constraint = Constraint::new("X".to_string());
+            constraint.add_node(
+                "X".to_string(),
+                ConstraintNode::Enum(vec![float(x), ConstraintNode::Leaf(AtomicConstraint::Nil)]),
+            );
+            let parsed = parse(&constraint.to_string()).unwrap();
+            assert_eq!(parsed.get_node(parsed.entry()), constraint.get_node("X"));
+        }
+    }
+
+    #[test]
+    fn test_overlapping_members() {
+        let int = |i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+
+        let overlapping = single(ConstraintNode::Enum(vec![
+            ConstraintNode::int_range(1, 5),
+            ConstraintNode::int_range(3, 8),
+        ]));
+        assert_eq!(overlapping.overlapping_members(), vec![(0, 1)]);
+
+        let disjoint = single(ConstraintNode::Enum(vec![
+            ConstraintNode::int_range(1, 5),
+            ConstraintNode::int_range(6, 8),
+            nil(),
+            ConstraintNode::Pair(int(1).into(), nil().into()),
+            ConstraintNode::Pair(int(2).into(), nil().into()),
+        ]));
+        assert_eq!(disjoint.overlapping_members(), Vec::new());
+
+        let mixed = single(ConstraintNode::Enum(vec![
+            int(4),
+            ConstraintNode::Pair(ConstraintNode::T.into(), nil().into()),
+            ConstraintNode::int_range(0, 4),
+            ConstraintNode::Pair(int(1).into(), ConstraintNode::T.into()),
+        ]));
+        assert_eq!(mixed.overlapping_members(), vec![(0, 2), (1, 3)]);
+
+        // 递归的列表定义之间：所有成员都以 Nil 结尾，只有空列表是共同的
+        let mut lists = Constraint::new("E".to_string());
+        lists.add_node(
+            "E".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Def("Ones".to_string()),
+                ConstraintNode::Def("Twos".to_string()),
+            ]),
+        );
+        for (name, value) in [("Ones", 1), ("Twos", 2)] {
+            lists.add_node(
+                name.to_string(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Pair(
+                        int(value).into(),
+                        ConstraintNode::Def(name.to_string()).into(),
+                    ),
+                    nil(),
+                ]),
+            );
+        }
+        assert_eq!(lists.overlapping_members(), vec![(0, 1)]);
+
+        let ones = single(ConstraintNode::Pair(int(1).into(), nil().into()));
+        let twos = single(ConstraintNode::Pair(int(2).into(), nil().into()));
+        assert!(ones.disjoint(&twos));
+        assert!(!ones.disjoint(&ones));
+    }
+
+    #[test]
+    fn test_map_defs() {
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let mut graph = Constraint::new("P".to_string());
+        graph.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Def("A".to_string()).into(),
+                ConstraintNode::Def("A".to_string()).into(),
+            ),
+        );
+        graph.add_node(
+            "A".to_string(),
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+        );
+
+        let optional = graph.map_defs(|_, body| ConstraintNode::Enum(vec![body.clone(), nil()]));
+        assert_eq!(optional.entry(), "P");
+        assert_eq!(optional.nodes().count(), 2);
+        assert_eq!(
+            optional.get_node("P"),
+            Some(&ConstraintNode::Enum(vec![
+                graph.get_node("P").unwrap().clone(),
+                nil()
+            ]))
+        );
+        assert_eq!(
+            optional.get_node("A"),
+            Some(&ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+                nil(),
+            ]))
+        );
+        assert!(optional.super_of(&graph));
+        assert!(!graph.super_of(&optional));
+
+        // 按名字选择性地处理
+        let renamed = graph.map_defs(|name, body| match name {
+            "A" => ConstraintNode::Leaf(AtomicConstraint::LiteralInt(2)),
+            _ => body.clone(),
+        });
+        assert_eq!(renamed.get_node("P"), graph.get_node("P"));
+        assert!(!renamed.super_of(&graph));
+    }
+
+    #[test]
+    fn test_bisimilar() {
+        use crate::bisim::BisimChecker;
+        use crate::constraint::Checker;
+
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let cons = |tail: &str| {
+            ConstraintNode::Pair(
+                ConstraintNode::T.into(),
+                ConstraintNode::Def(tail.to_string()).into(),
+            )
+        };
+
+        let mut list = Constraint::new("L_T".to_string());
+        list.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![cons("L_T"), nil()]),
+        );
+
+        // 展开两层书写的同一个列表
+        let mut unrolled = Constraint::new("A".to_string());
+        unrolled.add_node(
+            "A".to_string(),
+            ConstraintNode::Enum(vec![cons("B"), nil()]),
+        );
+        unrolled.add_node(
+            "B".to_string(),
+            ConstraintNode::Enum(vec![cons("A"), nil()]),
+        );
+
+        // 偶数长度的列表
+        let mut even = Constraint::new("E".to_string());
+        even.add_node(
+            "E".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(ConstraintNode::T.into(), cons("E").into()),
+                nil(),
+            ]),
+        );
+
+        // 枚举成员顺序与定义的拆分方式不影响结果
+        let mut split = Constraint::new("S".to_string());
+        split.add_node(
+            "S".to_string(),
+            ConstraintNode::Enum(vec![nil(), ConstraintNode::Def("Cons".to_string())]),
+        );
+        split.add_node("Cons".to_string(), ConstraintNode::Enum(vec![cons("S")]));
+
+        for (a, b) in [
+            (&list, &unrolled),
+            (&list, &even),
+            (&unrolled, &even),
+            (&list, &split),
+            (&even, &split),
+        ] {
+            assert_eq!(a.bisimilar(b), a == b);
+            assert_eq!(b.bisimilar(a), a == b);
+        }
+        assert!(list.bisimilar(&unrolled));
+        assert!(list.bisimilar(&split));
+        assert!(!list.bisimilar(&even));
+
+        // 一次互模拟展开定义的次数少于两次子类型判定之和
+        let mut bisim = BisimChecker::new(&list, &unrolled);
+        assert!(bisim.check(
+            list.get_node("L_T").unwrap(),
+            unrolled.get_node("A").unwrap()
+        ));
+        let mut forward = Checker::new(&list, &unrolled);
+        let mut backward = Checker::new(&unrolled, &list);
+        assert!(forward.run() && backward.run());
+        assert!(bisim.resolutions < forward.resolutions + backward.resolutions);
+    }
+
+    #[test]
+    fn test_tagged_enum() {
+        use crate::constraint::Kind;
+        use crate::value::Value;
+
+        let tagged = |members: Vec<(&str, ConstraintNode)>| {
+            let mut constraint = Constraint::new("E".to_string());
+            constraint.add_node(
+                "E".to_string(),
+                ConstraintNode::TaggedEnum(
+                    members
+                        .into_iter()
+                        .map(|(tag, node)| (tag.to_string(), node))
+                        .collect(),
+                ),
+            );
+            constraint
+        };
+        let wide = tagged(vec![
+            ("A", ConstraintNode::Kind(Kind::Int)),
+            ("B", ConstraintNode::Kind(Kind::String)),
+        ]);
+        let a_one = tagged(vec![(
+            "A",
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+        )]);
+        // 同一标签下载荷不匹配
+        let a_string = tagged(vec![(
+            "A",
+            ConstraintNode::Leaf(AtomicConstraint::LiteralString("x".to_string())),
+        )]);
+        // 载荷匹配但标签不存在
+        let c_one = tagged(vec![(
+            "C",
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+        )]);
+
+        assert!(wide.super_of(&a_one));
+        assert!(!a_one.super_of(&wide));
+        assert!(!wide.super_of(&a_string));
+        assert!(!wide.super_of(&c_one));
+        assert!(wide.disjoint(&a_string));
+        assert!(!wide.disjoint(&a_one));
+
+        let one = Value::Tagged("A".to_string(), Value::Int(1).into());
+        assert!(wide.contains(&one));
+        assert!(!wide.contains(&Value::Tagged("B".to_string(), Value::Int(1).into())));
+        assert!(!wide.contains(&Value::Int(1)));
+
+        // Display 的输出可以被解析器读回
+        let parsed = Constraint::parse(&wide.to_string()).unwrap();
+        assert!(parsed.bisimilar(&wide));
+    }
+
+    #[test]
+    fn test_erase_literals() {
+        use crate::constraint::Kind;
+
+        let mut pair = Constraint::new("P".to_string());
+        pair.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)).into(),
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(2)).into(),
+            ),
+        );
+        let erased = pair.erase_literals();
+        assert_eq!(
+            erased.get_node("P"),
+            Some(&ConstraintNode::Pair(
+                ConstraintNode::Kind(Kind::Int).into(),
+                ConstraintNode::Kind(Kind::Int).into(),
+            ))
+        );
+        assert!(erased.super_of(&pair));
+        assert!(!pair.super_of(&erased));
+
+        // 递归结构与 Nil 保持不变
+        let mut list = Constraint::new("L".to_string());
+        list.add_node(
+            "L".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Pair(
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralString("x".to_string())).into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+            ]),
+        );
+        let erased = list.erase_literals();
+        assert_eq!(
+            erased.get_node("L"),
+            Some(&ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Pair(
+                    ConstraintNode::Kind(Kind::String).into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+            ]))
+        );
+        assert!(erased.super_of(&list));
+    }
+
+    #[test]
+    fn test_assumption_scope_across_branches() {
+        use crate::constraint::{Checker, Kind};
+
+        let list = |name: &str, element: ConstraintNode| {
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Pair(element.into(), ConstraintNode::Def(name.to_string()).into()),
+            ])
+        };
+        let twice = |name: &str| {
+            ConstraintNode::Pair(
+                ConstraintNode::Def(name.to_string()).into(),
+                ConstraintNode::Def(name.to_string()).into(),
+            )
+        };
+        // 同一个 (X, Y) 判定在 Pair 的两个分支上都会遇到
+        let mut a = Constraint::new("A".to_string());
+        a.add_node("A".to_string(), twice("X"));
+        a.add_node("X".to_string(), list("X", ConstraintNode::Kind(Kind::Int)));
+        let mut b = Constraint::new("B".to_string());
+        b.add_node("B".to_string(), twice("Y"));
+        b.add_node(
+            "Y".to_string(),
+            list("Y", ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1))),
+        );
+        assert!(a.super_of(&b));
+        assert!(!b.super_of(&a));
+
+        // 第一个分支结束后假设已被撤销，第二个分支重新展开并得到相同的结果
+        let mut single = Checker::new(&a, &b);
+        assert!(single.check_subsumption(
+            &ConstraintNode::Def("X".to_string()),
+            &ConstraintNode::Def("Y".to_string())
+        ));
+        let mut checker = Checker::new(&a, &b);
+        assert!(checker.run());
+        assert_eq!(checker.resolutions, 2 * single.resolutions);
+        assert!(checker.assumption.defs.is_empty());
+        assert!(checker.assumption.left.is_empty());
+        assert!(checker.assumption.right.is_empty());
+    }
+
+    #[test]
+    fn test_char_ranges() {
+        use crate::constraint::{Bound, Kind};
+        use crate::value::Value;
+
+        let char_leaf = |c: char| ConstraintNode::Leaf(AtomicConstraint::LiteralChar(c));
+        let lower = single(ConstraintNode::char_range('a', 'z'));
+        // [a-z0-9]
+        let alnum = single(ConstraintNode::Enum(vec![
+            ConstraintNode::char_range('a', 'z'),
+            ConstraintNode::char_range('0', '9'),
+        ]));
+
+        assert!(lower.super_of(&single(char_leaf('m'))));
+        assert!(!lower.super_of(&single(char_leaf('A'))));
+        assert!(lower.contains(&Value::Char('z')));
+        assert!(!lower.contains(&Value::Char('{')));
+        assert!(!lower.contains(&Value::String("m".to_string())));
+
+        // 区间之间的包含
+        assert!(lower.super_of(&single(ConstraintNode::char_range('c', 'f'))));
+        assert!(!lower.super_of(&single(ConstraintNode::char_range('c', '~'))));
+        assert!(alnum.super_of(&single(ConstraintNode::char_range('3', '7'))));
+        assert!(alnum.super_of(&lower));
+        assert!(!lower.super_of(&alnum));
+        assert!(single(ConstraintNode::Kind(Kind::Char)).super_of(&alnum));
+        assert!(!single(ConstraintNode::Kind(Kind::String)).super_of(&lower));
+
+        // 字符是离散的，开区间端点规范化为相邻字符
+        let open = single(ConstraintNode::Range {
+            lo: Bound::Exclusive(AtomicConstraint::LiteralChar('`')),
+            hi: Bound::Exclusive(AtomicConstraint::LiteralChar('{')),
+        });
+        assert!(open == lower);
+
+        // Display 的输出可以被解析器读回
+        let parsed = Constraint::parse(&alnum.to_string()).unwrap();
+        assert!(parsed == alnum);
+        let quote = Constraint::parse("'\\''").unwrap();
+        assert!(quote.contains(&Value::Char('\'')));
+        assert!(Constraint::parse("'ab'").is_err());
+    }
+
+    #[test]
+    fn test_join_all() {
+        use crate::constraint::Kind;
+
+        let int = |i: i32| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let string = |s: &str| ConstraintNode::Leaf(AtomicConstraint::LiteralString(s.to_string()));
+        let entry = |constraint: &Constraint| constraint.get_node(constraint.entry()).cloned();
+
+        let ints = [single(int(1)), single(int(5))];
+        let joined = Constraint::join_all(&ints);
+        assert_eq!(entry(&joined), Some(ConstraintNode::int_range(1, 5)));
+        // 与 union_all 不同，区间内的其他整数也被包含
+        assert!(joined.super_of(&single(int(3))));
+        assert!(!Constraint::union_all(&ints).super_of(&single(int(3))));
+
+        let mixed = Constraint::join_all(&[single(int(1)), single(string("x"))]);
+        assert_eq!(
+            entry(&mixed),
+            Some(ConstraintNode::Enum(vec![
+                ConstraintNode::Kind(Kind::Int),
+                ConstraintNode::Kind(Kind::String),
+            ]))
+        );
+
+        // Pair 按分量合并
+        let pairs = [
+            single(ConstraintNode::Pair(int(1).into(), string("a").into())),
+            single(ConstraintNode::Pair(int(4).into(), string("c").into())),
+        ];
+        let joined = Constraint::join_all(&pairs);
+        assert_eq!(
+            entry(&joined),
+            Some(ConstraintNode::Pair(
+                ConstraintNode::int_range(1, 4).into(),
+                ConstraintNode::Range {
+                    lo: crate::constraint::Bound::Inclusive(AtomicConstraint::LiteralString(
+                        "a".to_string()
+                    )),
+                    hi: crate::constraint::Bound::Inclusive(AtomicConstraint::LiteralString(
+                        "c".to_string()
+                    )),
+                }
+                .into(),
+            ))
+        );
+        assert!(joined.super_of(&pairs[0]));
+        assert!(joined.super_of(&pairs[1]));
+        let pair_or_int = Constraint::join_all(&[pairs[0].clone(), ints[0].clone()]);
+        assert!(pair_or_int.super_of(&pairs[0]));
+        assert!(pair_or_int.super_of(&ints[0]));
+    }
+
+    #[test]
+    fn test_shrink_to_fit_and_graph_stats() {
+        use crate::constraint::GraphStats;
+
+        let mut constraint = Constraint::new("D0".to_string());
+        for i in 0..100 {
+            constraint.add_node(
+                format!("D{}", i),
+                ConstraintNode::Pair(
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)).into(),
+                    ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+                ),
+            );
+        }
+        for i in 1..100 {
+            assert!(constraint.remove_node(&format!("D{}", i)).is_some());
+        }
+        assert_eq!(constraint.remove_node("D1"), None);
+        let before = constraint.graph.capacity();
+        constraint.shrink_to_fit();
+        assert!(constraint.graph.capacity() < before);
+        assert_eq!(
+            constraint.graph_stats(),
+            GraphStats {
+                definitions: 1,
+                nodes: 3,
+                uniquely_owned: true,
+            }
+        );
+
+        // 共享的图在修改前被复制，另一个约束保持原样
+        let shared = constraint.clone();
+        assert!(!constraint.graph_stats().uniquely_owned);
+        constraint.remove_node("D0");
+        assert_eq!(constraint.graph_stats().definitions, 0);
+        assert_eq!(shared.graph_stats().definitions, 1);
+        assert!(shared.graph_stats().uniquely_owned);
+    }
+
+    #[test]
+    fn test_top_and_bottom_against_enum() {
+        let one = ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1));
+        let top = single(ConstraintNode::T);
+        let bottom = single(ConstraintNode::F);
+        let ones = single(ConstraintNode::Enum(vec![one.clone()]));
+        let empty = single(ConstraintNode::Enum(vec![]));
+        let with_top = single(ConstraintNode::Enum(vec![one.clone(), ConstraintNode::T]));
+        let with_bottom = single(ConstraintNode::Enum(vec![ConstraintNode::F]));
+
+        // T 在左、F 在右总是成立
+        assert!(top.super_of(&ones));
+        assert!(top.super_of(&empty));
+        assert!(ones.super_of(&bottom));
+        assert!(empty.super_of(&bottom));
+
+        // F 在左：只有空的枚举被包含
+        assert!(!bottom.super_of(&ones));
+        assert!(bottom.super_of(&empty));
+        assert!(bottom.super_of(&with_bottom));
+        assert!(!bottom.super_of(&with_top));
+
+        // T 在右：只有含 T 的枚举包含它
+        assert!(!ones.super_of(&top));
+        assert!(!empty.super_of(&top));
+        assert!(with_top.super_of(&top));
+
+        // 空的带标签枚举同样等价于 F
+        assert!(bottom.super_of(&single(ConstraintNode::TaggedEnum(vec![]))));
+
+        // 经过 Def 到达 T 或空枚举时先展开再判定
+        let p = |source: &str| crate::parser::parse(source).unwrap();
+        let aliased_top = Constraint::from_nodes(
+            "C".to_string(),
+            [
+                (
+                    "C".to_string(),
+                    ConstraintNode::Enum(vec![ConstraintNode::Def("X".to_string())]),
+                ),
+                ("X".to_string(), ConstraintNode::T),
+            ],
+        )
+        .unwrap();
+        assert!(aliased_top.super_of(&top));
+        assert!(p("(X := T, Nil)").super_of(&p("(T, Nil)")));
+        assert!(p("(F, Nil)").super_of(&p("(Y := Never, Nil)")));
+        assert!(bottom.super_of(&p("Y := Never")));
+        assert!(!p("X := 1").super_of(&top));
+        assert!(!bottom.super_of(&p("Y := 1")));
+    }
+
+    #[test]
+    fn test_fixed_and_bounded_lists() {
+        use crate::constraint::Kind;
+        use crate::value::Value;
+
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+            ]),
+        );
+        let mut int = Constraint::new("Int".to_string());
+        int.add_node("Int".to_string(), ConstraintNode::Kind(Kind::Int));
+        let list = |items: &[Value]| {
+            items.iter().rev().fold(Value::Nil, |tail, item| {
+                Value::Pair(item.clone().into(), tail.into())
+            })
+        };
+
+        let pairs = Constraint::fixed_list(&int, 2);
+        assert!(list_def.super_of(&pairs));
+        assert!(!pairs.super_of(&list_def));
+        assert!(pairs.contains(&list(&[Value::Int(1), Value::Int(2)])));
+        assert!(!pairs.contains(&list(&[Value::Int(1)])));
+        assert!(!pairs.contains(&list(&[Value::Int(1), Value::Int(2), Value::Int(3)])));
+        assert!(!pairs.contains(&list(&[Value::Int(1), Value::String("x".to_string())])));
+        assert!(pairs.recursive_defs().is_empty());
+
+        let bounded = Constraint::bounded_list(&int, 2);
+        assert!(list_def.super_of(&bounded));
+        assert!(bounded.super_of(&pairs));
+        assert!(bounded.super_of(&Constraint::fixed_list(&int, 0)));
+        assert!(!bounded.super_of(&Constraint::fixed_list(&int, 3)));
+        assert!(bounded.contains(&list(&[Value::Int(7)])));
+    }
+
+    #[test]
+    fn test_display_sorted() {
+        use crate::constraint::Kind;
+
+        let build = |members: Vec<ConstraintNode>| {
+            let mut constraint = Constraint::new("E".to_string());
+            constraint.add_node("E".to_string(), ConstraintNode::Enum(members));
+            constraint.add_node(
+                "P".to_string(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(2)),
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+                ]),
+            );
+            constraint
+        };
+        let members = vec![
+            ConstraintNode::Leaf(AtomicConstraint::LiteralString("b".to_string())),
+            ConstraintNode::Kind(Kind::Int),
+            ConstraintNode::Def("P".to_string()),
+            ConstraintNode::Leaf(AtomicConstraint::Nil),
+        ];
+        let forward = build(members.clone());
+        let backward = build(members.into_iter().rev().collect());
+
+        assert_ne!(forward.to_string(), backward.to_string());
+        assert_eq!(forward.display_sorted(), backward.display_sorted());
+        // 嵌套枚举的成员同样被排序，排序后的输出仍然可以被读回
+        assert!(forward.display_sorted().contains("1 | 2"));
+        assert!(Constraint::parse(&forward.display_sorted()).unwrap() == forward);
+    }
+
+    #[test]
+    fn test_pair_distribution() {
+        let int = |i: i32| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let pair = |a: ConstraintNode, b: ConstraintNode| ConstraintNode::Pair(a.into(), b.into());
+        let either = |a: i32, b: i32| ConstraintNode::Enum(vec![int(a), int(b)]);
+
+        // 左侧分量是枚举，本来就能判定
+        let factored = single(pair(either(1, 2), int(3)));
+        assert!(factored.super_of(&single(pair(int(1), int(3)))));
+        assert!(!factored.super_of(&single(pair(int(1), int(4)))));
+
+        // 右侧的单个 Pair 被展开后才能与左侧枚举的成员逐个比较
+        let distributed = single(ConstraintNode::Enum(vec![
+            pair(int(1), int(3)),
+            pair(int(2), int(3)),
+        ]));
+        assert!(distributed.super_of(&factored));
+        assert!(factored.super_of(&distributed));
+        assert!(distributed == factored);
+
+        // 两侧分量都是枚举
+        let square = single(pair(either(1, 2), either(3, 4)));
+        let corners = single(ConstraintNode::Enum(vec![
+            pair(int(1), either(3, 4)),
+            pair(int(2), int(3)),
+            pair(int(2), int(4)),
+        ]));
+        assert!(corners == square);
+        let missing = single(ConstraintNode::Enum(vec![
+            pair(int(1), either(3, 4)),
+            pair(int(2), int(3)),
+        ]));
+        assert!(!missing.super_of(&square));
+        assert!(square.super_of(&missing));
+
+        // 定义、嵌套的 Pair、小的区间与整数集合同样先拆成成员
+        let p = |source: &str| crate::parser::parse(source).unwrap();
+        assert!(distributed.super_of(&p("(X := 1 | 2, 3)")));
+        assert!(p("((1, 3), 4) | ((2, 3), 4)").super_of(&p("((1 | 2, 3), 4)")));
+        assert!(distributed.super_of(&p("(1..=2, 3)")));
+        assert!(!distributed.super_of(&p("(1..=3, 3)")));
+        let set = single(pair(ConstraintNode::IntSet([1, 2].into()), int(3)));
+        assert!(distributed.super_of(&set));
+        // 递归的定义保持不展开
+        let lists = p("L := (1, L) | (2, L) | Nil");
+        assert!(!lists.super_of(&p("(1 | 2, M := (Int, M) | Nil)")));
+        assert!(lists.super_of(&p("(1 | 2, M := (1 | 2, M) | Nil)")));
+        assert!(p("M := (1, M) | (2, M) | Nil").super_of(&p("L := (1 | 2, L) | Nil")));
+    }
+
+    #[test]
+    fn test_constraint_pool() {
+        use crate::pool::ConstraintPool;
+
+        let list = |name: &str, element: ConstraintNode| {
+            let mut constraint = Constraint::new(name.to_string());
+            constraint.add_node(
+                name.to_string(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Leaf(AtomicConstraint::Nil),
+                    ConstraintNode::Pair(
+                        element.into(),
+                        ConstraintNode::Def(name.to_string()).into(),
+                    ),
+                ]),
+            );
+            constraint
+        };
+        let mut pool = ConstraintPool::new();
+        let any = pool.insert(list("L_T", ConstraintNode::T));
+        // 只相差定义名的图规范形式相同，共享同一个编号
+        let any_again = pool.insert(list("List", ConstraintNode::T));
+        let ones = pool.insert(list(
+            "L_1",
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+        ));
+        assert_eq!(any, any_again);
+        assert_ne!(any, ones);
+        assert_eq!(pool.len(), 2);
+
+        assert!(pool.super_of(any, ones));
+        assert!(!pool.super_of(ones, any));
+        assert_eq!(pool.results.len(), 2);
+        // 再次判定直接使用缓存
+        assert!(pool.super_of(any_again, ones));
+        assert_eq!(pool.results.len(), 2);
+        assert!(pool.super_of(any, any_again));
+        assert_eq!(pool.get(any).entry(), "L_T");
+
+        // 规范形式不同的语义相等约束分别驻留，判定结果依然正确
+        let factored = pool.insert(crate::parser::parse("(1 | 2, 3)").unwrap());
+        let distributed = pool.insert(crate::parser::parse("(1, 3) | (2, 3)").unwrap());
+        assert_ne!(factored, distributed);
+        assert!(pool.super_of(factored, distributed));
+        assert!(pool.super_of(distributed, factored));
+    }
+
+    #[test]
+    fn test_project_pairs() {
+        use crate::constraint::Kind;
+
+        let int = |i: i32| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let string = |s: &str| ConstraintNode::Leaf(AtomicConstraint::LiteralString(s.to_string()));
+        let mut rows = Constraint::new("R".to_string());
+        rows.add_node(
+            "R".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(int(1).into(), string("a").into()),
+                ConstraintNode::Pair(int(2).into(), string("b").into()),
+            ]),
+        );
+        let left = rows.project_left();
+        assert_eq!(
+            left.get_node(left.entry()),
+            Some(&ConstraintNode::Enum(vec![int(1), int(2)]))
+        );
+        let right = rows.project_right();
+        assert_eq!(
+            right.get_node(right.entry()),
+            Some(&ConstraintNode::Enum(vec![string("a"), string("b")]))
+        );
+
+        // 递归的列表类型：左投影是元素类型，右投影是列表本身
+        let mut list = Constraint::new("L".to_string());
+        list.add_node(
+            "L".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Pair(
+                    ConstraintNode::Kind(Kind::Int).into(),
+                    ConstraintNode::Def("L".to_string()).into(),
+                ),
+            ]),
+        );
+        let mut int_kind = Constraint::new("I".to_string());
+        int_kind.add_node("I".to_string(), ConstraintNode::Kind(Kind::Int));
+        assert!(list.project_left() == int_kind);
+        assert!(list.project_right() == list);
+    }
+
+    #[test]
+    fn test_custom_constraint() {
+        use crate::constraint::Kind;
+        use crate::custom::CustomConstraint;
+        use crate::value::Value;
+        use std::any::Any;
+        use std::hash::{Hash, Hasher};
+        use std::sync::Arc;
+
+        // 能被 n 整除的整数
+        #[derive(Debug)]
+        struct DivisibleBy(i32);
+
+        impl CustomConstraint for DivisibleBy {
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+
+            fn subsumes(&self, other: &dyn CustomConstraint) -> Option<bool> {
+                let other = other.as_any().downcast_ref::<DivisibleBy>()?;
+                Some(other.0 % self.0 == 0)
+            }
+
+            fn hash_custom(&self, mut state: &mut dyn Hasher) {
+                self.0.hash(&mut state);
+            }
+
+            fn eq_custom(&self, other: &dyn CustomConstraint) -> bool {
+                other
+                    .as_any()
+                    .downcast_ref::<DivisibleBy>()
+                    .is_some_and(|other| other.0 == self.0)
+            }
+
+            fn contains(&self, v: &Value) -> bool {
+                matches!(v, Value::Int(i) if i % self.0 == 0)
+            }
+        }
+
+        let divisible = |n: i32| single(ConstraintNode::Custom(Arc::new(DivisibleBy(n))));
+        let even = divisible(2);
+        let by_four = divisible(4);
+
+        assert!(even.super_of(&by_four));
+        assert!(!by_four.super_of(&even));
+        assert!(even == divisible(2));
+        assert!(even.bisimilar(&divisible(2)));
+        assert!(!even.bisimilar(&by_four));
+        assert!(even.contains(&Value::Int(6)));
+        assert!(!even.contains(&Value::Int(7)));
+
+        // 与内置节点组合：T 与 Enum 照常处理，其他内置节点与自定义节点互不包含
+        assert!(single(ConstraintNode::T).super_of(&even));
+        assert!(!even.super_of(&single(ConstraintNode::Kind(Kind::Int))));
+        assert!(!single(ConstraintNode::Kind(Kind::Int)).super_of(&even));
+        let either = single(ConstraintNode::Enum(vec![
+            ConstraintNode::Custom(Arc::new(DivisibleBy(3))),
+            ConstraintNode::Custom(Arc::new(DivisibleBy(2))),
+        ]));
+        assert!(either.super_of(&by_four));
+        assert!(!either.super_of(&divisible(5)));
+        assert_eq!(even.to_string(), "DivisibleBy(2)");
+    }
+
+    #[test]
+    fn test_reduce_drops_self_members() {
+        let mut idempotent = Constraint::new("A".to_string());
+        idempotent.add_node(
+            "A".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Def("A".to_string()),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        let simplified = idempotent.simplify();
+        assert_eq!(
+            simplified.get_node("A"),
+            Some(&ConstraintNode::Leaf(AtomicConstraint::Nil))
+        );
+        let mut five = Constraint::new("N".to_string());
+        five.add_node(
+            "N".to_string(),
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(5)),
+        );
+        assert!(!simplified.super_of(&five));
+
+        // 经过构造子的自引用是真正的递归，保持不变
+        let mut list = Constraint::new("L".to_string());
+        let body = ConstraintNode::Enum(vec![
+            ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ConstraintNode::Pair(
+                ConstraintNode::T.into(),
+                ConstraintNode::Def("L".to_string()).into(),
+            ),
+        ]);
+        list.add_node("L".to_string(), body.clone());
+        assert_eq!(list.reduce().get_node("L"), Some(&body));
+    }
+
+    #[test]
+    fn test_cycle_count() {
+        use crate::error::ConstraintError;
+
+        let def = |name: &str| ConstraintNode::Def(name.to_string());
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                nil(),
+                ConstraintNode::Pair(ConstraintNode::T.into(), def("L_T").into()),
+            ]),
+        );
+        assert_eq!(list_def.cycle_count(), 1);
+
+        // A 与 B 互相引用构成一个环，A 的自引用是另一个环
+        let mut mutual = Constraint::new("A".to_string());
+        mutual.add_node(
+            "A".to_string(),
+            ConstraintNode::Enum(vec![
+                nil(),
+                ConstraintNode::Pair(def("B").into(), def("A").into()),
+            ]),
+        );
+        mutual.add_node(
+            "B".to_string(),
+            ConstraintNode::Pair(def("A").into(), def("A").into()),
+        );
+        assert_eq!(mutual.cycle_count(), 2);
+
+        let mut flat = Constraint::new("P".to_string());
+        flat.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(def("Q").into(), def("Q").into()),
+        );
+        flat.add_node("Q".to_string(), nil());
+        assert_eq!(flat.cycle_count(), 0);
+
+        assert_eq!(mutual.reject_if_cycles_exceed(2), Ok(()));
+        assert_eq!(
+            mutual.reject_if_cycles_exceed(1),
+            Err(ConstraintError::TooManyCycles { cycles: 2, max: 1 })
+        );
+        assert_eq!(flat.reject_if_cycles_exceed(0), Ok(()));
+    }
+
+    #[test]
+    fn test_contains_cyclic_values() {
+        use crate::constraint::Kind;
+        use crate::value::Value;
+
+        let list = |element: ConstraintNode| {
+            let mut constraint = Constraint::new("L".to_string());
+            constraint.add_node(
+                "L".to_string(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Leaf(AtomicConstraint::Nil),
+                    ConstraintNode::Pair(
+                        element.into(),
+                        ConstraintNode::Def("L".to_string()).into(),
+                    ),
+                ]),
+            );
+            constraint
+        };
+        let any_list = list(ConstraintNode::T);
+        let int_list = list(ConstraintNode::Kind(Kind::Int));
+
+        // 循环列表 1, 2, 1, 2, ...
+        let values = vec![
+            Value::Pair(Value::Int(1).into(), Value::Ref(1).into()),
+            Value::Pair(Value::Int(2).into(), Value::Ref(0).into()),
+        ];
+        assert!(any_list.contains_in(&Value::Ref(0), &values));
+        assert!(int_list.contains_in(&Value::Ref(0), &values));
+        assert!(int_list.contains_in(&values[1], &values));
+
+        // 环上有一个元素不满足约束
+        let mixed = vec![
+            Value::Pair(Value::Int(1).into(), Value::Ref(1).into()),
+            Value::Pair(Value::String("x".to_string()).into(), Value::Ref(0).into()),
+        ];
+        assert!(any_list.contains_in(&Value::Ref(0), &mixed));
+        assert!(!int_list.contains_in(&Value::Ref(0), &mixed));
+
+        // 悬空的引用与只由引用组成的环不满足任何约束
+        assert!(!any_list.contains_in(&Value::Ref(5), &values));
+        assert!(!any_list.contains_in(&Value::Ref(0), &[Value::Ref(0)]));
+        assert!(!any_list.contains(&Value::Ref(0)));
+    }
+
+    #[test]
+    fn test_super_of_finite() {
+        use crate::constraint::Kind;
+
+        let list = |name: &str, element: ConstraintNode| {
+            let mut constraint = Constraint::new(name.to_string());
+            constraint.add_node(
+                name.to_string(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Leaf(AtomicConstraint::Nil),
+                    ConstraintNode::Pair(
+                        element.into(),
+                        ConstraintNode::Def(name.to_string()).into(),
+                    ),
+                ]),
+            );
+            constraint
+        };
+        let any_list = list("L_T", ConstraintNode::T);
+        let int_list = list("L_I", ConstraintNode::Kind(Kind::Int));
+        let mut int = Constraint::new("I".to_string());
+        int.add_node("I".to_string(), ConstraintNode::Kind(Kind::Int));
+
+        // 有限的列表只需有限次展开
+        let finite = Constraint::bounded_list(&int, 3);
+        assert!(any_list.super_of_finite(&finite));
+        assert!(int_list.super_of_finite(&finite));
+        assert!(!int_list.super_of_finite(&Constraint::fixed_list(&any_list, 1)));
+
+        // 两侧都是递归类型时需要无穷展开，归纳模式下不成立
+        assert!(any_list.super_of(&int_list));
+        assert!(!any_list.super_of_finite(&int_list));
+
+        // 展开深度不足时同样失败
+        assert!(!any_list.super_of_finite_with_fuel(&Constraint::fixed_list(&int, 4), 2));
+        assert!(any_list.super_of_finite_with_fuel(&Constraint::fixed_list(&int, 4), 8));
+    }
+
+    #[test]
+    fn test_head_constructors() {
+        use crate::constraint::Kind;
+        use crate::shape::HeadConstructor;
+        use std::collections::HashSet;
+
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+            ]),
+        );
+        assert_eq!(
+            list_def.head_constructors(),
+            HashSet::from([HeadConstructor::Pair, HeadConstructor::Nil])
+        );
+
+        // 经过定义别名与嵌套枚举
+        let mut mixed = Constraint::new("M".to_string());
+        mixed.add_node(
+            "M".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Def("L_T".to_string()),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+                    ConstraintNode::Kind(Kind::String),
+                    ConstraintNode::F,
+                ]),
+            ]),
+        );
+        mixed.add_node("L_T".to_string(), list_def.get_node("L_T").unwrap().clone());
+        assert_eq!(
+            mixed.head_constructors(),
+            HashSet::from([
+                HeadConstructor::Pair,
+                HeadConstructor::Nil,
+                HeadConstructor::IntLit(1),
+                HeadConstructor::Kind(Kind::String),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_list_of() {
+        let one = || ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1));
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                nil(),
+            ]),
+        );
+        // 与 test2 中手工构建的 S_L 相同
+        let mut simple_list = Constraint::new("S_L".to_string());
+        simple_list.add_node(
+            "S_L".to_string(),
+            ConstraintNode::Pair(
+                one().into(),
+                ConstraintNode::Pair(one().into(), nil().into()).into(),
+            ),
+        );
+
+        let built = Constraint::list_of(&[one(), one()], nil());
+        assert_eq!(built.get_node("List"), simple_list.get_node("S_L"));
+        assert!(built == simple_list);
+        assert!(list_def.super_of(&built));
+        assert!(Constraint::list(&[one(), one()]) == built);
+
+        // 非 Nil 的结尾不是列表
+        let improper = Constraint::list_of(&[one()], one());
+        assert!(!list_def.super_of(&improper));
+        assert_eq!(Constraint::list(&[]).get_node("List"), Some(&nil()));
+    }
+
+    #[test]
+    fn test_closed_world() {
+        use crate::constraint::Kind;
+
+        let universe = [
+            ConstraintNode::Kind(Kind::Int),
+            ConstraintNode::Kind(Kind::String),
+        ];
+        let top = single(ConstraintNode::T);
+        let int = single(ConstraintNode::Kind(Kind::Int));
+        let string = single(ConstraintNode::Kind(Kind::String));
+        let int_or_string = single(ConstraintNode::Enum(universe.to_vec()));
+
+        // 开放世界中 T 严格大于已知类别的并
+        assert!(!int_or_string.super_of(&top));
+        assert!(int_or_string.super_of_closed(&top, &universe));
+        assert!(top.super_of_closed(&int, &universe));
+        assert!(!int.super_of_closed(&top, &universe));
+
+        let rest = int.complement_within_closed(&top, &universe);
+        assert!(rest == string);
+        assert_eq!(
+            rest.get_node(rest.entry()),
+            Some(&ConstraintNode::Kind(Kind::String))
+        );
+    }
+
+    #[test]
+    fn test_is_top() {
+        use crate::constraint::Kind;
+        use std::collections::HashMap;
+
+        assert!(single(ConstraintNode::T).is_top());
+        assert!(
+            single(ConstraintNode::Enum(vec![
+                ConstraintNode::T,
+                ConstraintNode::Kind(Kind::Int)
+            ]))
+            .is_top()
+        );
+        assert!(!single(ConstraintNode::Kind(Kind::Int)).is_top());
+        assert!(
+            !single(ConstraintNode::Pair(
+                ConstraintNode::T.into(),
+                ConstraintNode::T.into()
+            ))
+            .is_top()
+        );
+
+        // 经过循环的 Def 到达 T
+        let looping = Constraint::from_graph(
+            HashMap::from([
+                (
+                    "A".to_string(),
+                    ConstraintNode::Enum(vec![
+                        ConstraintNode::Def("B".to_string()),
+                        ConstraintNode::Kind(Kind::Int),
+                    ]),
+                ),
+                (
+                    "B".to_string(),
+                    ConstraintNode::Enum(vec![
+                        ConstraintNode::Def("A".to_string()),
+                        ConstraintNode::T,
+                    ]),
+                ),
+            ]),
+            "A".to_string(),
+        );
+        assert!(looping.is_top());
+
+        let universe = [
+            ConstraintNode::Kind(Kind::Int),
+            ConstraintNode::Kind(Kind::String),
+        ];
+        let covering = single(ConstraintNode::Enum(universe.to_vec()));
+        assert!(!covering.is_top());
+        assert!(covering.is_top_closed(&universe));
+        assert!(!single(ConstraintNode::Kind(Kind::Int)).is_top_closed(&universe));
+    }
+
+    #[test]
+    fn test_disjoint_partition() {
+        use crate::constraint::{Bound, Kind};
+
+        let int_range = |lo: i32, hi: i32| ConstraintNode::Range {
+            lo: Bound::Inclusive(AtomicConstraint::LiteralInt(lo)),
+            hi: Bound::Inclusive(AtomicConstraint::LiteralInt(hi)),
+        };
+        let input = single(ConstraintNode::Enum(vec![int_range(1, 5), int_range(3, 8)]));
+        let cases = input.disjoint_partition();
+        let entries: Vec<_> = cases
+            .iter()
+            .map(|case| case.get_node(case.entry()).cloned())
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                Some(int_range(1, 2)),
+                Some(int_range(3, 5)),
+                Some(int_range(6, 8))
+            ]
+        );
+        for (i, a) in cases.iter().enumerate() {
+            for b in &cases[i + 1..] {
+                assert!(a.disjoint(b));
+            }
+        }
+        // 检查器不会把区间拆开与多个成员比较，因此与合并后的输入比较
+        assert!(Constraint::union_all(&cases) == input.simplify());
+
+        // 被同一组成员覆盖的不相邻片段归为同一个分支，非整数成员减去之前的分支
+        let nested = single(ConstraintNode::Enum(vec![
+            int_range(1, 10),
+            int_range(3, 5),
+            ConstraintNode::Kind(Kind::String),
+            ConstraintNode::Leaf(AtomicConstraint::LiteralString("a".to_string())),
+        ]));
+        let cases = nested.disjoint_partition();
+        assert_eq!(cases.len(), 3);
+        assert!(
+            cases[0]
+                == single(ConstraintNode::Enum(vec![
+                    int_range(1, 2),
+                    int_range(6, 10)
+                ]))
+        );
+        assert!(Constraint::union_all(&cases) == nested.simplify());
+
+        // 整数集合按连续的段参与切分
+        let set = single(ConstraintNode::Enum(vec![
+            ConstraintNode::IntSet([1, 2, 3, 10].into()),
+            int_range(2, 4),
+        ]));
+        let cases = set.disjoint_partition();
+        let entries: Vec<_> = cases
+            .iter()
+            .map(|case| case.get_node(case.entry()).cloned())
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                Some(ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1))),
+                Some(int_range(2, 3)),
+                Some(ConstraintNode::Leaf(AtomicConstraint::LiteralInt(4))),
+                Some(ConstraintNode::Leaf(AtomicConstraint::LiteralInt(10))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduce_bounded() {
+        use crate::constraint::{Bound, Kind};
+
+        let ints = |values: &mut dyn Iterator<Item = i32>| {
+            single(ConstraintNode::Enum(
+                values
+                    .map(|i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)))
+                    .collect(),
+            ))
+        };
+
+        let wide = ints(&mut (0..10_000));
+        let bounded = wide.reduce_bounded(16);
+        assert_eq!(
+            bounded.get_node("C"),
+            Some(&ConstraintNode::Range {
+                lo: Bound::Inclusive(AtomicConstraint::LiteralInt(0)),
+                hi: Bound::Inclusive(AtomicConstraint::LiteralInt(9_999)),
+            })
+        );
+        assert!(bounded.super_of(&wide));
+
+        // 不相邻的字面量跨过空隙合并，结果是原约束的超约束
+        let sparse = ints(&mut (0..1_000).map(|i| i * 3));
+        let bounded = sparse.reduce_bounded(8);
+        let Some(ConstraintNode::Enum(members)) = bounded.get_node("C") else {
+            panic!("expected an enum");
+        };
+        assert!(members.len() <= 8);
+        assert!(bounded.super_of(&sparse));
+
+        // 不足宽度的枚举保持精确（大量整数字面量归约为整数集合）；无法用区间表示的字面量
+        // 放宽为类别
+        assert_eq!(
+            sparse.reduce_bounded(1_000).get_node("C"),
+            Some(&ConstraintNode::IntSet((0..1_000).map(|i| i * 3).collect()))
+        );
+        let strings = single(ConstraintNode::Enum(
+            (0..100)
+                .map(|i| ConstraintNode::Leaf(AtomicConstraint::LiteralString(i.to_string())))
+                .chain([ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1))])
+                .collect(),
+        ));
+        assert_eq!(
+            strings.reduce_bounded(4).get_node("C"),
+            Some(&ConstraintNode::Enum(vec![
+                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)),
+                ConstraintNode::Kind(Kind::String)
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_display_debug() {
+        let mut list = Constraint::new("L_T".to_string());
+        list.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        let output = list.display_debug();
+        assert!(output.starts_with("L_T@0 := "));
+        assert_eq!(output.matches("<cycle: L_T>").count(), 1);
+
+        // 被多处引用但不成环的定义只展开一次，不标注循环
+        let mut shared = Constraint::new("A".to_string());
+        shared.add_node(
+            "A".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Def("B".to_string()).into(),
+                ConstraintNode::Def("B".to_string()).into(),
+            ),
+        );
+        shared.add_node("B".to_string(), ConstraintNode::Leaf(AtomicConstraint::Nil));
+        assert_eq!(shared.display_debug(), "A@0 := (B@1 := Nil, B)");
+    }
+
+    #[test]
+    fn test_is_uniformly() {
+        use crate::constraint::Kind;
+        use crate::shape::HeadConstructor;
+
+        let mut list = Constraint::new("L_T".to_string());
+        list.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        assert!(!list.is_uniformly(HeadConstructor::Pair));
+
+        // 各成员都是 Pair，F 不贡献构造子
+        let mut pairs = Constraint::new("P".to_string());
+        pairs.add_node(
+            "P".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::Kind(Kind::Int).into(),
+                    ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+                ),
+                ConstraintNode::Def("Q".to_string()),
+                ConstraintNode::F,
+            ]),
+        );
+        pairs.add_node(
+            "Q".to_string(),
+            ConstraintNode::Pair(ConstraintNode::T.into(), ConstraintNode::T.into()),
+        );
+        assert!(pairs.is_uniformly(HeadConstructor::Pair));
+        assert!(!pairs.is_uniformly(HeadConstructor::Nil));
+    }
+
+    #[test]
+    fn test_canonicalize_lists() {
+        let one = || ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1));
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let mut simple_list = Constraint::new("S_L".to_string());
+        simple_list.add_node(
+            "S_L".to_string(),
+            ConstraintNode::Pair(
+                one().into(),
+                ConstraintNode::Pair(one().into(), nil().into()).into(),
+            ),
+        );
+        // 同一个列表，经由中间定义构造
+        let mut chained = Constraint::new("C".to_string());
+        chained.add_node(
+            "C".to_string(),
+            ConstraintNode::Pair(one().into(), ConstraintNode::Def("C1".to_string()).into()),
+        );
+        chained.add_node(
+            "C1".to_string(),
+            ConstraintNode::Pair(one().into(), ConstraintNode::Def("C2".to_string()).into()),
+        );
+        chained.add_node("C2".to_string(), nil());
+
+        let canonical = chained.canonicalize_lists();
+        assert_eq!(
+            canonical.get_node("C"),
+            simple_list.canonicalize_lists().get_node("S_L")
+        );
+        assert_eq!(canonical.nodes().count(), 1);
+        assert!(canonical.bisimilar(&simple_list));
+
+        // 递归的列表与不以 Nil 结尾的 Pair 保持不变
+        let mut others = Constraint::new("L_T".to_string());
+        others.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Pair(one().into(), ConstraintNode::Def("P".to_string()).into()),
+                nil(),
+            ]),
+        );
+        others.add_node(
+            "P".to_string(),
+            ConstraintNode::Pair(one().into(), one().into()),
+        );
+        let canonical = others.canonicalize_lists();
+        assert_eq!(canonical.get_node("L_T"), others.get_node("L_T"));
+        assert_eq!(canonical.get_node("P"), others.get_node("P"));
+    }
+
+    #[test]
+    fn test_restrict_to_kind() {
+        use crate::constraint::{Bound, Kind};
+        use std::collections::HashMap;
+
+        let int = |i: i32| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let mixed = Constraint::from_graph(
+            HashMap::from([
+                (
+                    "A".to_string(),
+                    ConstraintNode::Enum(vec![
+                        int(1),
+                        ConstraintNode::Leaf(AtomicConstraint::LiteralString("a".to_string())),
+                        int(2),
+                        ConstraintNode::Leaf(AtomicConstraint::LiteralChar('t')),
+                        ConstraintNode::Def("B".to_string()),
+                    ]),
+                ),
+                (
+                    "B".to_string(),
+                    ConstraintNode::Enum(vec![
+                        ConstraintNode::Leaf(AtomicConstraint::Nil),
+                        ConstraintNode::Pair(int(3).into(), int(4).into()),
+                    ]),
+                ),
+            ]),
+            "A".to_string(),
+        );
+        let ints = mixed.restrict_to_kind(Kind::Int);
+        assert_eq!(
+            ints.get_node("A"),
+            Some(&ConstraintNode::Enum(vec![int(1), int(2)]))
+        );
+        assert_eq!(ints.to_string(), "1 | 2");
+        assert!(mixed.super_of(&ints));
+
+        assert_eq!(
+            mixed.restrict_to_kind(Kind::Float).get_node("A"),
+            Some(&ConstraintNode::F)
+        );
+        // T 与两端都无界的区间贡献整个类别
+        let top = Constraint::from_graph(
+            HashMap::from([(
+                "A".to_string(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::T,
+                    ConstraintNode::Range {
+                        lo: Bound::Unbounded,
+                        hi: Bound::Unbounded,
+                    },
+                ]),
+            )]),
+            "A".to_string(),
+        );
+        assert_eq!(
+            top.restrict_to_kind(Kind::String).get_node("A"),
+            Some(&ConstraintNode::Kind(Kind::String))
+        );
+    }
+
+    #[test]
+    fn test_diff_defs() {
+        use crate::constraint::Kind;
+        use std::collections::{BTreeSet, HashMap};
+
+        let graph = |defs: Vec<(&str, ConstraintNode)>| {
+            Constraint::from_graph(
+                defs.into_iter()
+                    .map(|(name, node)| (name.to_string(), node))
+                    .collect::<HashMap<_, _>>(),
+                "A".to_string(),
+            )
+        };
+        let entry = || {
+            ConstraintNode::Pair(
+                ConstraintNode::Def("B".to_string()).into(),
+                ConstraintNode::Def("C".to_string()).into(),
+            )
+        };
+        let nil = || ConstraintNode::Leaf(AtomicConstraint::Nil);
+        let old = graph(vec![
+            ("A", entry()),
+            ("B", ConstraintNode::Kind(Kind::Int)),
+            ("C", nil()),
+        ]);
+
+        let edited = graph(vec![
+            ("A", entry()),
+            ("B", ConstraintNode::Kind(Kind::String)),
+            ("C", nil()),
+        ]);
+        let diff = edited.diff_defs(&old);
+        assert_eq!(diff.changed, BTreeSet::from(["B".to_string()]));
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+
+        let renamed = graph(vec![
+            ("A", entry()),
+            ("B", ConstraintNode::Kind(Kind::Int)),
+            ("D", nil()),
+        ]);
+        let diff = renamed.diff_defs(&old);
+        assert_eq!(diff.added, BTreeSet::from(["D".to_string()]));
+        assert_eq!(diff.removed, BTreeSet::from(["C".to_string()]));
+        assert!(diff.changed.is_empty());
+        assert!(old.diff_defs(&old).is_empty());
+    }
+
+    #[test]
+    fn test_negative_ints() {
+        use crate::constraint::{Bound, Kind};
+        use crate::parser::parse;
+        use std::collections::HashMap;
+
+        let int = |i: i32| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i));
+        let parsed = parse("-5 | -3").unwrap();
+        assert_eq!(
+            parsed.get_node(parsed.entry()),
+            Some(&ConstraintNode::Enum(vec![int(-5), int(-3)]))
+        );
+        assert_eq!(parsed.to_string(), "-5 | -3");
+        assert_eq!(parse("-5").unwrap().to_string(), "-5");
+
+        let range = parse("-10..=-1").unwrap();
+        assert_eq!(
+            range.get_node(range.entry()),
+            Some(&ConstraintNode::Range {
+                lo: Bound::Inclusive(AtomicConstraint::LiteralInt(-10)),
+                hi: Bound::Inclusive(AtomicConstraint::LiteralInt(-1)),
+            })
+        );
+        assert_eq!(range.to_string(), "-10..=-1");
+        assert!(parse(&range.to_string()).unwrap() == range);
+        assert!(range.super_of(&parse("-7").unwrap()));
+        assert!(!range.super_of(&parse("-11").unwrap()));
+        assert!(!range.super_of(&parse("0").unwrap()));
+        assert!(range.super_of(&parse("-9..-1").unwrap()));
+
+        // 补集在负数一侧与整数的极值处都正确
+        let ints = Constraint::from_graph(
+            HashMap::from([("I".to_string(), ConstraintNode::Kind(Kind::Int))]),
+            "I".to_string(),
+        );
+        let rest = range.complement_within(&ints);
+        assert!(rest == parse("..-10 | 0..").unwrap());
+        assert!(rest.super_of(&parse("-11 | 0 | -2147483648").unwrap()));
+        assert!(!rest.super_of(&parse("-7").unwrap()));
+        let extreme = parse("-2147483648..=-1").unwrap();
+        assert!(extreme.complement_within(&ints) == parse("0..").unwrap());
+        assert!(parse("-2147483649").is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        use crate::constraint::Ordering4;
+        use crate::parser::parse;
+
+        let int = parse("Int").unwrap();
+        let string = parse("String").unwrap();
+        assert_eq!(int.compare(&parse("Int").unwrap()), Ordering4::Equal);
+        let list = parse("X := (Int, X) | Nil").unwrap();
+        let reordered = parse("Y := Nil | (Int, Y)").unwrap();
+        assert_eq!(list.compare(&reordered), Ordering4::Equal);
+        assert_eq!(parse("1").unwrap().compare(&int), Ordering4::Subtype);
+        assert_eq!(int.compare(&parse("1 | 2").unwrap()), Ordering4::Supertype);
+        assert_eq!(int.compare(&string), Ordering4::Incomparable);
+        assert_eq!(
+            parse("1 | \"a\"")
+                .unwrap()
+                .compare(&parse("2 | \"a\"").unwrap()),
+            Ordering4::Incomparable
+        );
+
+        // 互模拟检查失败时，其中已经证明的定义对留给两个方向的判定复用
+        let a = parse("(X := (Int, X) | Nil, 1)").unwrap();
+        let b = parse("(Y := Nil | (Int, Y), 1 | 2)").unwrap();
+        let mut bisim = crate::bisim::BisimChecker::new(&a, &b);
+        assert!(!bisim.check(
+            a.get_node(a.entry()).unwrap(),
+            b.get_node(b.entry()).unwrap()
+        ));
+        assert_eq!(bisim.proven().len(), 1);
+        assert_eq!(a.compare(&b), Ordering4::Subtype);
+        assert_eq!(b.compare(&a), Ordering4::Supertype);
+    }
+
+    #[test]
+    fn test_super_of_diagnostic() {
+        use crate::parser::parse;
+
+        let list = parse("L := (Int, L) | Nil").unwrap();
+        let long = Constraint::fixed_list(&parse("Int").unwrap(), 40);
+        let (result, diagnostics) = list.super_of_diagnostic(&long);
+        assert!(result);
+        assert!(diagnostics.max_depth > 40);
+        assert!(diagnostics.steps >= diagnostics.max_depth);
+        assert!(!diagnostics.threshold_hit);
+        assert!(diagnostics.goal_stack.is_empty());
+
+        // 阈值很小时记录超过阈值时正在展开的定义判定，判定结果不受影响
+        let (result, diagnostics) = list.super_of_diagnostic_with_threshold(&long, 10);
+        assert!(result);
+        assert!(diagnostics.threshold_hit);
+        assert!(
+            diagnostics
+                .goal_stack
+                .iter()
+                .all(|goal| goal.starts_with("L >= (Int, "))
+        );
+        assert!(!diagnostics.goal_stack.is_empty());
+    }
+
+    #[test]
+    fn test_predicate() {
+        use crate::constraint::Kind;
+        use crate::value::Value;
+
+        let mut even = Constraint::new("Even".to_string());
+        let id = even.register_predicate(|v| matches!(v, Value::Int(i) if i % 2 == 0));
+        even.add_node(
+            "Even".to_string(),
+            ConstraintNode::Enum(vec![ConstraintNode::Predicate(id), ConstraintNode::F]),
+        );
+        assert!(even.contains(&Value::Int(4)));
+        assert!(!even.contains(&Value::Int(3)));
+        assert!(!even.contains(&Value::String("4".to_string())));
+        // 化简保留谓词表
+        assert!(even.simplify().contains(&Value::Int(4)));
+        assert_eq!(even.simplify().to_string(), "Predicate#0");
+
+        // 谓词只包含自身与 F，只被 T 与自身包含
+        let mut top = Constraint::new("T".to_string());
+        top.add_node("T".to_string(), ConstraintNode::T);
+        let mut int = Constraint::new("I".to_string());
+        int.add_node("I".to_string(), ConstraintNode::Kind(Kind::Int));
+        assert!(even.super_of(&even.clone()));
+        assert!(even.simplify() == even);
+        assert!(top.super_of(&even));
+        assert!(!int.super_of(&even));
+        assert!(!even.super_of(&int));
+
+        // 另一个约束中同一编号的谓词是不同的闭包
+        let mut odd = Constraint::new("Odd".to_string());
+        let odd_id = odd.register_predicate(|v| matches!(v, Value::Int(i) if i % 2 != 0));
+        odd.add_node("Odd".to_string(), ConstraintNode::Predicate(odd_id));
+        assert_eq!(odd_id, id);
+        assert!(!even.super_of(&odd));
+        assert!(odd.contains(&Value::Int(3)));
+    }
+
+    #[test]
+    fn test_from_str() {
+        let list: Constraint = "L_T := (T, L_T) | Nil".parse().unwrap();
+        let simple = Constraint::try_from("(1, (1, Nil))").unwrap();
+        assert!(list.super_of(&simple));
+        let error = "(1,".parse::<Constraint>().unwrap_err();
+        assert_eq!(error.position, 3);
+    }
+
+    #[test]
+    fn test_hoist_common() {
+        use crate::constraint::Kind;
+
+        let int_string = || {
+            ConstraintNode::Pair(
+                ConstraintNode::Kind(Kind::Int).into(),
+                ConstraintNode::Kind(Kind::String).into(),
+            )
+        };
+        let mut original = Constraint::new("A".to_string());
+        original.add_node(
+            "A".to_string(),
+            ConstraintNode::Pair(
+                int_string().into(),
+                ConstraintNode::Enum(vec![
+                    int_string(),
+                    ConstraintNode::Leaf(AtomicConstraint::Nil),
+                ])
+                .into(),
+            ),
+        );
+        let hoisted = original.hoist_common(2);
+        assert_eq!(hoisted.get_node("Common"), Some(&int_string()));
+        assert_eq!(
+            hoisted.get_node("A"),
+            Some(&ConstraintNode::Pair(
+                ConstraintNode::Def("Common".to_string()).into(),
+                ConstraintNode::Enum(vec![
+                    ConstraintNode::Def("Common".to_string()),
+                    ConstraintNode::Leaf(AtomicConstraint::Nil)
+                ])
+                .into(),
+            ))
+        );
+        assert_eq!(hoisted.nodes().count(), 2);
+        assert!(hoisted == original);
+
+        // 出现次数不足时不提取，叶子不会被提取
+        assert_eq!(original.hoist_common(3).nodes().count(), 1);
+        let mut leaves = Constraint::new("B".to_string());
+        leaves.add_node(
+            "B".to_string(),
+            ConstraintNode::Pair(
+                ConstraintNode::Kind(Kind::Int).into(),
+                ConstraintNode::Kind(Kind::Int).into(),
+            ),
+        );
+        assert_eq!(leaves.hoist_common(2).nodes().count(), 1);
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        use crate::parser::parse;
+
+        let list = parse("L := (Int, L) | Nil").unwrap();
+        // 同一个列表：成员顺序不同、定义名不同，并经过一个非递归的中间定义
+        let rebuilt = parse("M := Nil | (Cell := (Int, M))").unwrap();
+        assert!(list == rebuilt);
+        assert_eq!(list.canonical_key(), rebuilt.canonical_key());
+        assert_eq!(list.canonical_key(), "$0 := (Int, $0) | Nil");
+
+        let strings = parse("S := (String, S) | Nil").unwrap();
+        assert_ne!(strings.canonical_key(), list.canonical_key());
+        assert_eq!(
+            parse("1 | 2").unwrap().canonical_key(),
+            parse("2 | 1").unwrap().canonical_key()
+        );
+
+        // 展开了一层的递归定义与原定义互模拟，合并后得到相同的键
+        let any_list = parse("L := (T, L) | Nil").unwrap();
+        let unrolled = parse("L := (T, M := (T, L) | Nil) | Nil").unwrap();
+        assert_eq!(any_list.canonical_key(), unrolled.canonical_key());
+        assert_eq!(any_list.canonical_key(), "$0 := (T, $0) | Nil");
+        let twice = parse("L := (T, (T, M := (T, L) | Nil) | Nil) | Nil").unwrap();
+        assert_eq!(any_list.canonical_key(), twice.canonical_key());
+
+        // 覆盖全部整数的区间合并为 Int，与同时包含浮点数的 `..` 不同
+        let ints = parse("..=0 | 1..").unwrap();
+        assert_eq!(ints.canonical_key(), parse("Int").unwrap().canonical_key());
+        assert_ne!(ints.canonical_key(), parse("..").unwrap().canonical_key());
+    }
+
+    #[test]
+    fn test_matching_members() {
+        use crate::parser::parse;
+        use crate::value::Value;
+
+        let overlapping = parse("1..=5 | 3..=8 | String").unwrap();
+        assert_eq!(overlapping.matching_members(&Value::Int(4)), vec![0, 1]);
+        assert_eq!(overlapping.matching_members(&Value::Int(7)), vec![1]);
+        assert_eq!(
+            overlapping.matching_members(&Value::String("a".to_string())),
+            vec![2]
+        );
+        assert_eq!(
+            overlapping.matching_members(&Value::Int(9)),
+            Vec::<usize>::new()
+        );
+        assert_eq!(
+            parse("Int").unwrap().matching_members(&Value::Int(9)),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_share_subtrees() {
+        use crate::parser::parse;
+
+        let tree = parse("((1, (2, 3)), ((1, (2, 3)), Nil))").unwrap();
+        let shared = tree.share_subtrees(3);
+        let entry = shared.get_node(shared.entry()).unwrap();
+        // 两处相同的 (1, (2, 3)) 共用一个定义，更小的子树保持内联
+        assert_eq!(
+            entry,
+            &ConstraintNode::Pair(
+                ConstraintNode::Def("Shared".to_string()).into(),
+                ConstraintNode::Def("Shared#1".to_string()).into(),
+            )
+        );
+        assert_eq!(
+            shared.get_node("Shared#1"),
+            Some(&ConstraintNode::Pair(
+                ConstraintNode::Def("Shared".to_string()).into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ))
+        );
+        assert_eq!(
+            shared.get_node("Shared"),
+            parse("(1, (2, 3))").unwrap().get_node("Entry")
+        );
+        assert!(shared == tree);
+        assert_eq!(
+            shared.inline_nonrecursive().get_node(shared.entry()),
+            tree.get_node(tree.entry())
+        );
+        assert_eq!(tree.share_subtrees(100).nodes().count(), 1);
+    }
+
+    #[test]
+    fn test_self_comparison_fast_path() {
+        use crate::parser::parse;
+
+        let list = parse("L := (Int, (Int, L) | Nil) | Nil").unwrap();
+        let copy = parse("L := (Int, (Int, L) | Nil) | Nil").unwrap();
+        let (result, same_graph) = list.super_of_diagnostic(&list.clone());
+        assert!(result);
+        let (result, other_graph) = list.super_of_diagnostic(&copy);
+        assert!(result);
+        // 共享同一张图时同名定义不再展开
+        assert!(same_graph.steps < other_graph.steps);
+        assert!(same_graph.goal_stack.is_empty());
+        assert!(list == list.clone());
+    }
+
+    #[test]
+    fn test_definitions() {
+        use crate::parser::parse;
+
+        let mut list_def = Constraint::new("L_T".to_string());
+        list_def.add_node(
+            "L_T".to_string(),
+            ConstraintNode::Enum(vec![
+                ConstraintNode::Pair(
+                    ConstraintNode::T.into(),
+                    ConstraintNode::Def("L_T".to_string()).into(),
+                ),
+                ConstraintNode::Leaf(AtomicConstraint::Nil),
+            ]),
+        );
+        assert_eq!(list_def.definitions(), vec![("L_T".to_string(), 0)]);
+        assert_eq!(
+            parse("Tree := (Tree, Leaf := Int) | Nil")
+                .unwrap()
+                .definitions(),
+            vec![("Leaf".to_string(), 0), ("Tree".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_int_tolerance() {
+        use crate::parser::parse;
+
+        let five = parse("5").unwrap();
+        assert!(five.super_of_with_int_tolerance(&parse("6").unwrap(), 2));
+        assert!(five.super_of_with_int_tolerance(&parse("3").unwrap(), 2));
+        assert!(!five.super_of_with_int_tolerance(&parse("8").unwrap(), 2));
+        assert!(!five.super_of(&parse("6").unwrap()));
+        assert!(!five.super_of_with_int_tolerance(&parse("6").unwrap(), -1));
+
+        // 容差同样作用于结构内部，字符串等其他字面量仍然精确比较
+        let reading = parse("(5, \"a\")").unwrap();
+        assert!(reading.super_of_with_int_tolerance(&parse("(7, \"a\")").unwrap(), 2));
+        assert!(!reading.super_of_with_int_tolerance(&parse("(7, \"b\")").unwrap(), 2));
+        // 不传递
+        assert!(
+            parse("7")
+                .unwrap()
+                .super_of_with_int_tolerance(&parse("9").unwrap(), 2)
+        );
+        assert!(!five.super_of_with_int_tolerance(&parse("9").unwrap(), 2));
+        // 两端的极值相减不会溢出
+        let max = parse("2147483647").unwrap();
+        assert!(!max.super_of_with_int_tolerance(&parse("-2147483648").unwrap(), i32::MAX));
+    }
+
+    #[test]
+    fn test_depth_truncate() {
+        use crate::constraint::Approximation;
+        use crate::parser::parse;
+
+        let list = parse("L := (T, L) | Nil").unwrap();
+        let two = parse("(1, (2, Nil))").unwrap();
+        let three = parse("(1, (2, (3, Nil)))").unwrap();
+
+        let over = list.depth_truncate(2, Approximation::Over);
+        assert!(over.recursive_defs().is_empty());
+        assert!(over.super_of(&two));
+        assert!(over.super_of(&three));
+
+        let under = list.depth_truncate(2, Approximation::Under);
+        assert!(under.recursive_defs().is_empty());
+        assert!(under.super_of(&two));
+        assert!(!under.super_of(&three));
+        assert!(list.super_of(&under));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_json_schema() {
+        use crate::parser::parse;
+        use serde_json::json;
+
+        assert_eq!(
+            parse("1..=10").unwrap().to_json_schema(),
+            json!({ "type": "integer", "minimum": 1, "maximum": 10 })
+        );
+        assert_eq!(
+            parse("1 | \"a\"").unwrap().to_json_schema(),
+            json!({ "enum": [1, "a"] })
+        );
+        assert_eq!(
+            parse("(Int, (String, Nil))").unwrap().to_json_schema(),
+            json!({
+                "type": "array",
+                "minItems": 2,
+                "prefixItems": [{ "type": "integer" }, { "type": "string" }],
+                "items": false,
+            })
+        );
+        assert_eq!(
+            parse("L := (Int, L) | Nil").unwrap().to_json_schema(),
+            json!({
+                "$ref": "#/$defs/L",
+                "$defs": {
+                    "L": {
+                        "anyOf": [
+                            { "type": "array", "items": { "type": "integer" } },
+                            { "type": "null" },
+                        ]
+                    }
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_entry_name() {
+        use crate::error::ConstraintError;
+
+        for entry in ["", "  "] {
+            assert_eq!(
+                Constraint::try_new(entry.to_string()).unwrap_err(),
+                ConstraintError::InvalidEntryName(entry.to_string())
+            );
+            assert_eq!(
+                Constraint::from_nodes(
+                    entry.to_string(),
+                    vec![(entry.to_string(), ConstraintNode::T)]
+                )
+                .unwrap_err(),
+                ConstraintError::InvalidEntryName(entry.to_string())
+            );
+        }
+        assert!(Constraint::try_new("L".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_dependencies() {
+        use crate::parser::parse;
+        use std::collections::HashSet;
+
+        let names = |names: &[&str]| -> HashSet<String> {
+            names.iter().map(|name| name.to_string()).collect()
+        };
+        let graph =
+            parse("Top := (A := (Int, B) | (A, Nil) | Nil, B := (String, A) | B | Leaf := Nil)")
+                .unwrap();
+        assert_eq!(graph.dependencies("A"), names(&["A", "B", "Leaf"]));
+        assert_eq!(graph.dependencies("B"), names(&["A", "B", "Leaf"]));
+        assert_eq!(graph.dependencies("Top"), names(&["A", "B", "Leaf"]));
+        assert_eq!(graph.dependencies("Leaf"), names(&[]));
+        assert_eq!(graph.dependents("A"), names(&["A", "B", "Top"]));
+        assert_eq!(graph.dependents("B"), names(&["A", "B", "Top"]));
+        assert_eq!(graph.dependents("Leaf"), names(&["A", "B", "Top"]));
+        assert_eq!(graph.dependents("Top"), names(&[]));
+    }
+
+    #[test]
+    fn test_render() {
+        use crate::error::ConstraintError;
+        use crate::parser::parse;
+        use std::collections::HashMap;
+
+        let list = parse("L := (Int, L) | Nil").unwrap();
+        assert_eq!(list.render(), Ok(list.to_string()));
+
+        let missing_entry = Constraint::new("L".to_string());
+        assert_eq!(
+            missing_entry.render(),
+            Err(ConstraintError::MissingEntry("L".to_string()))
+        );
+
+        let dangling = Constraint::from_graph(
+            HashMap::from([(
+                "P".to_string(),
+                ConstraintNode::Pair(
+                    ConstraintNode::Def("Q".to_string()).into(),
+                    ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+                ),
+            )]),
+            "P".to_string(),
+        );
+        assert_eq!(
+            dangling.render(),
+            Err(ConstraintError::UndefinedDef("Q".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_coalesce_float_ranges() {
+        use crate::parser::parse;
+        use crate::value::Value;
+
+        assert_eq!(
+            parse("0.0..1.0 | 1.0..=2.0")
+                .unwrap()
+                .coalesce_ints()
+                .to_string(),
+            "0.0..=2.0"
+        );
+        assert_eq!(
+            parse("0.5..=1.5 | 0.0..1.0 | 3.0")
+                .unwrap()
+                .coalesce_ints()
+                .to_string(),
+            parse("0.0..=1.5 | 3.0").unwrap().to_string()
+        );
+        // 1.0 被两侧排除，合并会错误地接受 1.0
+        let split = parse("0.0..1.0 | 1.0<..=2.0").unwrap();
+        assert_eq!(split.coalesce_ints().to_string(), split.to_string());
+        assert!(!split.contains(&Value::Float(1.0)));
+        assert!(split.contains(&Value::Float(1.5)));
+        // 浮点字面量填补了两个开端点之间的空隙
+        assert_eq!(
+            parse("0.0..1.0 | 1.0 | 1.0<..=2.0")
+                .unwrap()
+                .coalesce_ints()
+                .to_string(),
+            "0.0..=2.0"
+        );
+        assert!(
+            parse("0.0..=2.0")
+                .unwrap()
+                .super_of(&parse("0.5 | 1.0..2.0").unwrap())
+        );
+        assert!(parse("Float").unwrap().super_of(&parse("-1.5e3").unwrap()));
+        assert!(!parse("Int").unwrap().super_of(&parse("1.0").unwrap()));
+    }
+
+    #[test]
+    fn test_node_refs() {
+        use crate::parser::parse;
+
+        let list = parse("L := (Int, L) | Nil").unwrap();
+        let refs = list.node_refs();
+        assert_eq!(refs.len(), 1);
+
+        // 克隆共享同一张图，修改克隆会先复制，原来的引用仍然有效
+        let mut copy = list.clone();
+        for (name, node) in &refs {
+            assert!(std::ptr::eq(*node, copy.get_node(name).unwrap()));
+        }
+        assert!(
+            copy.try_add_node("Extra".to_string(), ConstraintNode::T)
+                .is_err()
+        );
+        assert!(copy.remove_node("L").is_some());
+        assert_eq!(copy.node_refs().len(), 0);
+        assert_eq!(refs["L"], list.get_node("L").unwrap());
+        assert!(std::ptr::eq(refs["L"], list.get_node("L").unwrap()));
+    }
+
+    #[test]
+    fn test_and_subsumption() {
+        use crate::parser::parse;
+        use crate::value::Value;
+
+        let p = |src: &str| parse(src).unwrap();
+        assert!(p("Int").super_of(&p("Int & 1..=5")));
+        assert!(p("1..=5").super_of(&p("Int & 1..=5")));
+        assert!(!p("Int & String").super_of(&p("3")));
+        assert!(p("Int & 1..=5").super_of(&p("3")));
+        assert!(!p("Int & 1..=5").super_of(&p("7")));
+        assert!(p("Int & 1..=5").super_of(&p("1..=5 & Int")));
+        // 交集为空时被任何约束包含
+        assert!(p("Nil").super_of(&p("Int & String")));
+        // 把交分配到枚举上：两个成员单独都不被包含，但交集只含 1..=2
+        let intersection = p("(Int | String) & (1..=2 | Nil)");
+        assert!(p("1..=2").super_of(&intersection));
+        assert!(!p("1").super_of(&intersection));
+
+        assert!(intersection.contains(&Value::Int(2)));
+        assert!(!intersection.contains(&Value::Nil));
+        assert_eq!(intersection.to_string(), "(Int | String) & (1..=2 | Nil)");
+        assert!(p(&intersection.to_string()).super_of(&intersection));
+        assert_eq!(p("Int & T & Int").simplify().to_string(), "Int");
+
+        // 交集的构造子取所有成员构造子的交，与成员顺序无关
+        use crate::constraint::Kind;
+        use crate::shape::HeadConstructor;
+        assert!(!p("T & Int").is_top());
+        assert!(!p("Int & T").is_top());
+        assert!(p("T & Int").is_uniformly(HeadConstructor::Kind(Kind::Int)));
+        assert!(!p("(T, T) & Nil").is_uniformly(HeadConstructor::Pair));
+        assert!(p("(T, T) & Nil").head_constructors().is_empty());
+        assert!(p("Int & 1..=5").is_uniformly(HeadConstructor::Range(Some(Kind::Int))));
+
+        // 类别限制与投影分配到交集的各成员上
+        let restricted = p("Int & 1..=5").restrict_to_kind(Kind::Int);
+        assert!(restricted.super_of(&p("3")));
+        assert!(!restricted.super_of(&p("7")));
+        assert!(
+            !p("Int & 1..=5")
+                .restrict_to_kind(Kind::String)
+                .super_of(&p("\"a\""))
+        );
+        let left = p("(1, 2) & (T, 2)").project_left();
+        assert!(left.super_of(&p("1")));
+        assert!(!left.super_of(&p("2")));
+
+        // 交集中的区间先求交再判定
+        assert!(p("1..=5").super_of(&p("0..=5 & 1..=10")));
+        assert!(p("3").super_of(&p("1..=3 & 3..=9")));
+        assert!(!p("4").super_of(&p("1..=3 & 3..=9")));
+        assert!(p("Nil").super_of(&p("1..=2 & 5..=9")));
+    }
+
+    #[test]
+    fn test_pretty_diff() {
+        use crate::parser::parse;
+
+        let old = parse("Doc := (L := (Int, L) | Nil, Meta := String)").unwrap();
+        let new = parse("Doc := (L := (String, L) | Nil, Tag := String)").unwrap();
+        assert_eq!(
+            old.pretty_diff(&new),
+            [
+                "- Doc := (L, Meta)",
+                "+ Doc := (L, Tag)",
+                "    Meta -> Tag",
+                "- L := (Int, L) | Nil",
+                "+ L := (String, L) | Nil",
+                "    Int -> String",
+                "- Meta := String",
+                "+ Tag := String",
+            ]
+            .join("\n")
+        );
+        assert!(
+            old.pretty_diff(&old)
+                .lines()
+                .all(|line| line.starts_with("  "))
+        );
+    }
+
+    #[test]
+    fn test_is_finite() {
+        use crate::parser::parse;
+
+        let p = |src: &str| parse(src).unwrap();
+        assert!(p("1 | 2 | \"a\"").is_finite());
+        assert!(p("(1..=10, 'a'..='z' | Nil)").is_finite());
+        assert!(p("B := (Bit := 0 | 1, Bit)").is_finite());
+        assert!(p("(F, T)").is_finite());
+        assert!(p("Int & 1..=3").is_finite());
+        assert!(!p("L_T := (T, L_T) | Nil").is_finite());
+        assert!(!p("L := (1, L) | Nil").is_finite());
+        assert!(!p("Int").is_finite());
+        assert!(!p("1..").is_finite());
+        assert!(!p("0.0..=1.0").is_finite());
+        assert!(!p("\"a\"..=\"b\"").is_finite());
+    }
+
+    #[test]
+    fn test_bottom_is_shared() {
+        use crate::parser::parse;
+        use std::sync::Arc;
+
+        let a = Constraint::bottom();
+        let b = Constraint::bottom();
+        assert!(Arc::ptr_eq(&a.graph, &b.graph));
+        assert_eq!(a.to_string(), "F");
+
+        // refine 失败时返回同一个共享的空约束
+        let refined = parse("Int").unwrap().refine(&parse("\"a\"").unwrap());
+        assert!(Arc::ptr_eq(&refined.graph, &a.graph));
+        assert!(parse("Nil").unwrap().super_of(&refined));
+    }
+
+    #[test]
+    fn test_entry_shape_predicates() {
+        use crate::parser::parse;
+
+        let p = |src: &str| parse(src).unwrap();
+        assert!(p("1 | 2").is_enum());
+        assert!(p("A := B := 1 | 2").is_enum());
+        assert!(!p("A := B := C := 1 | 2").is_enum());
+        assert!(p("A := B := C := 1 | 2").is_def());
+        assert!(p("(1, Nil)").is_pair());
+        assert!(!p("L := (Int, L) | Nil").is_pair());
+        assert!(p("\"a\"").is_leaf());
+        assert!(!p("Int").is_leaf());
+        assert!(p("T").is_t());
+        assert!(!p("T").is_f());
+        assert!(p("F").is_f());
+        assert!(Constraint::bottom().is_f());
+        assert!(p("(T, T)").entry_is(|node| node.children().len() == 2));
+        assert!(!Constraint::new("Missing".to_string()).is_t());
+    }
+
+    #[test]
+    fn test_super_of_string_mode() {
+        use crate::constraint::StringMatch;
+        use crate::parser::parse;
+
+        let p = |src: &str| parse(src).unwrap();
+        let foo = p("\"foo\"");
+        assert!(!foo.super_of(&p("\"foobar\"")));
+        assert!(!foo.super_of_string_mode(&p("\"foobar\""), StringMatch::Exact));
+        assert!(foo.super_of_string_mode(&p("\"foobar\""), StringMatch::Prefix));
+        assert!(!foo.super_of_string_mode(&p("\"fo\""), StringMatch::Prefix));
+
+        let glob = |pattern: &str, text: &str| {
+            p(&format!("{:?}", pattern))
+                .super_of_string_mode(&p(&format!("{:?}", text)), StringMatch::Glob)
+        };
+        assert!(glob("foo/*", "foo/bar"));
+        assert!(glob("foo/*", "foo/"));
+        assert!(!glob("foo/*", "bar/foo"));
+        assert!(glob("*.rs", "src/lib.rs"));
+        assert!(glob("a*b*c", "aXbYbZc"));
+        assert!(!glob("a*b*c", "aXbYbZ"));
+        assert!(glob("?at", "cat"));
+        assert!(!glob("?at", "at"));
+
+        // 结构内部同样生效，其他类别的字面量仍然精确比较
+        assert!(
+            p("(\"src/*\", 1)").super_of_string_mode(&p("(\"src/main\", 1)"), StringMatch::Glob)
+        );
+        assert!(
+            !p("(\"src/*\", 1)").super_of_string_mode(&p("(\"src/main\", 2)"), StringMatch::Glob)
+        );
+    }
+
+    #[test]
+    fn test_def_occurrences() {
+        use crate::constraint::PathSegment;
+        use crate::parser::parse;
+
+        let list = parse("L_T := (T, L_T) | Nil").unwrap();
+        assert_eq!(
+            list.def_occurrences(),
+            vec![(
+                "L_T".to_string(),
+                vec![
+                    PathSegment::InDef("L_T".to_string()),
+                    PathSegment::EnumIndex(0),
+                    PathSegment::PairRight,
+                ]
+            )]
+        );
+
+        // 每个定义只在第一次引用处展开
+        let doc = parse("Doc := (Id := Int, Id)").unwrap();
+        let occurrences: Vec<_> = doc
+            .def_occurrences()
+            .into_iter()
+            .map(|(name, path)| (name, path.last().cloned()))
+            .collect();
+        assert_eq!(
+            occurrences,
+            vec![
+                ("Id".to_string(), Some(PathSegment::PairLeft)),
+                ("Id".to_string(), Some(PathSegment::PairRight)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_any_pair_fast_path() {
+        use crate::parser::parse;
+
+        let any_pair = parse("(T, T)").unwrap();
+        let complex = parse(
+            "((L := (Int, L) | Nil, \"a\" | \"b\" | 1..=10), (M := (String, M) | Nil, (1, (2, Nil))))",
+        )
+        .unwrap();
+        let (result, diagnostics) = any_pair.super_of_diagnostic(&complex);
+        assert!(result);
+        // 不展开两个分量
+        assert!(diagnostics.steps <= 3);
+        assert_eq!(any_pair.explain_super_of(&complex).rule, "any-pair");
+
+        assert!(!any_pair.super_of(&parse("1").unwrap()));
+        assert!(!any_pair.super_of(&parse("Nil").unwrap()));
+    }
+
+    #[test]
+    fn test_templates() {
+        use crate::constraint::Ordering4;
+        use crate::parser::parse;
+        use crate::templates;
+
+        let int = || parse("Int").unwrap();
+        let list = templates::list(int());
+        let hand_built = parse("L_T := (Int, L_T) | Nil").unwrap();
+        assert_eq!(list.compare(&hand_built), Ordering4::Equal);
+
+        // 元素中的同名定义不会与模板的入口冲突
+        let nested = templates::list(parse("List := (String, List) | Nil").unwrap());
+        assert!(nested.validate().is_ok());
+        assert_eq!(
+            nested
+                .compare(&parse("O := ((String, I := (String, I) | Nil) | Nil, O) | Nil").unwrap()),
+            Ordering4::Equal
+        );
+
+        let option = templates::option(int());
+        assert_eq!(
+            option.compare(&parse("Int | Nil").unwrap()),
+            Ordering4::Equal
+        );
+
+        let result = templates::result(int(), parse("String").unwrap());
+        assert_eq!(
+            result.compare(&parse("#Ok(Int) | #Err(String)").unwrap()),
+            Ordering4::Equal
+        );
+
+        let map = templates::map(parse("String").unwrap(), int());
+        assert_eq!(
+            map.compare(&parse("M := ((String, Int), M) | Nil").unwrap()),
+            Ordering4::Equal
+        );
+        assert!(!map.super_of(&templates::list(int())));
+    }
+
+    #[test]
+    fn test_simplify_confluent() {
+        use std::collections::HashMap;
+
+        use crate::constraint::{Bound, Kind};
+
+        /// 确定的伪随机数，使失败可以复现
+        struct Rng(u64);
+        impl Rng {
+            fn below(&mut self, n: u64) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0 % n
+            }
+        }
+
+        /// 随机的节点；Pair 之外只引用编号更大的定义，递归因此总是经过构造子
+        fn node(
+            rng: &mut Rng,
+            index: usize,
+            defs: usize,
+            depth: usize,
+            guarded: bool,
+        ) -> ConstraintNode {
+            let choice = if depth == 0 {
+                rng.below(6)
+            } else {
+                rng.below(9)
+            };
+            match choice {
+                0 => ConstraintNode::T,
+                1 => ConstraintNode::F,
+                2 => ConstraintNode::Leaf(AtomicConstraint::Nil),
+                3 => ConstraintNode::Leaf(AtomicConstraint::LiteralInt(rng.below(6) as i32)),
+                4 => {
+                    let lo = rng.below(6) as i32;
+                    ConstraintNode::Range {
+                        lo: Bound::Inclusive(AtomicConstraint::LiteralInt(lo)),
+                        hi: Bound::Inclusive(AtomicConstraint::LiteralInt(
+                            lo + rng.below(4) as i32,
+                        )),
+                    }
+                }
+                5 => match (guarded, index + 1 < defs) {
+                    (true, _) => ConstraintNode::Def(format!("D{}", rng.below(defs as u64))),
+                    (false, true) => ConstraintNode::Def(format!(
+                        "D{}",
+                        index + 1 + rng.below((defs - index - 1) as u64) as usize
+                    )),
+                    (false, false) => ConstraintNode::Kind(Kind::Int),
+                },
+                6 | 7 => ConstraintNode::Enum(
+                    (0..rng.below(4))
+                        .map(|_| node(rng, index, defs, depth - 1, guarded))
+                        .collect(),
+                ),
+                _ => ConstraintNode::Pair(
+                    node(rng, index, defs, depth - 1, true).into(),
+                    node(rng, index, defs, depth - 1, true).into(),
+                ),
+            }
+        }
+
+        fn reverse_enums(node: &ConstraintNode) -> ConstraintNode {
+            match node {
+                ConstraintNode::Enum(members) => {
+                    ConstraintNode::Enum(members.iter().rev().map(reverse_enums).collect())
+                }
+                other => other.map_children(reverse_enums),
+            }
+        }
+
+        /// 化简步骤以不同顺序执行后再化简，输出都与直接化简逐字相同；canonical_key
+        /// 内部会再化简一次，不能用来比较化简的结果
+        fn assert_confluent(c: &Constraint) {
+            let passes: [fn(&Constraint) -> Constraint; 4] = [
+                Constraint::inline_nonrecursive,
+                Constraint::reduce,
+                Constraint::coalesce_ints,
+                Constraint::dedup_subsumed,
+            ];
+            let expected = c.simplify();
+            assert_eq!(
+                expected.simplify().display_sorted(),
+                expected.display_sorted(),
+                "{}",
+                c
+            );
+            // 枚举成员的顺序不影响结果
+            let reversed = c.map_defs(|_, node| reverse_enums(node));
+            assert_eq!(
+                reversed.simplify().display_sorted(),
+                expected.display_sorted(),
+                "{}",
+                c
+            );
+            for order in [[0, 1, 2, 3], [3, 2, 1, 0], [2, 0, 3, 1], [1, 3, 0, 2]] {
+                let reordered = order.iter().fold(c.clone(), |c, &pass| passes[pass](&c));
+                assert_eq!(
+                    reordered.simplify().display_sorted(),
+                    expected.display_sorted(),
+                    "{} with order {:?}",
+                    c,
+                    order
+                );
+            }
+        }
+
+        // 入口是递归定义的别名时，两个枚举成员互相吸收的顺序曾使结果保留别名
+        let aliased = crate::parser::parse("D0 := D1 := ((1, 4), D1 | D0 | 5..=7)").unwrap();
+        assert_eq!(
+            aliased.simplify().display_sorted(),
+            crate::parser::parse("D0 := ((1, 4), D0 | 5..=7)")
+                .unwrap()
+                .display_sorted()
+        );
+        assert_confluent(&aliased);
+
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let defs = 1 + rng.below(3) as usize;
+            let graph: HashMap<String, ConstraintNode> = (0..defs)
+                .map(|i| (format!("D{}", i), node(&mut rng, i, defs, 3, false)))
+                .collect();
+            assert_confluent(&Constraint::from_graph(graph, "D0".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_record_defaults() {
+        use std::collections::BTreeMap;
+
+        use crate::parser::parse;
+        use crate::value::Value;
+
+        let config = parse("{host: String, port: 1..=65535 = 8080}").unwrap();
+        assert_eq!(config.to_string(), "{host: String, port: 1..=65535 = 8080}");
+
+        // 缺少有默认值的字段时取默认值
+        let input = parse("{host: \"localhost\"}").unwrap();
+        assert!(config.super_of(&input));
+        assert!(!input.super_of(&config));
+        assert!(config.super_of(&parse("{host: String, port: 80}").unwrap()));
+        assert!(!config.super_of(&parse("{port: 80}").unwrap()));
+        assert!(!config.super_of(&parse("{host: String, debug: Int}").unwrap()));
+        // 默认值不在字段类型中时，不能缺少该字段
+        let broken = parse("{host: String, port: 1..=65535 = 0}").unwrap();
+        assert!(!broken.super_of(&input));
+
+        let record = |entries: &[(&str, Value)]| {
+            Value::Record(
+                entries
+                    .iter()
+                    .map(|(name, v)| (name.to_string(), v.clone()))
+                    .collect::<BTreeMap<_, _>>(),
+            )
+        };
+        let host = ("host", Value::String("example.com".to_string()));
+        assert!(config.contains(&record(std::slice::from_ref(&host))));
+        assert!(config.contains(&record(&[host.clone(), ("port", Value::Int(443))])));
+        assert!(!config.contains(&record(&[host.clone(), ("port", Value::Int(0))])));
+        assert!(!config.contains(&record(&[("port", Value::Int(443))])));
+        assert!(!config.contains(&record(&[host, ("debug", Value::Nil)])));
+        assert!(config.witness().is_some_and(|v| config.contains(&v)));
+    }
+
+    #[test]
+    fn test_int_set() {
+        use std::collections::BTreeSet;
+
+        use crate::parser::parse;
+        use crate::value::Value;
+
+        let codes: Vec<i32> = (0..256).map(|i| i * 2).collect();
+        let wide = single(ConstraintNode::Enum(
+            codes
+                .iter()
+                .map(|i| ConstraintNode::Leaf(AtomicConstraint::LiteralInt(*i)))
+                .collect(),
+        ));
+        let reduced = wide.reduce();
+        assert_eq!(
+            reduced.get_node("C"),
+            Some(&ConstraintNode::IntSet(
+                codes.iter().copied().collect::<BTreeSet<_>>()
+            ))
+        );
+        assert_eq!(reduced, wide);
+
+        // 与成员的比较是集合查找，不需要逐个尝试枚举成员
+        let member = parse("42").unwrap();
+        let (result, diagnostics) = reduced.super_of_diagnostic(&member);
+        assert!(result);
+        assert!(diagnostics.steps <= 2);
+        assert!(!reduced.super_of(&parse("43").unwrap()));
+        assert!(reduced.super_of(&parse("0 | 10 | 510").unwrap()));
+        assert!(!reduced.super_of(&parse("0..=2").unwrap()));
+        assert!(parse("Int").unwrap().super_of(&reduced));
+        assert!(parse("0..=511").unwrap().super_of(&reduced));
+        assert!(parse("0..=100 | 101..=511").unwrap().super_of(&reduced));
+        assert!(!parse("0..=509").unwrap().super_of(&reduced));
+
+        let set = Constraint::int_set([1, 2, 3, 7, 9, 10]);
+        assert_eq!(set.to_string(), "1..=3 | 7 | 9 | 10");
+        assert!(set.super_of(&Constraint::int_set([2, 9])));
+        assert!(!set.super_of(&Constraint::int_set([2, 8])));
+        assert!(set.contains(&Value::Int(7)));
+        assert!(!set.contains(&Value::Int(8)));
+        assert!(set.disjoint(&parse("4..=6 | 8").unwrap()));
+        assert!(!set.disjoint(&parse("8..=9").unwrap()));
+        assert!(!Constraint::int_set([]).super_of(&parse("1").unwrap()));
+        assert_eq!(Constraint::int_set(0..100).simplify().to_string(), "0..=99");
+    }
+
+    #[test]
+    fn test_parse_reader() {
+        use std::io::{self, Cursor, Read};
+
+        use crate::parser::{ParseErrorKind, parse};
+
+        let src = "L_T := (T, L_T) | Nil";
+        let streamed = Constraint::parse_reader(Cursor::new(src)).unwrap();
+        assert_eq!(streamed, parse(src).unwrap());
+        assert_eq!(streamed.to_string(), parse(src).unwrap().to_string());
+
+        // 定义、字符串字面量与错误位置都可以跨行
+        let multiline = "Doc := (\n  Id := Int,\n  (\"a\nb\", Id)\n) | Nil\n";
+        assert_eq!(
+            Constraint::parse_reader(Cursor::new(multiline)).unwrap(),
+            parse(multiline).unwrap()
+        );
+        let broken = "A := (1,\n  Int\n  | 2..=3 +)";
+        let error = Constraint::parse_reader(Cursor::new(broken)).unwrap_err();
+        assert_eq!(error, parse(broken).unwrap_err());
+        assert_eq!(error.kind, ParseErrorKind::Syntax);
+        assert_eq!((error.line, error.column), (3, 11));
+        let undefined = "(1,\n  Missing)";
+        assert_eq!(
+            Constraint::parse_reader(Cursor::new(undefined)).unwrap_err(),
+            parse(undefined).unwrap_err()
+        );
+
+        // 读取失败与非 UTF-8 输入报告为 IO 错误
+        struct Failing(Cursor<&'static str>);
+        impl Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.read(buf)? {
+                    0 => Err(io::Error::other("disk on fire")),
+                    n => Ok(n),
+                }
+            }
+        }
+        let error = Constraint::parse_reader(Failing(Cursor::new("(1,\n2"))).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::Io(io::ErrorKind::Other));
+        assert_eq!(error.line, 2);
+        let error = Constraint::parse_reader(Cursor::new(b"(1, \"\xff\")".as_slice())).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::Io(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_assert_super_of() {
+        use std::panic;
+
+        use crate::parser::parse;
+
+        let list = parse("L := (Int, L) | Nil").unwrap();
+        list.assert_super_of(&parse("(1, (2, Nil))").unwrap());
+
+        let bad = parse("(1, (\"two\", Nil))").unwrap();
+        let payload = panic::catch_unwind(panic::AssertUnwindSafe(|| list.assert_super_of(&bad)))
+            .unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.ends_with("mismatch: Int >= \"two\""));
+        assert!(message.contains("(Int, L) >= (\"two\", Nil) [pair]"));
+
+        let derivation = list.explain_super_of(&bad);
+        let path = derivation.failure_path();
+        assert_eq!(path.first().unwrap().rule, derivation.rule);
+        assert!(path.iter().all(|step| !step.result));
+        assert!(list.explain_super_of(&list).failure_path().is_empty());
+    }
+
+    #[test]
+    fn test_combine() {
+        use std::collections::HashMap;
+
+        use crate::constraint::ConflictPolicy;
+        use crate::error::ConstraintError;
+        use crate::parser::parse;
+
+        let ints = parse("T_list := (Int, T_list) | Nil").unwrap();
+        let strings = parse("Pair := (T_list := (String, T_list) | Nil, Shared := 1..=3)").unwrap();
+        let shared = parse("Doc := (T_list := (Int, T_list) | Nil, Shared := 1..=3)").unwrap();
+
+        // Rename：右侧的 T_list 改名，右侧的引用随之改写
+        let renamed = ints.combine(&strings, ConflictPolicy::Rename).unwrap();
+        assert_eq!(renamed.entry(), "T_list");
+        assert_eq!(renamed.get_node("T_list"), ints.get_node("T_list"));
+        assert_eq!(
+            renamed.get_node("Pair"),
+            Some(&ConstraintNode::Pair(
+                ConstraintNode::Def("T_list#1".to_string()).into(),
+                ConstraintNode::Def("Shared".to_string()).into(),
+            ))
+        );
+        let renames = HashMap::from([("T_list".to_string(), "T_list#1".to_string())]);
+        assert_eq!(
+            renamed.get_node("T_list#1"),
+            Some(&strings.get_node("T_list").unwrap().rename_defs(&renames))
+        );
+        assert_eq!(renamed.validate(), Ok(()));
+
+        // 定义体相同的同名定义只保留一份；引用了改名定义的同名定义也要改名
+        let merged = ints.combine(&shared, ConflictPolicy::Rename).unwrap();
+        assert_eq!(merged.get_node("T_list#1"), None);
+        let wrapped = parse("Doc := (T_list := (String, T_list) | Nil, Nil)").unwrap();
+        let left = parse("W := (Doc := (T_list := (Int, T_list) | Nil, Nil), Nil)").unwrap();
+        let cascaded = left.combine(&wrapped, ConflictPolicy::Rename).unwrap();
+        assert_eq!(
+            cascaded.get_node("Doc#1"),
+            Some(&ConstraintNode::Pair(
+                ConstraintNode::Def("T_list#1".to_string()).into(),
+                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+            ))
+        );
+        assert_eq!(cascaded.validate(), Ok(()));
+
+        // Error：定义体不同的同名定义报错，相同的不报错
+        assert_eq!(
+            ints.combine(&strings, ConflictPolicy::Error).unwrap_err(),
+            ConstraintError::ConflictingDef("T_list".to_string())
+        );
+        assert!(ints.combine(&shared, ConflictPolicy::Error).is_ok());
+
+        // KeepLeft：右侧的引用指向左侧的定义
+        let kept = ints.combine(&strings, ConflictPolicy::KeepLeft).unwrap();
+        assert_eq!(kept.get_node("T_list"), ints.get_node("T_list"));
+        assert_eq!(kept.get_node("T_list#1"), None);
+        assert_eq!(
+            kept.get_node("Pair"),
+            Some(&ConstraintNode::Pair(
+                ConstraintNode::Def("T_list".to_string()).into(),
+                ConstraintNode::Def("Shared".to_string()).into(),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_iter_values() {
+        use crate::parser::parse;
+        use crate::value::Value;
+
+        fn length(v: &Value) -> Option<usize> {
+            match v {
+                Value::Nil => Some(0),
+                Value::Pair(_, tail) => Some(length(tail)? + 1),
+                _ => None,
+            }
+        }
+        let list = parse("L := (Int, L) | Nil").unwrap();
+        let values: Vec<Value> = list.iter_values().take(5).collect();
+        assert_eq!(
+            values.iter().map(length).collect::<Vec<_>>(),
+            vec![Some(0), Some(1), Some(2), Some(3), Some(4)]
+        );
+        assert!(values.iter().all(|v| list.contains(v)));
+
+        // 有界的约束枚举完后结束，同一大小内不重复
+        let finite = parse("1 | (2, 3) | 1..=2 | #Some(Nil)").unwrap();
+        let values: Vec<Value> = finite.iter_values().collect();
+        assert_eq!(
+            values,
+            vec![
+                Value::Int(1),
+                Value::Int(2),
+                Value::Tagged("Some".to_string(), Value::Nil.into()),
+                Value::Pair(Value::Int(2).into(), Value::Int(3).into()),
+            ]
+        );
+        let record = parse("{a: 1, b: 2 = 2}").unwrap();
+        assert_eq!(record.iter_values().count(), 2);
+
+        // 只有有限推导的值，交集过滤掉所有更大的值后同样结束
+        assert_eq!(parse("A := (1, A)").unwrap().iter_values().next(), None);
+        let nil = parse("(L := (Int, L) | Nil) & (S := (String, S) | Nil)").unwrap();
+        assert_eq!(nil.iter_values().collect::<Vec<_>>(), vec![Value::Nil]);
+
+        // 在 A 的计算中途得到的 B 还缺少来自 A 的值，不能缓存
+        let mutual = parse("(A := (B := A | 2) | 1, B)").unwrap();
+        let mut values: Vec<Value> = mutual.iter_values().collect();
+        values.sort_by_key(|v| format!("{:?}", v));
+        let pair = |a, b| Value::Pair(Value::Int(a).into(), Value::Int(b).into());
+        assert_eq!(values, vec![pair(1, 1), pair(1, 2), pair(2, 1), pair(2, 2)]);
+    }
+
+    #[test]
+    fn test_subsumption_semantics_note() {
+        use crate::constraint::SemanticsNote;
+        use crate::parser::parse;
+
+        // 两侧都递归时只有余归纳的证明，默认的 super_of 取余归纳的结果
+        let ints = parse("L := (Int, L) | Nil").unwrap();
+        let ones = parse("O := (1, O) | Nil").unwrap();
+        assert!(ints.super_of(&ones));
+        assert!(!ints.super_of_finite(&ones));
+        assert_eq!(
+            ints.subsumption_semantics_note(&ones),
+            SemanticsNote::CoinductiveOnly
+        );
+
+        let short = parse("(1, (2, Nil))").unwrap();
+        assert_eq!(
+            ints.subsumption_semantics_note(&short),
+            SemanticsNote::Proved
+        );
+        assert_eq!(
+            ones.subsumption_semantics_note(&ints),
+            SemanticsNote::Refuted
+        );
+        // 没有有限值的流按最小不动点是空集，会被 Nil 包含；默认的判定取最大不动点，
+        // 流不被 Nil 包含，因此结果是 Refuted
+        let stream = parse("S := (1, S)").unwrap();
+        assert_eq!(
+            ints.subsumption_semantics_note(&stream),
+            SemanticsNote::CoinductiveOnly
+        );
+        assert_eq!(
+            parse("Nil").unwrap().subsumption_semantics_note(&stream),
+            SemanticsNote::Refuted
+        );
+    }
+
+    #[test]
+    fn test_subconstraint() {
+        use std::sync::Arc;
+
+        use crate::error::ConstraintError;
+        use crate::parser::parse;
+
+        let doc = parse("Doc := (Item := (Int, String), Items := (Item, Items) | Nil)").unwrap();
+        let item = doc.subconstraint("Item").unwrap();
+        assert_eq!(item.entry(), "Item");
+        assert!(Arc::ptr_eq(&item.graph, &doc.graph));
+        assert!(item.super_of(&parse("(1, \"a\")").unwrap()));
+        assert!(!item.super_of(&doc));
+        assert!(parse("(Int, T)").unwrap().super_of(&item));
+
+        let items = doc.subconstraint("Items").unwrap();
+        assert!(items.super_of(&parse("((1, \"a\"), Nil)").unwrap()));
+        assert_eq!(items.prune().get_node("Doc"), None);
+        assert_eq!(
+            doc.subconstraint("Missing").unwrap_err(),
+            ConstraintError::MissingEntry("Missing".to_string())
+        );
+    }
+
+    #[test]
+    fn test_recursive_against_top_and_bottom() {
+        use crate::parser::parse;
+
+        let list = parse("L_T := (T, L_T) | Nil").unwrap();
+        let top = Constraint::top();
+        let bottom = Constraint::bottom();
+        assert_eq!(top.entry(), "T");
+        assert!(top.is_top());
+
+        // 列表不是全集，但被全集包含，且包含空约束
+        assert!(!list.super_of(&top));
+        assert!(top.super_of(&list));
+        assert!(list.super_of(&bottom));
+        assert!(!bottom.super_of(&list));
+        assert!(!list.super_of_finite(&top));
+        assert!(list.super_of_finite(&bottom));
+
+        // 嵌在递归定义中的 T 与 F 同样由最先的规则处理
+        let nested = parse("(1, L_T := (T, L_T) | Nil)").unwrap();
+        assert!(nested.super_of(&parse("(1, (F, Nil))").unwrap()));
+        assert!(!nested.super_of(&parse("(1, T)").unwrap()));
+        assert!(top.super_of(&top));
+        assert!(!bottom.super_of(&top));
+    }
+
+    #[test]
+    fn test_display_with_registry() {
+        use crate::constraint::Bound;
+        use crate::format::{FormatKind, FormatterRegistry};
+        use crate::parser::parse;
+
+        fn int(bound: &Bound) -> Option<i32> {
+            match bound {
+                Bound::Inclusive(AtomicConstraint::LiteralInt(i))
+                | Bound::Exclusive(AtomicConstraint::LiteralInt(i)) => Some(*i),
+                _ => None,
+            }
+        }
+        let mut registry = FormatterRegistry::new();
+        registry.register(FormatKind::Int, |node| match node {
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)) => format!("{:#x}", i),
+            _ => unreachable!(),
+        });
+        let hex_only = registry.clone();
+        // Rust 风格的半开区间 `a..b`
+        registry.register(FormatKind::Range, |node| match node {
+            ConstraintNode::Range { lo, hi } => {
+                let end = match hi {
+                    Bound::Inclusive(_) => int(hi).map(|i| i + 1),
+                    _ => int(hi),
+                };
+                format!(
+                    "{}..{}",
+                    int(lo).map_or(String::new(), |i| i.to_string()),
+                    end.map_or(String::new(), |i| i.to_string())
+                )
+            }
+            _ => unreachable!(),
+        });
+
+        let mixed = parse("(255, (0..=9, (\"a\", ..100)))").unwrap();
+        assert_eq!(
+            mixed.display_with_registry(&registry),
+            "(0xff, (0..10, (\"a\", ..100)))"
+        );
+        // 没有注册 Range 时区间端点使用字面量的格式化函数
+        assert_eq!(
+            mixed.display_with_registry(&hex_only),
+            "(0xff, (0x0..=0x9, (\"a\", ..0x64)))"
+        );
+        assert_eq!(
+            mixed.display_with_registry(&FormatterRegistry::new()),
+            mixed.to_string()
+        );
+    }
+
+    #[test]
+    fn test_inline_occurs_check() {
+        use std::collections::HashSet;
+
+        use crate::error::ConstraintError;
+        use crate::parser::parse;
+
+        // 故意把递归定义当作可以内联的定义，occurs-check 报错而不是无限内联
+        let list = parse("L := (Int, L) | Nil").unwrap();
+        assert_eq!(
+            list.inline_defs(&HashSet::from(["L".to_string()]))
+                .unwrap_err(),
+            ConstraintError::RecursionDetected("L".to_string())
+        );
+        let mutual = parse("A := (1, B := (2, A) | Nil)").unwrap();
+        let error = mutual
+            .inline_defs(&HashSet::from(["A".to_string(), "B".to_string()]))
+            .unwrap_err();
+        assert!(
+            matches!(error, ConstraintError::RecursionDetected(name) if name == "A" || name == "B")
+        );
+        // 只内联其中一个定义时内联会终止
+        let inlined = mutual
+            .inline_defs(&HashSet::from(["B".to_string()]))
+            .unwrap();
+        assert_eq!(inlined.get_node("A").unwrap().children().len(), 2);
+        assert!(mutual.super_of(&inlined));
+
+        let nested = parse("(Item := (Int, Int), L := (Item, L) | Nil)").unwrap();
+        assert_eq!(
+            nested.try_inline_nonrecursive().unwrap().graph,
+            nested.inline_nonrecursive().graph
+        );
+        assert!(
+            nested
+                .try_depth_truncate(2, crate::constraint::Approximation::Over)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_super_of_structural() {
+        use std::collections::HashMap;
+
+        use crate::constraint::Kind;
+        use crate::parser::parse;
+
+        let signature = Constraint::from_graph(
+            HashMap::from([(
+                "Sig".to_string(),
+                ConstraintNode::Pair(
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(1)).into(),
+                    ConstraintNode::Kind(Kind::String).into(),
+                ),
+            )]),
+            "Sig".to_string(),
+        );
+        let call = parse("(99, \"x\")").unwrap();
+        assert!(!signature.super_of(&call));
+        assert!(signature.super_of_structural(&call));
+        assert!(!signature.super_of_structural(&parse("(1, 2)").unwrap()));
+        assert!(!signature.super_of_structural(&parse("(1, (\"x\", Nil))").unwrap()));
+
+        // 与先擦除字面量再比较的结果一致
+        let cases = [
+            ("L := (1, L) | Nil", "(5, (6, Nil))"),
+            ("L := (1, L) | Nil", "(5, (\"a\", Nil))"),
+            ("0..=3 | \"a\"", "7 | \"zz\""),
+            ("#Ok(1) | #Err(\"e\")", "#Ok(2)"),
+            ("{a: 1, b: 'c' = 'd'}", "{a: 7}"),
+            ("Nil", "1"),
+            ("1.5", "2"),
+        ];
+        for (a, b) in cases {
+            let (a, b) = (parse(a).unwrap(), parse(b).unwrap());
+            assert_eq!(
+                a.super_of_structural(&b),
+                a.erase_literals().super_of(&b.erase_literals())
+            );
+        }
+        assert!(
+            parse("L := (1, L) | Nil")
+                .unwrap()
+                .super_of_structural(&parse("(5, (6, Nil))").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_super_of_bounded_mem() {
+        use crate::constraint::{Checker, Memo};
+        use std::collections::HashMap;
+
+        /// 确定的伪随机数，使失败可以复现
+        struct Rng(u64);
+        impl Rng {
+            fn below(&mut self, n: u64) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0 % n
+            }
+        }
+
+        /// 随机的节点，定义之间可以任意互相引用
+        fn node(rng: &mut Rng, defs: u64, depth: usize) -> ConstraintNode {
+            let choice = if depth == 0 {
+                rng.below(5)
+            } else {
+                rng.below(8)
+            };
+            match choice {
+                0 => ConstraintNode::T,
+                1 => ConstraintNode::Leaf(AtomicConstraint::Nil),
+                2 => ConstraintNode::Leaf(AtomicConstraint::LiteralInt(rng.below(3) as i32)),
+                3 | 4 => ConstraintNode::Def(format!("D{}", rng.below(defs))),
+                5 => ConstraintNode::Enum(
+                    (0..1 + rng.below(3))
+                        .map(|_| node(rng, defs, depth - 1))
+                        .collect(),
+                ),
+                _ => ConstraintNode::Pair(
+                    node(rng, defs, depth - 1).into(),
+                    node(rng, defs, depth - 1).into(),
+                ),
+            }
+        }
+
+        fn graph(rng: &mut Rng) -> Constraint {
+            let defs = 1 + rng.below(4);
+            let graph: HashMap<String, ConstraintNode> = (0..defs)
+                .map(|i| (format!("D{}", i), node(rng, defs, 3)))
+                .collect();
+            Constraint::from_graph(graph, "D0".to_string())
+        }
+
+        /// 任意容量下都与不带缓存的判定结果相同
+        fn assert_same(a: &Constraint, b: &Constraint) {
+            let expected = a.super_of(b);
+            for cap in [0, 1, 4, 1024] {
+                assert_eq!(
+                    a.super_of_bounded_mem(b, cap),
+                    expected,
+                    "{} >= {} with cap {}",
+                    a,
+                    b,
+                    cap
+                );
+            }
+        }
+
+        let lists = crate::parser::parse("L := (Int, L) | Nil").unwrap();
+        let ones = crate::parser::parse("O := (1, (1, O)) | Nil").unwrap();
+        let strings = crate::parser::parse("S := (String, S) | Nil").unwrap();
+        assert_same(&lists, &ones);
+        assert_same(&ones, &lists);
+        assert_same(&lists, &strings);
+        assert!(lists.super_of_bounded_mem(&ones, 1));
+        assert!(!ones.super_of_bounded_mem(&lists, 1));
+
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let a = graph(&mut rng);
+            let b = graph(&mut rng);
+            assert_same(&a, &b);
+            assert_same(&a, &a);
+        }
+
+        // 内层的定义对在外层假设之下反复出现：外层成立之前，待定的结果同样可以复用，
+        // 外层成立之后写入缓存
+        let nested = crate::parser::parse("(L := (M := (Int, M) | Nil, L) | Nil, Nil)").unwrap();
+        let repeated =
+            crate::parser::parse("(K := (N := (1, N) | Nil, (N, (N, (N, K)))) | Nil, Nil)")
+                .unwrap();
+        let mut plain = Checker::new(&nested, &repeated);
+        assert!(plain.run());
+        let mut memoized = Checker::new(&nested, &repeated);
+        memoized.memo = Some(Memo::new(16));
+        assert!(memoized.run());
+        assert!(memoized.resolutions < plain.resolutions);
+        assert_same(&nested, &repeated);
+    }
+
+    #[test]
+    fn test_deterministic_def_order() {
+        // 以不同的顺序加入同样的定义
+        let names = ["Zeta", "alpha", "Beta", "D10", "D2", "Entry"];
+        let build = |order: &[usize]| {
+            let mut constraint = Constraint::new("Entry".to_string());
+            for &i in order {
+                constraint.add_node(
+                    names[i].to_string(),
+                    ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i as i32)),
+                );
+            }
+            constraint
+        };
+        let forward = build(&[0, 1, 2, 3, 4, 5]);
+        let backward = build(&[5, 4, 3, 2, 1, 0]);
+
+        let definitions = forward.definitions();
+        let mut sorted = definitions.clone();
+        sorted.sort();
+        assert_eq!(definitions, sorted);
+        for _ in 0..10 {
+            assert_eq!(forward.definitions(), definitions);
+            assert_eq!(backward.definitions(), definitions);
+        }
+        assert_eq!(
+            forward
+                .iter_defs()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>(),
+            vec!["Beta", "D10", "D2", "Entry", "Zeta", "alpha"]
+        );
+        assert_eq!(
+            forward.nodes().collect::<Vec<_>>(),
+            backward.nodes().collect::<Vec<_>>()
+        );
+        assert_eq!(format!("{:?}", forward), format!("{:?}", backward));
+    }
+
+    #[test]
+    fn test_subsumption_matrix() {
+        let small = crate::parser::parse("2..=3").unwrap();
+        let medium = crate::parser::parse("1..=5").unwrap();
+        let large = crate::parser::parse("0..=10").unwrap();
+        let items = [large.clone(), small, medium, large];
+        assert_eq!(
+            Constraint::subsumption_matrix(&items),
+            vec![
+                vec![true, true, true, true],
+                vec![false, true, false, false],
+                vec![false, true, true, false],
+                vec![true, true, true, true],
+            ]
+        );
+        // large 不直接覆盖 small，两个 large 互相包含，之间没有边
+        assert_eq!(
+            Constraint::subsumption_covers(&items),
+            vec![
+                vec![false, false, true, false],
+                vec![false, false, false, false],
+                vec![false, true, false, false],
+                vec![false, false, true, false],
+            ]
+        );
+        assert_eq!(Constraint::subsumption_matrix(&[]), Vec::<Vec<bool>>::new());
+    }
+
+    #[test]
+    fn test_display_truncated() {
+        let wide = Constraint::from_nodes(
+            "Wide".to_string(),
+            [(
+                "Wide".to_string(),
+                ConstraintNode::Enum(
+                    (0..1000)
+                        .map(|i| {
+                            ConstraintNode::Pair(
+                                ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)).into(),
+                                ConstraintNode::Leaf(AtomicConstraint::Nil).into(),
+                            )
+                        })
+                        .collect(),
+                ),
+            )],
+        )
+        .unwrap();
+        let output = wide.display_truncated(5);
+        assert!(output.lines().count() <= 8);
+        assert!(output.contains("(4, Nil)"));
+        assert!(!output.contains("(5, Nil)"));
+        assert!(output.contains("... (995 more)"));
+        assert!(wide.to_string().lines().count() > 1000);
+
+        // 成员数不超过上限时与 Display 相同
+        let small = crate::parser::parse("1 | 2 | 3").unwrap();
+        assert_eq!(small.display_truncated(3), small.to_string());
+        assert_eq!(small.display_truncated(1), "1 | ... (2 more)");
+    }
+
+    #[test]
+    fn test_record_pairs() {
+        let record = crate::parser::parse("{name: String, age: 0..=150}").unwrap();
+        let pairs = record.record_to_pairs();
+        let encoded =
+            crate::parser::parse("((\"name\", String), ((\"age\", 0..=150), Nil))").unwrap();
+        assert_eq!(
+            pairs.get_node(pairs.entry()),
+            encoded.get_node(encoded.entry())
+        );
+        let back = pairs.pairs_to_record();
+        assert_eq!(back.get_node(back.entry()), record.get_node(record.entry()));
+        assert!(back == record);
+
+        // 键重复或不是字符串字面量的列表保持不变
+        for source in ["((\"a\", 1), ((\"a\", 2), Nil))", "((1, 2), Nil)", "Nil"] {
+            let list = crate::parser::parse(source).unwrap();
+            let converted = list.pairs_to_record();
+            assert_eq!(
+                converted.get_node(converted.entry()),
+                list.get_node(list.entry())
+            );
+        }
+    }
 }
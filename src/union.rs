@@ -0,0 +1,284 @@
+//! 多个约束的并集
+
+use std::collections::HashMap;
+
+use crate::{
+    constraint::{Bound, ConflictPolicy, Constraint, ConstraintNode, Kind, int_set_parts},
+    error::ConstraintError,
+    range,
+    simplify::{coalesce_node, reduce_node},
+};
+
+/// 在 graph 中尚未使用的名字，优先使用 base 本身
+pub(crate) fn fresh_name(graph: &HashMap<String, ConstraintNode>, base: &str) -> String {
+    if !graph.contains_key(base) {
+        return base.to_string();
+    }
+    (1..)
+        .map(|i| format!("{}#{}", base, i))
+        .find(|name| !graph.contains_key(name))
+        .unwrap()
+}
+
+/// 把 constraint 的所有定义并入 graph，名字冲突时改名，返回改名后的入口节点
+pub(crate) fn merge_into(
+    graph: &mut HashMap<String, ConstraintNode>,
+    constraint: &Constraint,
+) -> Option<ConstraintNode> {
+    let mut renames = HashMap::new();
+    let mut names: Vec<&String> = constraint.graph.keys().collect();
+    names.sort();
+    for name in names {
+        let fresh = fresh_name(graph, name);
+        // 先占位，避免同一个约束中的两个定义得到同一个新名字
+        graph.insert(fresh.clone(), ConstraintNode::F);
+        renames.insert(name.clone(), fresh);
+    }
+    for (name, node) in constraint.graph.iter() {
+        graph.insert(renames[name].clone(), node.rename_defs(&renames));
+    }
+    renames
+        .get(constraint.entry())
+        .map(|entry| graph[entry].clone())
+}
+
+/// 成员数超过 max_members 时，把成员最多的类别的字面量与区间替换为类别本身，
+/// 重复直到不再超过或没有可以放宽的字面量
+pub(crate) fn widen_members(
+    mut members: Vec<ConstraintNode>,
+    max_members: usize,
+) -> Vec<ConstraintNode> {
+    while members.len() > max_members {
+        let mut counts: HashMap<Kind, usize> = HashMap::new();
+        for kind in members.iter().filter_map(literal_kind) {
+            *counts.entry(kind).or_default() += 1;
+        }
+        let Some(kind) = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .max_by_key(|(kind, count)| (*count, kind.to_string()))
+            .map(|(kind, _)| kind)
+        else {
+            break;
+        };
+        members.retain(|member| literal_kind(member) != Some(kind));
+        if !members.contains(&ConstraintNode::Kind(kind)) {
+            members.push(ConstraintNode::Kind(kind));
+        }
+    }
+    members
+}
+
+/// 节点列表的最小上界：同一类别的字面量与区间合并为覆盖它们的区间，出现多个类别时
+/// 各自放宽为类别，所有 Pair 按分量合并为一个 Pair，其余成员（Nil、Def 等）原样保留
+fn join_nodes(nodes: Vec<&ConstraintNode>) -> ConstraintNode {
+    fn flatten<'a>(node: &'a ConstraintNode, members: &mut Vec<&'a ConstraintNode>) {
+        match node.strip_annotations() {
+            ConstraintNode::Enum(nodes) => nodes.iter().for_each(|node| flatten(node, members)),
+            ConstraintNode::F => {}
+            other => members.push(other),
+        }
+    }
+    let mut members = Vec::new();
+    nodes
+        .into_iter()
+        .for_each(|node| flatten(node, &mut members));
+
+    let mut spans: Vec<(Kind, Option<(Bound, Bound)>)> = Vec::new(); // None 表示整个类别
+    let mut lefts = Vec::new();
+    let mut rights = Vec::new();
+    let mut others = Vec::new();
+    for member in members {
+        let (kind, interval) = match member {
+            ConstraintNode::T => return ConstraintNode::T,
+            ConstraintNode::Pair(left, right) => {
+                lefts.push(left.as_ref());
+                rights.push(right.as_ref());
+                continue;
+            }
+            ConstraintNode::Kind(kind) => (*kind, None),
+            ConstraintNode::Leaf(atom) if atom.kind().is_some() => (
+                atom.kind().unwrap(),
+                Some((
+                    Bound::Inclusive(atom.clone()),
+                    Bound::Inclusive(atom.clone()),
+                )),
+            ),
+            ConstraintNode::Range { lo, hi } if range::kind(lo, hi).is_some() => {
+                (range::kind(lo, hi).unwrap(), Some((lo.clone(), hi.clone())))
+            }
+            other => {
+                if !others.contains(&other) {
+                    others.push(other);
+                }
+                continue;
+            }
+        };
+        match spans.iter_mut().find(|(existing, _)| *existing == kind) {
+            Some((_, current)) => {
+                *current = match (current.take(), interval) {
+                    (Some((a_lo, a_hi)), Some((b_lo, b_hi))) => {
+                        range::span(&a_lo, &a_hi, &b_lo, &b_hi)
+                    }
+                    _ => None,
+                }
+            }
+            None => spans.push((kind, interval)),
+        }
+    }
+
+    let mut result: Vec<ConstraintNode> = others.into_iter().cloned().collect();
+    let widen = spans.len() > 1;
+    for (kind, interval) in spans {
+        result.push(match interval {
+            Some((lo, hi)) if !widen => ConstraintNode::Range { lo, hi },
+            _ => ConstraintNode::Kind(kind),
+        });
+    }
+    if !lefts.is_empty() {
+        result.push(ConstraintNode::Pair(
+            join_nodes(lefts).into(),
+            join_nodes(rights).into(),
+        ));
+    }
+    reduce_node(&ConstraintNode::Enum(result))
+}
+
+fn literal_kind(node: &ConstraintNode) -> Option<Kind> {
+    match node.strip_annotations() {
+        ConstraintNode::Leaf(atom) => atom.kind(),
+        ConstraintNode::Range { lo, hi } => range::kind(lo, hi),
+        _ => None,
+    }
+}
+
+impl Constraint {
+    /// 所有 items 的并集，各约束的定义在名字冲突时改名后合并到同一张图
+    pub fn union_all<'a, I>(items: I) -> Constraint
+    where
+        I: IntoIterator<Item = &'a Constraint>,
+    {
+        Constraint::union_all_bounded(items, usize::MAX)
+    }
+
+    /// 与 `union_all` 相同，但入口枚举的成员超过 max_members 时，把字面量放宽为其类别
+    /// 以限制结果的大小，代价是损失精度
+    pub fn union_all_bounded<'a, I>(items: I, max_members: usize) -> Constraint
+    where
+        I: IntoIterator<Item = &'a Constraint>,
+    {
+        let mut graph = HashMap::new();
+        let mut members = Vec::new();
+        let entry = "Union".to_string();
+        // 入口名先占住，合并进来的同名定义会被改名
+        graph.insert(entry.clone(), ConstraintNode::F);
+        for item in items {
+            if let Some(node) = merge_into(&mut graph, item) {
+                members.push(node);
+            }
+        }
+        let members = match coalesce_node(&reduce_node(&ConstraintNode::Enum(members))) {
+            ConstraintNode::Enum(members) => widen_members(members, max_members),
+            ConstraintNode::IntSet(set) => widen_members(int_set_parts(&set), max_members),
+            other => vec![other],
+        };
+        graph.insert(entry.clone(), ConstraintNode::Enum(members));
+        Constraint::from_graph(graph, entry).reduce().prune()
+    }
+
+    /// 把 other 的定义并入 self 的图，入口仍是 self 的入口
+    ///
+    /// 同名且定义体相同的定义只保留一份，定义体不同时按 on_conflict 处理。`Rename` 时
+    /// 引用了改名定义的同名定义本身也会不同，同样改名；与 `union_all` 一样，谓词表不会被保留
+    pub fn combine(
+        &self,
+        other: &Self,
+        on_conflict: ConflictPolicy,
+    ) -> Result<Constraint, ConstraintError> {
+        let mut names: Vec<&String> = other.graph.keys().collect();
+        names.sort();
+        let differs = |name: &String, renames: &HashMap<String, String>| {
+            self.graph
+                .get(name)
+                .is_some_and(|body| *body != other.graph[name].rename_defs(renames))
+        };
+        let mut graph = (*self.graph).clone();
+        for name in &names {
+            graph.entry(name.to_string()).or_insert(ConstraintNode::F);
+        }
+        let mut renames = HashMap::new();
+        match on_conflict {
+            ConflictPolicy::Rename => loop {
+                let conflicting: Vec<&String> = names
+                    .iter()
+                    .copied()
+                    .filter(|name| !renames.contains_key(*name) && differs(name, &renames))
+                    .collect();
+                if conflicting.is_empty() {
+                    break;
+                }
+                for name in conflicting {
+                    // 先占位，避免两个改名的定义得到同一个新名字
+                    let fresh = fresh_name(&graph, name);
+                    graph.insert(fresh.clone(), ConstraintNode::F);
+                    renames.insert(name.clone(), fresh);
+                }
+            },
+            ConflictPolicy::Error => {
+                if let Some(name) = names.iter().find(|name| differs(name, &renames)) {
+                    return Err(ConstraintError::ConflictingDef(name.to_string()));
+                }
+            }
+            ConflictPolicy::KeepLeft => {}
+        }
+        for name in names {
+            if renames.contains_key(name) || !self.graph.contains_key(name) {
+                let fresh = renames.get(name).unwrap_or(name).clone();
+                graph.insert(fresh, other.graph[name].rename_defs(&renames));
+            }
+        }
+        Ok(Constraint::from_graph(graph, self.entry.clone()))
+    }
+
+    /// 入口为 Enum 时，把每个成员拆成独立的约束，否则返回 None
+    ///
+    /// 成员本身是 Def 时直接以该定义为入口并共享原图，其余成员以合成的入口名
+    /// 加入原图的副本
+    pub fn as_union(&self) -> Option<Vec<Constraint>> {
+        let ConstraintNode::Enum(members) = self.get_node(self.entry())?.strip_annotations() else {
+            return None;
+        };
+        let parts = members
+            .iter()
+            .enumerate()
+            .map(|(i, member)| match member.strip_annotations() {
+                ConstraintNode::Def(name) if self.graph.contains_key(name) => {
+                    Constraint::from_graph_shared(self.graph.clone(), name.clone())
+                }
+                _ => {
+                    let mut graph = (*self.graph).clone();
+                    let entry = fresh_name(&graph, &format!("{}.{}", self.entry(), i));
+                    graph.insert(entry.clone(), member.clone());
+                    Constraint::from_graph(graph, entry)
+                }
+            })
+            .collect();
+        Some(parts)
+    }
+
+    /// 所有 items 的最小上界（join），用于从多个候选约束推断一个共同的超约束
+    ///
+    /// 与保留全部成员的 `union_all` 不同，同一类别的字面量被合并为覆盖它们的区间，
+    /// 多种类别同时出现时放宽为类别的枚举，Pair 按分量分别合并。结果包含每个 item
+    pub fn join_all(items: &[Constraint]) -> Constraint {
+        let mut graph = HashMap::new();
+        let entry = "Join".to_string();
+        graph.insert(entry.clone(), ConstraintNode::F);
+        let entries: Vec<ConstraintNode> = items
+            .iter()
+            .filter_map(|item| merge_into(&mut graph, item))
+            .collect();
+        graph.insert(entry.clone(), join_nodes(entries.iter().collect()));
+        Constraint::from_graph(graph, entry).prune()
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    assumption::DefId,
+    constraint::{AtomicConstraint, Checker, Constraint, ConstraintNode, Kind},
+    range,
+};
+
+/// 约束所描述的具体值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Int(i32),
+    Float(f64),
+    String(String),
+    Char(char),
+    Pair(Box<Value>, Box<Value>),
+    Tagged(String, Box<Value>), // 带标签的值，对应 TaggedEnum 的一个成员
+    Record(BTreeMap<String, Value>), // 按字段名组织的记录，对应 Record
+    Ref(usize), // 引用值存储中的第 i 个值，用于表示循环的值，见 `Constraint::contains_in`
+}
+
+impl Value {
+    /// 值对应的原子，复合值没有对应的原子
+    pub fn as_atom(&self) -> Option<AtomicConstraint> {
+        match self {
+            Value::Nil => Some(AtomicConstraint::Nil),
+            Value::Int(i) => Some(AtomicConstraint::LiteralInt(*i)),
+            Value::String(s) => Some(AtomicConstraint::LiteralString(s.clone())),
+            Value::Char(c) => Some(AtomicConstraint::LiteralChar(*c)),
+            Value::Float(x) => Some(AtomicConstraint::LiteralFloat(*x)),
+            Value::Pair(..) | Value::Tagged(..) | Value::Record(_) | Value::Ref(_) => None,
+        }
+    }
+
+    pub fn kind(&self) -> Option<Kind> {
+        self.as_atom().and_then(|atom| atom.kind())
+    }
+
+    /// 把 JSON 值转换为内部值：数组转换为以 Nil 结尾的右嵌套 Pair 列表，对象转换为记录，
+    /// null 转换为 Nil，暂时无法表示的 JSON 值返回 None
+    #[cfg(feature = "serde")]
+    pub fn from_json(v: &serde_json::Value) -> Option<Value> {
+        match v {
+            serde_json::Value::Null => Some(Value::Nil),
+            serde_json::Value::Number(number) => match number.as_i64() {
+                Some(i) => i32::try_from(i).ok().map(Value::Int),
+                None => number.as_f64().map(Value::Float),
+            },
+            serde_json::Value::Array(items) => {
+                items.iter().rev().try_fold(Value::Nil, |tail, item| {
+                    Some(Value::Pair(Value::from_json(item)?.into(), tail.into()))
+                })
+            }
+            serde_json::Value::String(s) => Some(Value::String(s.clone())),
+            serde_json::Value::Object(entries) => entries
+                .iter()
+                .map(|(name, item)| Some((name.clone(), Value::from_json(item)?)))
+                .collect::<Option<_>>()
+                .map(Value::Record),
+            serde_json::Value::Bool(_) => None,
+        }
+    }
+}
+
+impl Constraint {
+    /// 判断值 v 是否满足约束
+    ///
+    /// 与 `super_of` 一致，递归定义按余归纳解释：同一个定义在没有消耗任何值的情况下
+    /// 再次遇到同一个值时视为成立
+    pub fn contains(&self, v: &Value) -> bool {
+        self.contains_in(v, &[])
+    }
+
+    /// 判断可能含有循环的值 v 是否满足约束，v 及 values 中的 `Value::Ref(i)` 指向 values[i]
+    ///
+    /// 循环的值（例如无穷的流）按余归纳判定：同一个定义再次遇到同一个值节点时视为成立，
+    /// 因此循环的列表满足递归的列表约束。悬空的引用不满足任何约束
+    pub fn contains_in(&self, v: &Value, values: &[Value]) -> bool {
+        match self.get_node(self.entry()) {
+            Some(node) => self.node_contains(node, v, values, &mut HashSet::new()),
+            None => false,
+        }
+    }
+
+    /// 对每个值分别判断是否满足约束；入口只查找一次，并取自定义存储，其中的 Def 在构建
+    /// 定义存储时已经解析，整批值共用这些解析结果而不再按名字查找
+    pub fn contains_all(&self, values: &[Value]) -> Vec<bool> {
+        let arena = self.arena();
+        let Some(entry) = arena.index_of(self.entry()).map(|index| arena.node(index)) else {
+            return vec![false; values.len()];
+        };
+        let mut visiting = HashSet::new();
+        values
+            .iter()
+            .map(|v| self.node_contains(entry, v, &[], &mut visiting))
+            .collect()
+    }
+
+    /// 所有值都满足约束，遇到第一个不满足的值即停止
+    pub fn all_contained(&self, values: &[Value]) -> bool {
+        let Some(entry) = self.get_node(self.entry()) else {
+            return values.is_empty();
+        };
+        let mut visiting = HashSet::new();
+        values
+            .iter()
+            .all(|v| self.node_contains(entry, v, &[], &mut visiting))
+    }
+
+    /// 入口枚举中满足 v 的成员下标，成员彼此重叠时可能有多个；入口不是枚举时视为只有
+    /// 一个成员
+    pub fn matching_members(&self, v: &Value) -> Vec<usize> {
+        let Some(entry) = self.get_node(self.entry()) else {
+            return Vec::new();
+        };
+        let members = match entry.strip_annotations() {
+            ConstraintNode::Enum(members) => members.iter().collect(),
+            other => vec![other],
+        };
+        let mut visiting = HashSet::new();
+        members
+            .into_iter()
+            .enumerate()
+            .filter(|(_, member)| self.node_contains(member, v, &[], &mut visiting))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// 判断 JSON 值是否满足约束，无法转换为内部值的 JSON 值不满足任何约束
+    #[cfg(feature = "serde")]
+    pub fn contains_json(&self, v: &serde_json::Value) -> bool {
+        Value::from_json(v).is_some_and(|v| self.contains(&v))
+    }
+
+    pub(crate) fn node_contains(
+        &self,
+        node: &ConstraintNode,
+        v: &Value,
+        values: &[Value],
+        visiting: &mut HashSet<(DefId, *const Value)>,
+    ) -> bool {
+        // 解开引用链，链的长度超过存储大小说明引用形成了不含任何值的环
+        let mut v = v;
+        for _ in 0..=values.len() {
+            match v {
+                Value::Ref(i) => match values.get(*i) {
+                    Some(target) => v = target,
+                    None => return false,
+                },
+                _ => break,
+            }
+        }
+        if let Value::Ref(_) = v {
+            return false;
+        }
+        match node.strip_annotations() {
+            ConstraintNode::T => true,
+            ConstraintNode::F => false,
+            ConstraintNode::Leaf(atom) => v.as_atom().is_some_and(|v_atom| *atom == v_atom),
+            ConstraintNode::Kind(kind) => v.kind() == Some(*kind),
+            ConstraintNode::Range { lo, hi } => v
+                .as_atom()
+                .is_some_and(|v_atom| range::contains(lo, hi, &v_atom)),
+            ConstraintNode::IntSet(set) => matches!(v, Value::Int(i) if set.contains(i)),
+            ConstraintNode::Enum(nodes) => nodes
+                .iter()
+                .any(|node| self.node_contains(node, v, values, visiting)),
+            ConstraintNode::And(nodes) => nodes
+                .iter()
+                .all(|node| self.node_contains(node, v, values, visiting)),
+            ConstraintNode::Pair(left, right) => match v {
+                Value::Pair(v_left, v_right) => {
+                    self.node_contains(left, v_left, values, visiting)
+                        && self.node_contains(right, v_right, values, visiting)
+                }
+                _ => false,
+            },
+            ConstraintNode::TaggedEnum(members) => match v {
+                Value::Tagged(tag, payload) => members.iter().any(|(member_tag, member)| {
+                    member_tag == tag && self.node_contains(member, payload, values, visiting)
+                }),
+                _ => false,
+            },
+            // 缺少的字段取默认值，默认值必须被字段类型包含
+            ConstraintNode::Record(fields) => match v {
+                Value::Record(entries) => {
+                    entries
+                        .keys()
+                        .all(|name| fields.iter().any(|(field, _)| field == name))
+                        && fields.iter().all(|(name, field)| match entries.get(name) {
+                            Some(entry) => self.node_contains(&field.ty, entry, values, visiting),
+                            None => field.default.as_ref().is_some_and(|default| {
+                                Checker::new(self, self).check_subsumption(&field.ty, default)
+                            }),
+                        })
+                }
+                _ => false,
+            },
+            def @ ConstraintNode::Def(name) => {
+                let arena = self.arena();
+                let Some(index) = arena.target(def).or_else(|| arena.index_of(name)) else {
+                    return false;
+                };
+                let key = (index, v as *const Value);
+                if !visiting.insert(key) {
+                    return true;
+                }
+                let result = self.node_contains(arena.node(index), v, values, visiting);
+                visiting.remove(&key);
+                result
+            }
+            ConstraintNode::Custom(custom) => custom.contains(v),
+            ConstraintNode::Predicate(id) => self
+                .predicates
+                .get(*id)
+                .is_some_and(|predicate| predicate(v)),
+            ConstraintNode::Annotated(..) => unreachable!("annotations are stripped"),
+        }
+    }
+}
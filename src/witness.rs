@@ -0,0 +1,550 @@
+//! 有界的模型搜索：从约束的结构出发生成候选值
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::{
+    constraint::{AtomicConstraint, Bound, Constraint, ConstraintNode, Kind, RecordField},
+    range,
+    value::Value,
+};
+
+const SAMPLE_DEPTH: usize = 8; // 递归定义最多展开的层数
+const SAMPLE_LIMIT: usize = 256; // 每个节点最多产生的候选值数量
+const EMPTY_SIZES_LIMIT: usize = 64; // 无界的约束连续这么多个大小都没有值时停止枚举
+
+/// 收集约束中出现的整数端点及其相邻元素，区间之间的差异只会出现在这些点附近
+fn collect_points(constraint: &Constraint, points: &mut BTreeSet<i32>) {
+    fn add(atom: &AtomicConstraint, points: &mut BTreeSet<i32>) {
+        if let AtomicConstraint::LiteralInt(i) = atom {
+            points.insert(*i);
+            points.extend(i.checked_sub(1));
+            points.extend(i.checked_add(1));
+        }
+    }
+    fn visit(node: &ConstraintNode, points: &mut BTreeSet<i32>) {
+        match node {
+            ConstraintNode::Leaf(atom) => add(atom, points),
+            ConstraintNode::Range { lo, hi } => {
+                for bound in [lo, hi] {
+                    if let Bound::Inclusive(atom) | Bound::Exclusive(atom) = bound {
+                        add(atom, points);
+                    }
+                }
+            }
+            ConstraintNode::IntSet(set) => set
+                .iter()
+                .for_each(|i| add(&AtomicConstraint::LiteralInt(*i), points)),
+            ConstraintNode::Enum(nodes) | ConstraintNode::And(nodes) => {
+                nodes.iter().for_each(|node| visit(node, points))
+            }
+            ConstraintNode::TaggedEnum(members) => {
+                members.iter().for_each(|(_, node)| visit(node, points))
+            }
+            ConstraintNode::Pair(left, right) => {
+                visit(left, points);
+                visit(right, points);
+            }
+            ConstraintNode::Record(fields) => {
+                for (_, field) in fields {
+                    visit(&field.ty, points);
+                    field.default.iter().for_each(|node| visit(node, points));
+                }
+            }
+            ConstraintNode::Annotated(inner, _) => visit(inner, points),
+            ConstraintNode::T
+            | ConstraintNode::F
+            | ConstraintNode::Kind(_)
+            | ConstraintNode::Def(_)
+            | ConstraintNode::Custom(_)
+            | ConstraintNode::Predicate(_) => {}
+        }
+    }
+    for node in constraint.nodes() {
+        visit(node, points);
+    }
+}
+
+struct Sampler<'a> {
+    constraint: &'a Constraint,
+    points: &'a BTreeSet<i32>,
+}
+
+impl Sampler<'_> {
+    fn ints(&self) -> impl Iterator<Item = i32> + '_ {
+        std::iter::once(0).chain(self.points.iter().copied())
+    }
+
+    fn samples(&self, node: &ConstraintNode, depth: usize) -> Vec<Value> {
+        let mut values = match node.strip_annotations() {
+            ConstraintNode::T => {
+                let mut values = vec![Value::Nil, Value::Float(0.0), Value::String(String::new())];
+                values.extend(self.ints().map(Value::Int));
+                values.push(Value::Pair(Value::Nil.into(), Value::Nil.into()));
+                values
+            }
+            ConstraintNode::F => Vec::new(),
+            ConstraintNode::Leaf(AtomicConstraint::Nil) => vec![Value::Nil],
+            ConstraintNode::Leaf(AtomicConstraint::LiteralInt(i)) => vec![Value::Int(*i)],
+            ConstraintNode::Leaf(AtomicConstraint::LiteralString(s)) => {
+                vec![Value::String(s.clone())]
+            }
+            ConstraintNode::Kind(Kind::Int) => self.ints().map(Value::Int).collect(),
+            ConstraintNode::Kind(Kind::Float) => vec![Value::Float(0.0)],
+            ConstraintNode::Kind(Kind::String) => vec![Value::String(String::new())],
+            ConstraintNode::Leaf(AtomicConstraint::LiteralChar(c)) => vec![Value::Char(*c)],
+            ConstraintNode::Leaf(AtomicConstraint::LiteralFloat(x)) => vec![Value::Float(*x)],
+            ConstraintNode::Kind(Kind::Char) => vec![Value::Char('a')],
+            ConstraintNode::Range { lo, hi } if range::kind(lo, hi) == Some(Kind::Char) => {
+                // 端点与它们的相邻字符
+                let mut candidates = BTreeSet::new();
+                for bound in [lo, hi] {
+                    if let Bound::Inclusive(AtomicConstraint::LiteralChar(c))
+                    | Bound::Exclusive(AtomicConstraint::LiteralChar(c)) = bound
+                    {
+                        candidates.insert(*c);
+                        candidates.extend((*c as u32).checked_sub(1).and_then(char::from_u32));
+                        candidates.extend(char::from_u32(*c as u32 + 1));
+                    }
+                }
+                candidates
+                    .into_iter()
+                    .filter(|c| range::contains(lo, hi, &AtomicConstraint::LiteralChar(*c)))
+                    .map(Value::Char)
+                    .collect()
+            }
+            ConstraintNode::Range { lo, hi } if range::kind(lo, hi) == Some(Kind::String) => {
+                // 端点本身以及紧随其后的字符串
+                let mut candidates = BTreeSet::from([String::new()]);
+                for bound in [lo, hi] {
+                    if let Bound::Inclusive(AtomicConstraint::LiteralString(s))
+                    | Bound::Exclusive(AtomicConstraint::LiteralString(s)) = bound
+                    {
+                        candidates.insert(s.clone());
+                        candidates.insert(format!("{}\0", s));
+                    }
+                }
+                candidates
+                    .into_iter()
+                    .filter(|s| {
+                        range::contains(lo, hi, &AtomicConstraint::LiteralString(s.clone()))
+                    })
+                    .map(Value::String)
+                    .collect()
+            }
+            ConstraintNode::Range { lo, hi } if range::kind(lo, hi) == Some(Kind::Float) => {
+                // 端点、端点两侧以及两端点的中点
+                let endpoints: Vec<f64> = [lo, hi]
+                    .into_iter()
+                    .filter_map(|bound| match bound {
+                        Bound::Inclusive(AtomicConstraint::LiteralFloat(x))
+                        | Bound::Exclusive(AtomicConstraint::LiteralFloat(x)) => Some(*x),
+                        _ => None,
+                    })
+                    .collect();
+                let mut candidates = vec![0.0];
+                for x in &endpoints {
+                    candidates.extend([*x, x - 1.0, x + 1.0]);
+                }
+                if let [a, b] = endpoints.as_slice() {
+                    candidates.push(a / 2.0 + b / 2.0);
+                }
+                let mut values: Vec<Value> = Vec::new();
+                for x in candidates {
+                    let value = Value::Float(x);
+                    if range::contains(lo, hi, &AtomicConstraint::LiteralFloat(x))
+                        && !values.contains(&value)
+                    {
+                        values.push(value);
+                    }
+                }
+                values
+            }
+            ConstraintNode::Range { lo, hi } => {
+                let mut candidates: BTreeSet<i32> = self.ints().collect();
+                for bound in [lo, hi] {
+                    if let Bound::Inclusive(AtomicConstraint::LiteralInt(i))
+                    | Bound::Exclusive(AtomicConstraint::LiteralInt(i)) = bound
+                    {
+                        candidates.insert(*i);
+                        candidates.extend(i.checked_sub(1));
+                        candidates.extend(i.checked_add(1));
+                    }
+                }
+                candidates
+                    .into_iter()
+                    .filter(|i| range::contains(lo, hi, &AtomicConstraint::LiteralInt(*i)))
+                    .map(Value::Int)
+                    .collect()
+            }
+            ConstraintNode::IntSet(set) => set.iter().copied().map(Value::Int).collect(),
+            ConstraintNode::Enum(nodes) => nodes
+                .iter()
+                .flat_map(|node| self.samples(node, depth))
+                .collect(),
+            ConstraintNode::Pair(left, right) => {
+                let lefts = self.samples(left, depth);
+                let rights = self.samples(right, depth);
+                let mut values = Vec::new();
+                'outer: for l in &lefts {
+                    for r in &rights {
+                        if values.len() >= SAMPLE_LIMIT {
+                            break 'outer;
+                        }
+                        values.push(Value::Pair(l.clone().into(), r.clone().into()));
+                    }
+                }
+                values
+            }
+            // 从第一个成员的样本中挑出满足其余成员的值
+            ConstraintNode::And(nodes) => match nodes.split_first() {
+                Some((first, rest)) => self
+                    .samples(first, depth)
+                    .into_iter()
+                    .filter(|v| {
+                        rest.iter().all(|node| {
+                            self.constraint
+                                .node_contains(node, v, &[], &mut HashSet::new())
+                        })
+                    })
+                    .collect(),
+                None => self.samples(&ConstraintNode::T, depth),
+            },
+            // 各字段样本的组合，有默认值的字段还可以缺少
+            ConstraintNode::Record(fields) => {
+                let mut values = vec![BTreeMap::new()];
+                for (name, field) in fields {
+                    let mut options: Vec<Option<Value>> = self
+                        .samples(&field.ty, depth)
+                        .into_iter()
+                        .map(Some)
+                        .collect();
+                    if field.default.is_some() {
+                        options.push(None);
+                    }
+                    let mut extended = Vec::new();
+                    'outer: for record in &values {
+                        for option in &options {
+                            if extended.len() >= SAMPLE_LIMIT {
+                                break 'outer;
+                            }
+                            let mut record = record.clone();
+                            if let Some(v) = option {
+                                record.insert(name.clone(), v.clone());
+                            }
+                            extended.push(record);
+                        }
+                    }
+                    values = extended;
+                }
+                values.into_iter().map(Value::Record).collect()
+            }
+            ConstraintNode::TaggedEnum(members) => members
+                .iter()
+                .flat_map(|(tag, payload)| {
+                    self.samples(payload, depth)
+                        .into_iter()
+                        .map(|v| Value::Tagged(tag.clone(), v.into()))
+                })
+                .collect(),
+            ConstraintNode::Def(name) => match (depth, self.constraint.get_node(name)) {
+                (0, _) | (_, None) => Vec::new(),
+                (_, Some(body)) => self.samples(body, depth - 1),
+            },
+            // 无法从自定义约束与谓词中构造值
+            ConstraintNode::Custom(_) | ConstraintNode::Predicate(_) => Vec::new(),
+            ConstraintNode::Annotated(..) => unreachable!("annotations are stripped"),
+        };
+        values.truncate(SAMPLE_LIMIT);
+        values
+    }
+}
+
+/// 按值的大小枚举：原子的大小为 1，复合值的大小为 1 加上各分量的大小
+struct Enumerator<'a> {
+    constraint: &'a Constraint,
+    points: BTreeSet<i32>,
+    memo: HashMap<(String, usize), Vec<Value>>,
+    in_progress: HashMap<(String, usize), usize>, // 正在计算的 (定义, 大小) 及其嵌套层数
+    cut: usize, // 当前结果依赖的最外层正在计算的键所在的层数，usize::MAX 表示不依赖
+}
+
+impl Enumerator<'_> {
+    fn sampler(&self) -> Sampler<'_> {
+        Sampler {
+            constraint: self.constraint,
+            points: &self.points,
+        }
+    }
+
+    /// node 中大小恰好为 size 的值；原子类别与区间只取 `Sampler` 的代表值
+    fn of_size(&mut self, node: &ConstraintNode, size: usize) -> Vec<Value> {
+        if size == 0 {
+            return Vec::new();
+        }
+        match node.strip_annotations() {
+            ConstraintNode::T if size == 1 => self
+                .sampler()
+                .samples(&ConstraintNode::T, 0)
+                .into_iter()
+                .filter(|v| !matches!(v, Value::Pair(..)))
+                .collect(),
+            ConstraintNode::T => self.pairs(&ConstraintNode::T, &ConstraintNode::T, size),
+            ConstraintNode::Leaf(_)
+            | ConstraintNode::Kind(_)
+            | ConstraintNode::Range { .. }
+            | ConstraintNode::IntSet(_) => match size {
+                1 => self.sampler().samples(node, 0),
+                _ => Vec::new(),
+            },
+            ConstraintNode::F | ConstraintNode::Custom(_) | ConstraintNode::Predicate(_) => {
+                Vec::new()
+            }
+            ConstraintNode::Enum(nodes) => {
+                let mut values = Vec::new();
+                for node in nodes {
+                    for v in self.of_size(node, size) {
+                        if !values.contains(&v) {
+                            values.push(v);
+                        }
+                    }
+                }
+                values
+            }
+            ConstraintNode::And(nodes) => match nodes.split_first() {
+                Some((first, rest)) => {
+                    let constraint = self.constraint;
+                    self.of_size(first, size)
+                        .into_iter()
+                        .filter(|v| {
+                            rest.iter().all(|node| {
+                                constraint.node_contains(node, v, &[], &mut HashSet::new())
+                            })
+                        })
+                        .collect()
+                }
+                None => self.of_size(&ConstraintNode::T, size),
+            },
+            ConstraintNode::Pair(left, right) => self.pairs(left, right, size),
+            ConstraintNode::TaggedEnum(members) => {
+                let mut values = Vec::new();
+                for (tag, payload) in members {
+                    for v in self.of_size(payload, size - 1) {
+                        values.push(Value::Tagged(tag.clone(), v.into()));
+                    }
+                }
+                values
+            }
+            ConstraintNode::Record(fields) => {
+                let fields: Vec<_> = fields.iter().collect();
+                self.records(&fields, size - 1)
+                    .into_iter()
+                    .map(Value::Record)
+                    .collect()
+            }
+            // 正在计算的 (定义, 大小) 再次出现时没有新的值，只枚举有限推导得到的值。
+            // 依赖于外层正在计算的键的结果还不完整，不写入缓存
+            ConstraintNode::Def(name) => {
+                let key = (name.clone(), size);
+                if let Some(values) = self.memo.get(&key) {
+                    return values.clone();
+                }
+                let Some(body) = self.constraint.get_node(name) else {
+                    return Vec::new();
+                };
+                if let Some(&depth) = self.in_progress.get(&key) {
+                    self.cut = self.cut.min(depth);
+                    return Vec::new();
+                }
+                let depth = self.in_progress.len();
+                self.in_progress.insert(key.clone(), depth);
+                let outer = std::mem::replace(&mut self.cut, usize::MAX);
+                let values = self.of_size(body, size);
+                self.in_progress.remove(&key);
+                if self.cut >= depth {
+                    self.cut = outer;
+                    self.memo.insert(key, values.clone());
+                } else {
+                    self.cut = self.cut.min(outer);
+                }
+                values
+            }
+            ConstraintNode::Annotated(..) => unreachable!("annotations are stripped"),
+        }
+    }
+
+    fn pairs(&mut self, left: &ConstraintNode, right: &ConstraintNode, size: usize) -> Vec<Value> {
+        let mut values = Vec::new();
+        for left_size in 1..size.saturating_sub(1) {
+            let lefts = self.of_size(left, left_size);
+            if lefts.is_empty() {
+                continue;
+            }
+            let rights = self.of_size(right, size - 1 - left_size);
+            for l in &lefts {
+                for r in &rights {
+                    values.push(Value::Pair(l.clone().into(), r.clone().into()));
+                }
+            }
+        }
+        values
+    }
+
+    /// 字段大小之和恰好为 size 的记录，有默认值的字段可以缺少
+    fn records(
+        &mut self,
+        fields: &[&(String, RecordField)],
+        size: usize,
+    ) -> Vec<BTreeMap<String, Value>> {
+        let Some(((name, field), rest)) = fields.split_first() else {
+            return match size {
+                0 => vec![BTreeMap::new()],
+                _ => Vec::new(),
+            };
+        };
+        let mut values = Vec::new();
+        if field.default.is_some() {
+            values.extend(self.records(rest, size));
+        }
+        for field_size in 1..=size {
+            let options = self.of_size(&field.ty, field_size);
+            if options.is_empty() {
+                continue;
+            }
+            for record in self.records(rest, size - field_size) {
+                for v in &options {
+                    let mut record = record.clone();
+                    record.insert(name.clone(), v.clone());
+                    values.push(record);
+                }
+            }
+        }
+        values
+    }
+}
+
+/// node 中值的大小上界，None 表示无界
+fn max_size(
+    constraint: &Constraint,
+    node: &ConstraintNode,
+    visiting: &mut HashSet<String>,
+) -> Option<usize> {
+    let mut sum = |nodes: Vec<&ConstraintNode>| {
+        nodes.into_iter().try_fold(1, |total, node| {
+            Some(total + max_size(constraint, node, visiting)?)
+        })
+    };
+    match node.strip_annotations() {
+        ConstraintNode::T => None,
+        ConstraintNode::F | ConstraintNode::Custom(_) | ConstraintNode::Predicate(_) => Some(0),
+        ConstraintNode::Leaf(_)
+        | ConstraintNode::Kind(_)
+        | ConstraintNode::Range { .. }
+        | ConstraintNode::IntSet(_) => Some(1),
+        ConstraintNode::Pair(left, right) => sum(vec![left, right]),
+        ConstraintNode::Record(fields) => sum(fields.iter().map(|(_, field)| &field.ty).collect()),
+        ConstraintNode::Enum(nodes) => nodes.iter().try_fold(0, |max, node| {
+            Some(max.max(max_size(constraint, node, visiting)?))
+        }),
+        ConstraintNode::TaggedEnum(members) => members.iter().try_fold(0, |max, (_, node)| {
+            Some(max.max(1 + max_size(constraint, node, visiting)?))
+        }),
+        // 交集不大于任意一个有界的成员
+        ConstraintNode::And(nodes) => nodes
+            .iter()
+            .filter_map(|node| max_size(constraint, node, visiting))
+            .min(),
+        ConstraintNode::Def(name) => {
+            let body = constraint.get_node(name)?;
+            if !visiting.insert(name.clone()) {
+                return None;
+            }
+            let max = max_size(constraint, body, visiting);
+            visiting.remove(name);
+            max
+        }
+        ConstraintNode::Annotated(..) => unreachable!("annotations are stripped"),
+    }
+}
+
+impl Constraint {
+    /// 按大小从小到大惰性地枚举约束中的值，递归的约束可以无穷地枚举下去，用 `take` 截取
+    ///
+    /// 值的大小是它包含的原子与复合结构的个数，例如列表按长度递增。整数、字符串等类别
+    /// 与区间只取有限个代表值（端点及其相邻元素），T 只枚举原子与 Pair。约束有界时枚举完
+    /// 最大的值后结束；无界的约束连续 64 个大小都没有值时（例如交集过滤掉了更大的值）也结束
+    pub fn iter_values(&self) -> impl Iterator<Item = Value> + '_ {
+        let mut points = BTreeSet::new();
+        collect_points(self, &mut points);
+        let entry = self.get_node(self.entry()).cloned();
+        let max = match &entry {
+            Some(node) => max_size(self, node, &mut HashSet::new()),
+            None => Some(0),
+        };
+        let mut enumerator = Enumerator {
+            constraint: self,
+            points,
+            memo: HashMap::new(),
+            in_progress: HashMap::new(),
+            cut: usize::MAX,
+        };
+        let mut size = 0;
+        let mut empty_sizes = 0;
+        let mut pending = Vec::new().into_iter();
+        std::iter::from_fn(move || {
+            loop {
+                if let Some(v) = pending.next() {
+                    return Some(v);
+                }
+                let entry = entry.as_ref()?;
+                size += 1;
+                match max {
+                    Some(max) if size > max => return None,
+                    None if empty_sizes >= EMPTY_SIZES_LIMIT => return None,
+                    _ => {}
+                }
+                let values = enumerator.of_size(entry, size);
+                empty_sizes = if values.is_empty() {
+                    empty_sizes + 1
+                } else {
+                    0
+                };
+                pending = values.into_iter();
+            }
+        })
+    }
+
+    fn samples_with(&self, points: &BTreeSet<i32>) -> Vec<Value> {
+        let sampler = Sampler {
+            constraint: self,
+            points,
+        };
+        match self.get_node(self.entry()) {
+            Some(node) => sampler.samples(node, SAMPLE_DEPTH),
+            None => Vec::new(),
+        }
+    }
+
+    /// 在有界的搜索范围内寻找一个满足约束的值
+    pub fn witness(&self) -> Option<Value> {
+        let mut points = BTreeSet::new();
+        collect_points(self, &mut points);
+        self.samples_with(&points)
+            .into_iter()
+            .find(|value| self.contains(value))
+    }
+
+    /// 寻找恰好只满足两个约束之一的值，优先返回属于 self 而不属于 other 的值
+    ///
+    /// 递归定义最多展开有限层，因此返回 None 只说明在搜索范围内没有找到区分值
+    pub fn distinguishing_value(&self, other: &Self) -> Option<Value> {
+        let mut points = BTreeSet::new();
+        collect_points(self, &mut points);
+        collect_points(other, &mut points);
+        let only_in = |a: &Constraint, b: &Constraint| {
+            a.samples_with(&points)
+                .into_iter()
+                .find(|value| a.contains(value) && !b.contains(value))
+        };
+        only_in(self, other).or_else(|| only_in(other, self))
+    }
+}